@@ -1,4 +1,5 @@
 pub mod parse;
+pub mod visit;
 
 // use std::iter::Peekable;
 
@@ -32,7 +33,7 @@ use self::parse::spans::Span;
 //     fn parse<'a, I: Iterator<Item = Token<'a>>>(tokens: &mut Peekable<I>) -> Result<Self, String>;
 // }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
 pub enum Typ {
     Num,
     Str,
@@ -68,7 +69,7 @@ impl std::fmt::Display for Typ {
 //     }
 // }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
 pub enum Operator {
     Add,
     Sub,
@@ -232,10 +233,40 @@ pub enum Statement<'a> {
         Option<Span<'a, Block<'a>>>,
     ),
     While(Span<'a, Expression<'a>>, Span<'a, Block<'a>>),
+    /// Runs the block with the crewmate made to each number from the start up to,
+    /// but not including, the end, both evaluated once before the first turn
+    For(
+        Span<'a, String>,
+        Span<'a, Expression<'a>>,
+        Span<'a, Expression<'a>>,
+        Span<'a, Block<'a>>,
+    ),
+    /// Leaves the innermost loop
+    Break,
+    /// Skips to the next turn of the innermost loop
+    Continue,
     Return(Option<Span<'a, Expression<'a>>>),
     Expr(Span<'a, Expression<'a>>),
     Declare(Span<'a, String>, Span<'a, Typ>),
     Define(Span<'a, String>, Span<'a, Expression<'a>>),
+    /// Ends the life of a crewmate, see [`crate::flow`]
+    Kill(Span<'a, String>),
+    /// `emergency meeting` block, then the `impostor` crewmate given the reason of
+    /// a `sabotage` in it and the block that handles it
+    Try(
+        Span<'a, Block<'a>>,
+        Span<'a, String>,
+        Span<'a, Typ>,
+        Span<'a, Block<'a>>,
+    ),
+    /// Leaves for the innermost `emergency meeting` with a reason
+    Sabotage(Span<'a, Expression<'a>>),
+    /// Ends the program successfully
+    Win,
+    /// Ends the program with a failure
+    Lose,
+    /// Waits for a number of milliseconds
+    Afk(Span<'a, Expression<'a>>),
 }
 
 // impl Parse for Statement {
@@ -459,6 +490,7 @@ pub enum Ast<'a> {
 // }
 
 #[cfg(test)]
+#[allow(dead_code)]
 mod testing {
     macro_rules! span {
         // `()` indicates that the macro takes no argument.
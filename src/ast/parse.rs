@@ -43,7 +43,7 @@ pub fn valid_alpha(c: char) -> bool {
     c != 'ඞ' && c != 'ච' && c.is_alphabetic()
 }
 
-pub fn identifier<'a, E>(input: Span<'a>) -> IResult<'a, E, Identifier>
+pub fn identifier<'a, E>(input: Span<'a>) -> IResult<'a, E, Identifier<'a>>
 where
     E: ParseError<Span<'a>> + ContextError<Span<'a>, Context>,
 {
@@ -16,7 +16,14 @@ pub enum Context {
     Block,
     Define,
     Declare,
+    Kill,
+    Try,
+    Sabotage,
+    Exit,
+    Afk,
     Type,
     While,
+    For,
+    LoopControl,
     Path,
 }
@@ -29,7 +29,7 @@ use super::{
 
 use super::{Expression, Operator};
 
-pub fn parse_bool_lit<'a, E>(i: Span<'a>) -> IResult<E, bool>
+pub fn parse_bool_lit<'a, E>(i: Span<'a>) -> IResult<'a, E, bool>
 where
     E: ParseError<Span<'a>> + ContextError<Span<'a>, Context> + TagError<Span<'a>, &'static str>,
 {
@@ -41,7 +41,7 @@ where
     .parse(i)
 }
 
-pub fn parse_string_lit<'a, E>(i: Span<'a>) -> IResult<E, String>
+pub fn parse_string_lit<'a, E>(i: Span<'a>) -> IResult<'a, E, String>
 where
     E: ParseError<Span<'a>>
         + ContextError<Span<'a>, Context>
@@ -50,7 +50,7 @@ where
     parse_string.context(Context::StringLit).parse(i)
 }
 
-fn parse_parens<'a, E>(i: Span<'a>) -> IResult<'a, E, Expression>
+fn parse_parens<'a, E>(i: Span<'a>) -> IResult<'a, E, Expression<'a>>
 where
     E: ParseError<Span<'a>>
         + ContextError<Span<'a>, Context>
@@ -62,7 +62,7 @@ where
 
 fn parse_call<'a, E>(
     i: Span<'a>,
-) -> IResult<'a, E, (Span<'a, Identifier>, Vec<Span<'a, Expression>>)>
+) -> IResult<'a, E, (Span<'a, Identifier<'a>>, Vec<Span<'a, Expression<'a>>>)>
 where
     E: ParseError<Span<'a>>
         + ContextError<Span<'a>, Context>
@@ -94,8 +94,8 @@ fn parse_binary_operation<'a, E>(
     E,
     (
         Span<'a, Operator>,
-        Span<'a, Expression>,
-        Span<'a, Expression>,
+        Span<'a, Expression<'a>>,
+        Span<'a, Expression<'a>>,
     ),
 >
 where
@@ -109,7 +109,7 @@ where
         .parse(i)
 }
 
-pub fn parse_expr<'a, E>(i: Span<'a>) -> IResult<E, Expression>
+pub fn parse_expr<'a, E>(i: Span<'a>) -> IResult<'a, E, Expression<'a>>
 where
     E: ParseError<Span<'a>>
         + ContextError<Span<'a>, Context>
@@ -27,7 +27,7 @@ use super::{
     typ::parse_type,
 };
 
-pub fn parse_ast_item<'a, E>(i: Span<'a>) -> IResult<'a, E, Ast>
+pub fn parse_ast_item<'a, E>(i: Span<'a>) -> IResult<'a, E, Ast<'a>>
 where
     E: ParseError<Span<'a>>
         + ContextError<Span<'a>, Context>
@@ -85,7 +85,7 @@ where
     .parse(i)
 }
 
-pub fn parse_items<'a, E>(i: Span<'a>) -> nom::IResult<Span<'a>, Vec<Span<'a, Ast>>, E>
+pub fn parse_items<'a, E>(i: Span<'a>) -> nom::IResult<Span<'a>, Vec<Span<'a, Ast<'a>>>, E>
 where
     E: ParseError<Span<'a>>
         + ContextError<Span<'a>, Context>
@@ -97,6 +97,7 @@ where
 }
 #[cfg(test)]
 mod tests {
+    #[allow(unused_imports)]
     use crate::ast::{
         parse::{
             error::ParseError,
@@ -68,6 +68,51 @@ where
     ))
 }
 
+pub fn parse_for<'a, E>(suslevel: usize) -> impl FnMut(Span<'a>) -> IResult<'a, E, Statement>
+where
+    E: ParseError<Span<'a>>
+        + ContextError<Span<'a>, Context>
+        + FromExternalError<Span<'a>, ParseIntError>
+        + TagError<Span<'a>, &'static str>
+        + 'a,
+{
+    spanned(preceded(
+        ws(tag("for")),
+        map(
+            tuple((
+                terminated(ws(identifier), ws(tag("in"))),
+                separated_pair(ws(parse_expr), tag("to"), ws(parse_expr)),
+                parse_block(suslevel + 1),
+            )),
+            |(name, (start, end), b)| Statement::For(name.map(|a| a.0.into()), start, end, b),
+        ),
+    ))
+}
+
+pub fn parse_try<'a, E>(suslevel: usize) -> impl FnMut(Span<'a>) -> IResult<'a, E, Statement>
+where
+    E: ParseError<Span<'a>>
+        + ContextError<Span<'a>, Context>
+        + FromExternalError<Span<'a>, ParseIntError>
+        + TagError<Span<'a>, &'static str>
+        + 'a,
+{
+    spanned(preceded(
+        pair(ws(tag("emergency")), ws(tag("meeting"))),
+        map(
+            tuple((
+                parse_block(suslevel + 1),
+                preceded(
+                    pair(parse_tabs(suslevel), ws(tag("impostor"))),
+                    separated_pair(ws(identifier), char(':'), ws(parse_type)),
+                ),
+                parse_block(suslevel + 1),
+            )),
+            |(body, (name, typ), catch)| Statement::Try(body, name.map(|a| a.0.into()), typ, catch),
+        ),
+    ))
+}
+
 pub fn parse_block<'a, E>(
     suslevel: usize,
 ) -> impl FnMut(Span<'a>) -> IResult<'a, E, Vec<Span<'a, Statement>>>
@@ -108,6 +153,8 @@ where
             ws(alt((
                 parse_if(suslevel),
                 parse_while(suslevel),
+                parse_for(suslevel).context(Context::For),
+                parse_try(suslevel).context(Context::Try),
                 terminated(
                     spanned(map(preceded(ws(tag("eject")), opt(ws(parse_expr))), |x| {
                         Statement::Return(x)
@@ -134,6 +181,39 @@ where
                     char('ඞ'),
                 )
                 .context(Context::Define),
+                terminated(
+                    spanned(map(preceded(ws(tag("kill")), ws(identifier)), |name| {
+                        Statement::Kill(name.map(|a| a.0.into()))
+                    })),
+                    char('ඞ'),
+                )
+                .context(Context::Kill),
+                terminated(
+                    spanned(map(preceded(ws(tag("sabotage")), ws(parse_expr)), Statement::Sabotage)),
+                    char('ඞ'),
+                )
+                .context(Context::Sabotage),
+                terminated(
+                    spanned(alt((
+                        map(ws(tag("win")), |_| Statement::Win),
+                        map(ws(tag("lose")), |_| Statement::Lose),
+                    ))),
+                    char('ඞ'),
+                )
+                .context(Context::Exit),
+                terminated(
+                    spanned(alt((
+                        map(ws(tag("break")), |_| Statement::Break),
+                        map(ws(tag("continue")), |_| Statement::Continue),
+                    ))),
+                    char('ඞ'),
+                )
+                .context(Context::LoopControl),
+                terminated(
+                    spanned(map(preceded(ws(tag("afk")), ws(parse_expr)), Statement::Afk)),
+                    char('ඞ'),
+                )
+                .context(Context::Afk),
                 terminated(spanned(map(parse_expr, Statement::Expr)), char('ඞ')),
             ))),
         )
@@ -144,11 +224,41 @@ where
 
 #[cfg(test)]
 mod tests {
-    use crate::ast::{parse::spans::load_file_str, Expression, Operator, Statement};
+    use nom_locate::LocatedSpan;
+
+    use crate::ast::{
+        parse::spans::{load_file_str, ExtraData},
+        Expression, Operator, Statement, Typ,
+    };
 
     #[allow(unused_imports)]
     use super::*;
 
+    macro_rules! span {
+        ($inner:pat) => {
+            LocatedSpan {
+                extra: ExtraData { data: $inner, .. },
+                ..
+            }
+        };
+    }
+
+    fn is_lt_2_5(e: &Expression) -> bool {
+        matches!(e, Expression::Operation(span!(Operator::Lt), a, b)
+            if a.extra.data == Expression::NumLit(2) && b.extra.data == Expression::NumLit(5))
+    }
+
+    fn is_report_hey(s: &Statement) -> bool {
+        matches!(s, Statement::Expr(span!(Expression::Call(span!(name), args)))
+            if name == "report"
+                && matches!(args.as_slice(), [span!(Expression::StringLit(s))] if s == "hey\n"))
+    }
+
+    fn is_if_report_hey(s: &Statement) -> bool {
+        matches!(s, Statement::If(span!(cond), span!(block), None)
+            if is_lt_2_5(cond) && matches!(block.as_slice(), [span!(s)] if is_report_hey(s)))
+    }
+
     const TEST_DATA: &str = r#"
 sus? < 2 5
 චcomplete report with "hey\n"ඞ
@@ -161,57 +271,31 @@ eject 0ඞ"#;
     #[test]
     fn parse_block() {
         let data = load_file_str(&"test.sus", TEST_DATA);
-        let b = super::parse_block::<super::super::error::ParseError<Span>>(0)(data).map(
-            |(rest, s)| {
-                (
-                    *rest.fragment(),
-                    s.extra
-                        .data
-                        .into_iter()
-                        .map(|x| x.extra.data)
-                        .collect::<Vec<_>>(),
-                )
-            },
-        );
-        let expected = (
-            "",
-            vec![
-                Statement::If(
-                    Expression::Operation(
-                        Operator::Lt,
-                        Box::new(Expression::NumLit(2)),
-                        Box::new(Expression::NumLit(5)),
-                    ),
-                    vec![Statement::Expr(Expression::Call(
-                        "report".into(),
-                        vec![Expression::StringLit("hey\n".into())],
-                    ))],
-                    None,
-                ),
-                Statement::If(
-                    Expression::Operation(
-                        Operator::Lt,
-                        Box::new(Expression::NumLit(2)),
-                        Box::new(Expression::NumLit(5)),
-                    ),
-                    vec![Statement::Expr(Expression::Call(
-                        "report".into(),
-                        vec![Expression::StringLit("hey\n".into())],
-                    ))],
-                    None,
-                ),
-                Statement::Declare("red".into(), crate::ast::Typ::Num),
-                Statement::Define("red".into(), Expression::NumLit(5)),
-                Statement::Return(Some(Expression::NumLit(0))),
-            ],
-        );
+        let b = super::parse_block::<super::super::error::ParseError<Span>>(0)(data);
         println!("RESULT: {b:#?}");
-        assert!(b.is_ok());
-        let unwrapped = b.unwrap();
-        assert_eq!(
-            unwrapped, expected,
-            "Expected: {expected:#?}\n But got: {unwrapped:#?}"
-        );
+        let (rest, block) = b.unwrap();
+        assert_eq!(*rest.fragment(), "");
+        let block = block
+            .extra
+            .data
+            .into_iter()
+            .map(|x| x.extra.data)
+            .collect::<Vec<_>>();
+        assert_eq!(block.len(), 5, "But got: {block:#?}");
+        assert!(is_if_report_hey(&block[0]));
+        assert!(is_if_report_hey(&block[1]));
+        assert!(matches!(
+            &block[2],
+            Statement::Declare(span!(name), span!(Typ::Num)) if name == "red"
+        ));
+        assert!(matches!(
+            &block[3],
+            Statement::Define(span!(name), span!(Expression::NumLit(5))) if name == "red"
+        ));
+        assert!(matches!(
+            &block[4],
+            Statement::Return(Some(span!(Expression::NumLit(0))))
+        ));
     }
 
     #[test]
@@ -225,25 +309,9 @@ eject 0ඞ"#;
             TEST_IF,
         ));
         dbg!(&res);
-        assert!(res.is_ok());
-        assert_eq!(
-            res.map(|(a, b)| (*a.fragment(), b.extra.data)).unwrap(),
-            (
-                "",
-                Statement::If(
-                    Expression::Operation(
-                        Operator::Lt,
-                        Box::new(Expression::NumLit(2)),
-                        Box::new(Expression::NumLit(5))
-                    ),
-                    vec![Statement::Expr(Expression::Call(
-                        "report".into(),
-                        vec![Expression::StringLit("hey\n".into())]
-                    ))],
-                    None
-                )
-            )
-        )
+        let (rest, statement) = res.unwrap();
+        assert_eq!(*rest.fragment(), "");
+        assert!(is_if_report_hey(&statement.extra.data));
     }
 
     #[test]
@@ -253,17 +321,9 @@ eject 0ඞ"#;
             load_file_str(&"test_statement.sus", TEST_STATEMENT),
         );
         dbg!(&res);
-        assert!(res.is_ok());
-        assert_eq!(
-            res.map(|(a, b)| (*a.fragment(), b.extra.data)).unwrap(),
-            (
-                "",
-                Statement::Expr(Expression::Call(
-                    "report".into(),
-                    vec![Expression::StringLit("hey\n".into())]
-                ))
-            )
-        )
+        let (rest, statement) = res.unwrap();
+        assert_eq!(*rest.fragment(), "");
+        assert!(is_report_hey(&statement.extra.data));
     }
 
     #[test]
@@ -274,41 +334,21 @@ eject 0ඞ"#;
             TEST_STATEMENT1,
         ));
         dbg!(&res);
-        assert!(res.is_ok());
-        assert_eq!(
-            res.map(|(a, b)| (
-                *a.fragment(),
-                b.extra.data.into_iter().map(|s| s.extra.data).collect()
-            ))
-            .unwrap(),
-            (
-                "",
-                vec![Statement::Return(Some(Expression::StringLit(
-                    "hey\n".into()
-                )))]
-            )
-        );
+        let (rest, block) = res.unwrap();
+        assert_eq!(*rest.fragment(), "");
+        assert!(matches!(
+            block.extra.data.as_slice(),
+            [span!(Statement::Return(Some(span!(Expression::StringLit(s)))))] if s == "hey\n"
+        ));
 
         const TEST_STATEMENT2: &str = r#"ejectඞ"#;
         let res = super::parse_statement::<super::super::error::ParseError<Span>>(0)(
             load_file_str(&"test_statement.sus", TEST_STATEMENT2),
         );
-        // dbg!(terminated(
-        //     spanned::<super::super::error::ParseError<Span>, _, _>(map(
-        //         preceded(ws(tag("eject")), opt(ws(super::parse_expr))),
-        //         |x| { Statement::Return(x.map(|s| s.extra.data)) }
-        //     )),
-        //     success(()) // char('ඞ'),
-        // )(load_file_str(
-        //     &"test_statement.sus",
-        //     TEST_STATEMENT2
-        // )));
         dbg!(&res);
-        assert!(res.is_ok());
-        assert_eq!(
-            res.map(|(a, b)| (*a.fragment(), b.extra.data)).unwrap(),
-            ("", Statement::Return(None))
-        )
+        let (rest, statement) = res.unwrap();
+        assert_eq!(*rest.fragment(), "");
+        assert!(matches!(statement.extra.data, Statement::Return(None)));
     }
 
     #[test]
@@ -323,36 +363,70 @@ eject 0ඞ"#;
             TEST_WHILE,
         ));
         dbg!(&res);
-        assert!(res.is_ok());
-        assert_eq!(
-            res.map(|(a, b)| (*a.fragment(), b.extra.data)).unwrap(),
-            (
-                "",
-                Statement::While(
-                    Expression::Operation(
-                        Operator::Lt,
-                        Box::new(Expression::NumLit(2)),
-                        Box::new(Expression::NumLit(5))
-                    ),
-                    vec![
-                        Statement::While(
-                            Expression::Operation(
-                                Operator::Lt,
-                                Box::new(Expression::NumLit(2)),
-                                Box::new(Expression::NumLit(5))
-                            ),
-                            vec![Statement::Expr(Expression::Call(
-                                "report".into(),
-                                vec![Expression::StringLit("hey\n".into())]
-                            ))]
-                        ),
-                        Statement::Expr(Expression::Call(
-                            "report".into(),
-                            vec![Expression::StringLit("hey\n".into())]
-                        ))
-                    ]
-                )
-            )
-        )
+        let (rest, statement) = res.unwrap();
+        assert_eq!(*rest.fragment(), "");
+        let Statement::While(span!(cond), span!(block)) = &statement.extra.data else {
+            panic!("Expected while, got {statement:#?}")
+        };
+        assert!(is_lt_2_5(cond));
+        assert!(matches!(
+            block.as_slice(),
+            [span!(Statement::While(span!(inner_cond), span!(inner))), span!(last)]
+                if is_lt_2_5(inner_cond)
+                    && matches!(inner.as_slice(), [span!(s)] if is_report_hey(s))
+                    && is_report_hey(last)
+        ));
+    }
+
+    #[test]
+    fn parse_for() {
+        const TEST_FOR: &str = r#"for red in + 2 3 to 9
+    චsus? < red 2
+    චචcontinueඞ
+    චbreakඞ
+    "#;
+        let res = super::parse_for::<super::super::error::ParseError<Span>>(0)(load_file_str(
+            &"test_for.sus",
+            TEST_FOR,
+        ));
+        let (rest, statement) = res.unwrap();
+        assert_eq!(*rest.fragment(), "");
+        let Statement::For(span!(name), span!(start), span!(Expression::NumLit(9)), span!(block)) =
+            &statement.extra.data
+        else {
+            panic!("Expected for, got {statement:#?}")
+        };
+        assert_eq!(name, "red");
+        assert!(matches!(start, Expression::Operation(span!(Operator::Add), ..)));
+        assert!(matches!(
+            block.as_slice(),
+            [span!(Statement::If(_, span!(inner), None)), span!(Statement::Break)]
+                if matches!(inner.as_slice(), [span!(Statement::Continue)])
+        ));
+    }
+
+    #[test]
+    fn parse_try() {
+        const TEST_TRY: &str = r#"emergency meeting
+    චsabotage "hey\n"ඞ
+    impostor red: string
+    චcomplete report with "hey\n"ඞ
+    "#;
+        let res = super::parse_try::<super::super::error::ParseError<Span>>(0)(load_file_str(
+            &"test_try.sus",
+            TEST_TRY,
+        ));
+        let (rest, statement) = res.unwrap();
+        assert_eq!(*rest.fragment(), "");
+        let Statement::Try(span!(body), span!(name), span!(Typ::Str), span!(catch)) = &statement.extra.data
+        else {
+            panic!("Expected emergency meeting, got {statement:#?}")
+        };
+        assert_eq!(name, "red");
+        assert!(matches!(
+            body.as_slice(),
+            [span!(Statement::Sabotage(span!(Expression::StringLit(s))))] if s == "hey\n"
+        ));
+        assert!(matches!(catch.as_slice(), [span!(s)] if is_report_hey(s)));
     }
 }
@@ -0,0 +1,430 @@
+//! Traversals of the AST.
+//!
+//! [`Visit`] and [`VisitMut`] walk a borrowed tree, [`Fold`] rebuilds an owned
+//! one. Every method defaults to the matching `walk_*` or `fold_*` function, so
+//! an implementation only overrides the nodes it cares about and calls back into
+//! those functions to keep going deeper. The functions match every variant, so a
+//! new node only has to be handled here.
+
+use super::{
+    parse::spans::{MapExt, Span},
+    Ast, Block, Expression, Operator, Statement, Typ,
+};
+
+pub trait Visit<'a> {
+    fn visit_item(&mut self, item: &Span<'a, Ast<'a>>) {
+        walk_item(self, item)
+    }
+
+    fn visit_block(&mut self, block: &Span<'a, Block<'a>>) {
+        walk_block(self, block)
+    }
+
+    fn visit_statement(&mut self, statement: &Span<'a, Statement<'a>>) {
+        walk_statement(self, statement)
+    }
+
+    fn visit_expression(&mut self, expression: &Span<'a, Expression<'a>>) {
+        walk_expression(self, expression)
+    }
+
+    /// Name of a task, an argument or a crewmate, where it is declared or used by
+    /// `make` and `complete`
+    fn visit_name(&mut self, _name: &Span<'a, String>) {}
+
+    fn visit_typ(&mut self, _typ: &Span<'a, Typ>) {}
+
+    fn visit_operator(&mut self, _operator: &Span<'a, Operator>) {}
+}
+
+pub fn walk_item<'a, V: Visit<'a> + ?Sized>(v: &mut V, item: &Span<'a, Ast<'a>>) {
+    match &item.extra.data {
+        Ast::Func(name, ret, args, body) => {
+            v.visit_name(name);
+            v.visit_typ(ret);
+            for arg in args {
+                v.visit_name(&arg.extra.data.0);
+                v.visit_typ(&arg.extra.data.1);
+            }
+            v.visit_block(body);
+        }
+        Ast::Mod(_) | Ast::Import(_) => (),
+    }
+}
+
+pub fn walk_block<'a, V: Visit<'a> + ?Sized>(v: &mut V, block: &Span<'a, Block<'a>>) {
+    for statement in &block.extra.data {
+        v.visit_statement(statement);
+    }
+}
+
+pub fn walk_statement<'a, V: Visit<'a> + ?Sized>(v: &mut V, statement: &Span<'a, Statement<'a>>) {
+    match &statement.extra.data {
+        Statement::If(cond, body, else_body) => {
+            v.visit_expression(cond);
+            v.visit_block(body);
+            if let Some(else_body) = else_body {
+                v.visit_block(else_body);
+            }
+        }
+        Statement::While(cond, body) => {
+            v.visit_expression(cond);
+            v.visit_block(body);
+        }
+        Statement::For(name, start, end, body) => {
+            v.visit_name(name);
+            v.visit_expression(start);
+            v.visit_expression(end);
+            v.visit_block(body);
+        }
+        Statement::Return(value) => {
+            if let Some(value) = value {
+                v.visit_expression(value);
+            }
+        }
+        Statement::Expr(e) => v.visit_expression(e),
+        Statement::Declare(name, typ) => {
+            v.visit_name(name);
+            v.visit_typ(typ);
+        }
+        Statement::Define(name, e) => {
+            v.visit_name(name);
+            v.visit_expression(e);
+        }
+        Statement::Kill(name) => v.visit_name(name),
+        Statement::Try(body, name, typ, catch) => {
+            v.visit_block(body);
+            v.visit_name(name);
+            v.visit_typ(typ);
+            v.visit_block(catch);
+        }
+        Statement::Sabotage(reason) => v.visit_expression(reason),
+        Statement::Win | Statement::Lose | Statement::Break | Statement::Continue => (),
+        Statement::Afk(ms) => v.visit_expression(ms),
+    }
+}
+
+pub fn walk_expression<'a, V: Visit<'a> + ?Sized>(
+    v: &mut V,
+    expression: &Span<'a, Expression<'a>>,
+) {
+    match &expression.extra.data {
+        Expression::Call(name, args) => {
+            v.visit_name(name);
+            for arg in args {
+                v.visit_expression(arg);
+            }
+        }
+        Expression::Operation(op, a, b) => {
+            v.visit_operator(op);
+            v.visit_expression(a);
+            v.visit_expression(b);
+        }
+        Expression::StringLit(_)
+        | Expression::NumLit(_)
+        | Expression::BoolLit(_)
+        | Expression::Variable(_) => (),
+    }
+}
+
+pub trait VisitMut<'a> {
+    fn visit_item_mut(&mut self, item: &mut Span<'a, Ast<'a>>) {
+        walk_item_mut(self, item)
+    }
+
+    fn visit_block_mut(&mut self, block: &mut Span<'a, Block<'a>>) {
+        walk_block_mut(self, block)
+    }
+
+    fn visit_statement_mut(&mut self, statement: &mut Span<'a, Statement<'a>>) {
+        walk_statement_mut(self, statement)
+    }
+
+    fn visit_expression_mut(&mut self, expression: &mut Span<'a, Expression<'a>>) {
+        walk_expression_mut(self, expression)
+    }
+
+    /// See [`Visit::visit_name`]
+    fn visit_name_mut(&mut self, _name: &mut Span<'a, String>) {}
+
+    fn visit_typ_mut(&mut self, _typ: &mut Span<'a, Typ>) {}
+
+    fn visit_operator_mut(&mut self, _operator: &mut Span<'a, Operator>) {}
+}
+
+pub fn walk_item_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, item: &mut Span<'a, Ast<'a>>) {
+    match &mut item.extra.data {
+        Ast::Func(name, ret, args, body) => {
+            v.visit_name_mut(name);
+            v.visit_typ_mut(ret);
+            for arg in args {
+                v.visit_name_mut(&mut arg.extra.data.0);
+                v.visit_typ_mut(&mut arg.extra.data.1);
+            }
+            v.visit_block_mut(body);
+        }
+        Ast::Mod(_) | Ast::Import(_) => (),
+    }
+}
+
+pub fn walk_block_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, block: &mut Span<'a, Block<'a>>) {
+    for statement in &mut block.extra.data {
+        v.visit_statement_mut(statement);
+    }
+}
+
+pub fn walk_statement_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    statement: &mut Span<'a, Statement<'a>>,
+) {
+    match &mut statement.extra.data {
+        Statement::If(cond, body, else_body) => {
+            v.visit_expression_mut(cond);
+            v.visit_block_mut(body);
+            if let Some(else_body) = else_body {
+                v.visit_block_mut(else_body);
+            }
+        }
+        Statement::While(cond, body) => {
+            v.visit_expression_mut(cond);
+            v.visit_block_mut(body);
+        }
+        Statement::For(name, start, end, body) => {
+            v.visit_name_mut(name);
+            v.visit_expression_mut(start);
+            v.visit_expression_mut(end);
+            v.visit_block_mut(body);
+        }
+        Statement::Return(value) => {
+            if let Some(value) = value {
+                v.visit_expression_mut(value);
+            }
+        }
+        Statement::Expr(e) => v.visit_expression_mut(e),
+        Statement::Declare(name, typ) => {
+            v.visit_name_mut(name);
+            v.visit_typ_mut(typ);
+        }
+        Statement::Define(name, e) => {
+            v.visit_name_mut(name);
+            v.visit_expression_mut(e);
+        }
+        Statement::Kill(name) => v.visit_name_mut(name),
+        Statement::Try(body, name, typ, catch) => {
+            v.visit_block_mut(body);
+            v.visit_name_mut(name);
+            v.visit_typ_mut(typ);
+            v.visit_block_mut(catch);
+        }
+        Statement::Sabotage(reason) => v.visit_expression_mut(reason),
+        Statement::Win | Statement::Lose | Statement::Break | Statement::Continue => (),
+        Statement::Afk(ms) => v.visit_expression_mut(ms),
+    }
+}
+
+pub fn walk_expression_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    expression: &mut Span<'a, Expression<'a>>,
+) {
+    match &mut expression.extra.data {
+        Expression::Call(name, args) => {
+            v.visit_name_mut(name);
+            for arg in args {
+                v.visit_expression_mut(arg);
+            }
+        }
+        Expression::Operation(op, a, b) => {
+            v.visit_operator_mut(op);
+            v.visit_expression_mut(a);
+            v.visit_expression_mut(b);
+        }
+        Expression::StringLit(_)
+        | Expression::NumLit(_)
+        | Expression::BoolLit(_)
+        | Expression::Variable(_) => (),
+    }
+}
+
+pub trait Fold<'a> {
+    fn fold_item(&mut self, item: Span<'a, Ast<'a>>) -> Span<'a, Ast<'a>> {
+        fold_item(self, item)
+    }
+
+    fn fold_block(&mut self, block: Span<'a, Block<'a>>) -> Span<'a, Block<'a>> {
+        fold_block(self, block)
+    }
+
+    fn fold_statement(&mut self, statement: Span<'a, Statement<'a>>) -> Span<'a, Statement<'a>> {
+        fold_statement(self, statement)
+    }
+
+    fn fold_expression(&mut self, expression: Span<'a, Expression<'a>>) -> Span<'a, Expression<'a>> {
+        fold_expression(self, expression)
+    }
+
+    /// See [`Visit::visit_name`]
+    fn fold_name(&mut self, name: Span<'a, String>) -> Span<'a, String> {
+        name
+    }
+
+    fn fold_typ(&mut self, typ: Span<'a, Typ>) -> Span<'a, Typ> {
+        typ
+    }
+
+    fn fold_operator(&mut self, operator: Span<'a, Operator>) -> Span<'a, Operator> {
+        operator
+    }
+}
+
+pub fn fold_item<'a, F: Fold<'a> + ?Sized>(f: &mut F, item: Span<'a, Ast<'a>>) -> Span<'a, Ast<'a>> {
+    item.map(|item| match item {
+        Ast::Func(name, ret, args, body) => Ast::Func(
+            f.fold_name(name),
+            f.fold_typ(ret),
+            args.into_iter()
+                .map(|arg| arg.map(|(name, typ)| (f.fold_name(name), f.fold_typ(typ))))
+                .collect(),
+            f.fold_block(body),
+        ),
+        x @ (Ast::Mod(_) | Ast::Import(_)) => x,
+    })
+}
+
+pub fn fold_block<'a, F: Fold<'a> + ?Sized>(
+    f: &mut F,
+    block: Span<'a, Block<'a>>,
+) -> Span<'a, Block<'a>> {
+    block.map(|block| {
+        block
+            .into_iter()
+            .map(|statement| f.fold_statement(statement))
+            .collect()
+    })
+}
+
+pub fn fold_statement<'a, F: Fold<'a> + ?Sized>(
+    f: &mut F,
+    statement: Span<'a, Statement<'a>>,
+) -> Span<'a, Statement<'a>> {
+    statement.map(|statement| match statement {
+        Statement::If(cond, body, else_body) => Statement::If(
+            f.fold_expression(cond),
+            f.fold_block(body),
+            else_body.map(|else_body| f.fold_block(else_body)),
+        ),
+        Statement::While(cond, body) => Statement::While(f.fold_expression(cond), f.fold_block(body)),
+        Statement::For(name, start, end, body) => Statement::For(
+            f.fold_name(name),
+            f.fold_expression(start),
+            f.fold_expression(end),
+            f.fold_block(body),
+        ),
+        Statement::Return(value) => Statement::Return(value.map(|value| f.fold_expression(value))),
+        Statement::Expr(e) => Statement::Expr(f.fold_expression(e)),
+        Statement::Declare(name, typ) => Statement::Declare(f.fold_name(name), f.fold_typ(typ)),
+        Statement::Define(name, e) => Statement::Define(f.fold_name(name), f.fold_expression(e)),
+        Statement::Kill(name) => Statement::Kill(f.fold_name(name)),
+        Statement::Try(body, name, typ, catch) => Statement::Try(
+            f.fold_block(body),
+            f.fold_name(name),
+            f.fold_typ(typ),
+            f.fold_block(catch),
+        ),
+        Statement::Sabotage(reason) => Statement::Sabotage(f.fold_expression(reason)),
+        x @ (Statement::Win | Statement::Lose | Statement::Break | Statement::Continue) => x,
+        Statement::Afk(ms) => Statement::Afk(f.fold_expression(ms)),
+    })
+}
+
+pub fn fold_expression<'a, F: Fold<'a> + ?Sized>(
+    f: &mut F,
+    expression: Span<'a, Expression<'a>>,
+) -> Span<'a, Expression<'a>> {
+    expression.map(|expression| match expression {
+        Expression::Call(name, args) => Expression::Call(
+            f.fold_name(name),
+            args.into_iter().map(|arg| f.fold_expression(arg)).collect(),
+        ),
+        Expression::Operation(op, a, b) => Expression::Operation(
+            f.fold_operator(op),
+            Box::new(f.fold_expression(*a)),
+            Box::new(f.fold_expression(*b)),
+        ),
+        x @ (Expression::StringLit(_)
+        | Expression::NumLit(_)
+        | Expression::BoolLit(_)
+        | Expression::Variable(_)) => x,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::{parse::spans::Span, Ast, Expression},
+        parse_str,
+    };
+
+    use super::{walk_expression, Fold, Visit, VisitMut};
+
+    const PROGRAM: &str = "task twice with crewmate n: number ➤ number\nචeject + n nඞ\ntask ඬ with ➤ number\nචcrewmate x: numberඞ\nචmake x complete twice with 2ඞ\nචsus? == x 4\nචචeject 0ඞ\nචeject 1ඞ\n";
+
+    #[derive(Default)]
+    struct Names(Vec<String>, usize);
+
+    impl<'a> Visit<'a> for Names {
+        fn visit_name(&mut self, name: &Span<'a, String>) {
+            self.0.push(name.extra.data.clone());
+        }
+
+        fn visit_expression(&mut self, expression: &Span<'a, Expression<'a>>) {
+            self.1 += 1;
+            walk_expression(self, expression)
+        }
+    }
+
+    #[test]
+    fn visits_every_name_and_expression() {
+        let ast = parse_str(PROGRAM).unwrap();
+        let mut names = Names::default();
+        for item in &ast {
+            names.visit_item(item);
+        }
+        assert_eq!(names.0, ["twice", "n", "ඬ", "x", "x", "twice"]);
+        // three for each operation, two for the call and one for each eject
+        assert_eq!(names.1, 10);
+    }
+
+    struct Upper;
+
+    impl<'a> VisitMut<'a> for Upper {
+        fn visit_name_mut(&mut self, name: &mut Span<'a, String>) {
+            name.extra.data = name.extra.data.to_uppercase();
+        }
+    }
+
+    impl<'a> Fold<'a> for Upper {
+        fn fold_name(&mut self, name: Span<'a, String>) -> Span<'a, String> {
+            use crate::ast::parse::spans::MapExt;
+            name.map(|name| name.to_uppercase())
+        }
+    }
+
+    #[test]
+    fn fold_and_visit_mut_agree() {
+        let ast = parse_str(PROGRAM).unwrap();
+        let folded: Vec<_> = ast.iter().cloned().map(|item| Upper.fold_item(item)).collect();
+        let mut visited = ast.clone();
+        for item in &mut visited {
+            Upper.visit_item_mut(item);
+        }
+        let names = |items: &[Span<Ast>]| {
+            let mut names = Names::default();
+            for item in items {
+                names.visit_item(item);
+            }
+            names.0
+        };
+        assert_eq!(names(&folded), ["TWICE", "N", "ඬ", "X", "X", "TWICE"]);
+        assert_eq!(names(&folded), names(&visited));
+    }
+}
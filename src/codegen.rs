@@ -6,7 +6,7 @@ pub use c::C;
 #[cfg(feature = "backend-js")]
 mod js;
 #[cfg(feature = "backend-js")]
-pub use js::Js;
+pub use js::{Js, JsOutput};
 
 #[cfg(feature = "backend-python")]
 mod py;
@@ -18,7 +18,13 @@ mod scm;
 #[cfg(feature = "backend-scm")]
 pub use scm::Scm;
 
-use std::io::Write;
+mod rename;
+#[cfg(any(feature = "backend-c", feature = "backend-js", feature = "backend-python"))]
+mod tailcall;
+#[cfg(any(feature = "backend-js", feature = "backend-python"))]
+mod sourcemap;
+
+use std::{borrow::Cow, io::Write};
 
 pub trait Codegen<W: Write, T: ?Sized> {
     fn gen(&mut self, s: &T, buf: &mut W) -> std::io::Result<()>;
@@ -38,6 +44,18 @@ trait Typename {
     fn typename(t: &Typ) -> &'static str;
 }
 
+trait Identifiers {
+    /// Keywords and prelude names that can't be used verbatim as identifiers
+    const RESERVED: &'static [&'static str];
+    /// Name given to `ඬ` in the generated code
+    const ENTRY_POINT: &'static str = "ඬ";
+
+    /// Rewrites a name so it only contains characters the target language accepts
+    fn escape(name: &str) -> Cow<'_, str> {
+        Cow::Borrowed(name)
+    }
+}
+
 trait Codegeneable<W, C> {
     fn gen(&self, codegen: &mut C, buf: &mut W) -> std::io::Result<()>;
 }
@@ -1,42 +1,204 @@
-use std::io::Write;
+use std::{borrow::Cow, io::Write};
 
 use nom_locate::LocatedSpan;
 
-use crate::ast::{
-    parse::spans::{ExtraData, Span},
-    Ast, Expression, Operator, Statement, Typ,
+use crate::{
+    ast::{
+        parse::spans::{ExtraData, Span},
+        visit::{walk_statement, Visit},
+        Ast, Block, Expression, Operator, Statement, Typ,
+    },
+    linker::Linked,
 };
 
-use super::{Codegen, Typename};
+use super::{rename::sanitize, tailcall, Codegen, Identifiers, Typename};
 
-pub struct C;
+#[derive(Debug, Clone, Default)]
+pub struct C {
+    /// C type ejected by the task being written
+    ret: &'static str,
+    /// Whether the task being written holds an `emergency meeting`, whose
+    /// `longjmp` only keeps volatile crewmates
+    volatile: bool,
+    /// Numbers of the meetings around the statement being written, outermost first
+    meetings: Vec<usize>,
+    /// Meetings written so far in the task, which number the next one
+    count: usize,
+    /// How many of `meetings` were open at each loop around the statement being
+    /// written, innermost last
+    loops: Vec<usize>,
+}
+
+impl C {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-const NAME_REPLACE: &[(&str, &str)] = &[("ඬ", "main")];
+    fn volatile(&self) -> &'static str {
+        if self.volatile {
+            "volatile "
+        } else {
+            ""
+        }
+    }
+}
 
 impl Typename for C {
     fn typename(t: &Typ) -> &'static str {
         match t {
             Typ::Num => "int",
-            Typ::Str => "char*",
-            Typ::Bool => "int",
+            Typ::Str => "sus_str",
+            Typ::Bool => "bool",
             Typ::Void => "void",
         }
     }
 }
 
-impl<'a, W> Codegen<W, [Span<'a, Ast<'a>>]> for C
+const RUNTIME: &str = include_str!("c/runtime.h");
+
+/// `main` of a program whose `ඬ` ejects nothing
+const VOID_MAIN: &str = "int main(void) {\n\tsus_main();\n\treturn 0;\n}";
+
+/// Quotes `s` as a C string literal, returning it along with its length in bytes
+fn c_string(s: &str) -> (String, usize) {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            // Octal escapes take at most 3 digits, so they can't swallow the next char
+            c if c.is_ascii_control() => quoted.push_str(&format!("\\{:03o}", c as u32)),
+            // Keeps `??x` from being read as a trigraph
+            '?' => quoted.push_str("\\?"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    (quoted, s.len())
+}
+
+/// Makes the C compiler report errors and debug info with the suslang location of `span`
+fn line_directive<W: Write, T>(span: &Span<T>, buf: &mut W) -> std::io::Result<()> {
+    let (filename, _) = c_string(&span.extra.filename.display().to_string());
+    writeln!(buf, "#line {} {filename}", span.location_line())
+}
+
+/// Looks for an `emergency meeting`, there's no need to go deeper once one is found
+#[derive(Default)]
+struct HoldsMeeting(bool);
+
+impl<'a> Visit<'a> for HoldsMeeting {
+    fn visit_statement(&mut self, statement: &Span<'a, Statement<'a>>) {
+        match statement.extra.data {
+            Statement::Try(..) => self.0 = true,
+            _ => walk_statement(self, statement),
+        }
+    }
+}
+
+/// Whether `block` holds an `emergency meeting`
+fn holds_meeting(block: &Span<Block>) -> bool {
+    let mut finder = HoldsMeeting::default();
+    finder.visit_block(block);
+    finder.0
+}
+
+/// Writes the C declarator of a task, returning its body. Other items write nothing.
+/// `qualifier` goes before the type of every argument
+fn signature<'s, 'a, W: Write>(
+    item: &'s Ast<'a>,
+    qualifier: &str,
+    buf: &mut W,
+) -> std::io::Result<Option<&'s Span<'a, Block<'a>>>> {
+    let Ast::Func(name, typ, args, block) = item else {
+        return Ok(None);
+    };
+    // C wants an `int main`, a `ඬ` that ejects nothing runs from one, see `VOID_MAIN`
+    let name = match (name.extra.data.as_str(), typ.extra.data) {
+        (C::ENTRY_POINT, Typ::Void) => "sus_main",
+        (name, _) => name,
+    };
+    write!(buf, "{} {name}(", C::typename(&typ.extra.data))?;
+    if args.is_empty() {
+        write!(buf, "void")?;
+    }
+    let args_len = args.len();
+    for (i, arg) in args.iter().enumerate() {
+        let (name, typ) = &arg.extra.data;
+        write!(
+            buf,
+            "{qualifier}{} {}{}",
+            C::typename(&typ.extra.data),
+            name.extra.data,
+            if i == args_len - 1 { "" } else { ", " }
+        )?;
+    }
+    write!(buf, ")")?;
+    Ok(Some(block))
+}
+
+impl Identifiers for C {
+    const RESERVED: &'static [&'static str] = &[
+        "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else",
+        "enum", "extern", "float", "for", "goto", "if", "inline", "int", "long", "register",
+        "restrict", "return", "short", "signed", "sizeof", "static", "struct", "switch",
+        "typedef", "union", "unsigned", "void", "volatile", "while", "_Alignas", "_Alignof",
+        "_Atomic", "_Bool", "_Complex", "_Generic", "_Imaginary", "_Noreturn", "_Static_assert",
+        "_Thread_local", "bool", "true", "false", "NULL", "main", "printf", "report", "exit",
+        "free", "malloc", "realloc", "atexit", "fopen", "fclose", "fgetc", "fputs", "fprintf",
+        "snprintf", "strtol", "memcpy", "memcmp", "stderr", "len", "getelement", "setelement",
+        "replace", "split", "openfile", "sus_add", "sus_sub", "sus_mod", "sus_main", "sus_kill",
+        "setjmp", "longjmp", "jmp_buf", "sus_meeting", "sus_meetings", "sus_sabotaged",
+        "sus_sabotage", "sus_ret", "sus_afk", "nanosleep", "timespec", "fflush", "stdout",
+        "sus_i", "sus_end", "sus_realloc", "tostring", "tonumber", "concat", "sus_from_int",
+        "sus_to_int", "sus_concat", "sus_blank",
+    ];
+    const ENTRY_POINT: &'static str = "main";
+
+    fn escape(name: &str) -> Cow<'_, str> {
+        if name.is_ascii() {
+            return Cow::Borrowed(name);
+        }
+        let mut escaped = String::with_capacity(name.len());
+        for c in name.chars() {
+            if c.is_ascii() {
+                escaped.push(c);
+            } else {
+                escaped.push_str(&format!("_u{:04x}", c as u32));
+            }
+        }
+        Cow::Owned(escaped)
+    }
+}
+
+impl<'a, W> Codegen<W, Linked<'a>> for C
 where
     W: Write,
 {
-    fn gen(&mut self, s: &[Span<'a, Ast<'a>>], buf: &mut W) -> std::io::Result<()> {
+    fn gen(&mut self, s: &Linked<'a>, buf: &mut W) -> std::io::Result<()> {
         writeln!(
             buf,
-            r#"// C code generated from suslang
-#include <stdio.h>
-#define report printf
-"#
+            "// C code generated from suslang\n{RUNTIME}"
         )?;
-        for ast in s {
+        let ast = sanitize::<Self>(&tailcall::eliminate(s));
+        // Prototypes let tasks call each other regardless of the order they were linked in
+        for item in &ast {
+            if signature(&item.extra.data, "", buf)?.is_some() {
+                writeln!(buf, ";")?;
+            }
+        }
+        let void_main = ast.iter().any(|item| {
+            matches!(&item.extra.data, Ast::Func(name, typ, ..) if name.extra.data == C::ENTRY_POINT && typ.extra.data == Typ::Void)
+        });
+        if void_main {
+            writeln!(buf, "{VOID_MAIN}")?;
+        }
+        for ast in &ast {
+            line_directive(ast, buf)?;
             self.gen(ast, buf)?;
         }
         Ok(())
@@ -51,29 +213,17 @@ where
         match s {
             Ast::Mod(_) => Ok(()),
             Ast::Import(_) => unreachable!(),
-            Ast::Func(name, typ, args, block) => {
-                let mut name = name.extra.data.clone();
-                for (name_r, replace) in NAME_REPLACE {
-                    if name == *name_r {
-                        name = replace.to_string();
-                        break;
-                    }
-                }
-                write!(buf, "{} {}(", Self::typename(&typ.extra.data), name)?;
-                let args_len = args.len();
-                for (i, arg) in args.iter().enumerate() {
-                    let (name, typ) = &arg.extra.data;
-                    write!(
-                        buf,
-                        "{} {}{}",
-                        Self::typename(&typ.extra.data),
-                        name.extra.data,
-                        if i == args_len - 1 { "" } else { ", " }
-                    )?;
-                }
-                writeln!(buf, ") {{")?;
+            Ast::Func(_, ret, _, body) => {
+                self.ret = Self::typename(&ret.extra.data);
+                self.volatile = holds_meeting(body);
+                self.count = 0;
+                let Some(block) = signature(s, self.volatile(), buf)? else {
+                    unreachable!()
+                };
+                writeln!(buf, " {{")?;
 
                 for line in &block.extra.data {
+                    line_directive(line, buf)?;
                     write!(buf, "\t")?;
                     self.gen(line, buf)?;
                 }
@@ -90,11 +240,23 @@ where
 {
     fn gen(&mut self, s: &Statement<'a>, buf: &mut W) -> std::io::Result<()> {
         match s {
-            Statement::Return(n) => {
-                write!(buf, "return ")?;
-                n.as_ref().map(|x| self.gen(x, buf));
-                writeln!(buf, ";")?;
-            }
+            Statement::Return(n) => match (self.meetings.first(), n) {
+                (None, n) => {
+                    write!(buf, "return ")?;
+                    n.as_ref().map(|x| self.gen(x, buf));
+                    writeln!(buf, ";")?;
+                }
+                // the value may still sabotage, so the meetings end after it
+                (Some(outer), Some(value)) => {
+                    write!(buf, "{{\n\t{} sus_ret = ", self.ret)?;
+                    let outer = *outer;
+                    self.gen(value, buf)?;
+                    writeln!(buf, ";\n\tsus_meetings = sus_meeting_{outer}.outer;\n\treturn sus_ret;\n}}")?;
+                }
+                (Some(outer), None) => {
+                    writeln!(buf, "sus_meetings = sus_meeting_{outer}.outer;\n\treturn;")?
+                }
+            },
             Statement::Expr(m) => {
                 self.gen(m, buf)?;
                 writeln!(buf, ";")?;
@@ -104,12 +266,14 @@ where
                 self.gen(cond, buf)?;
                 writeln!(buf, ") {{")?;
                 for s in &b.extra.data {
+                    line_directive(s, buf)?;
                     write!(buf, "\t")?;
                     self.gen(s, buf)?;
                 }
                 if let Some(e) = e {
                     writeln!(buf, "}} else {{")?;
                     for s in &e.extra.data {
+                        line_directive(s, buf)?;
                         write!(buf, "\t")?;
                         self.gen(s, buf)?;
                     }
@@ -118,7 +282,8 @@ where
             }
             Statement::Declare(name, typ) => writeln!(
                 buf,
-                "{} {};",
+                "{}{} {};",
+                self.volatile(),
                 Self::typename(&typ.extra.data),
                 name.extra.data
             )
@@ -128,16 +293,87 @@ where
                 self.gen(expr, buf)?;
                 writeln!(buf, ";")?;
             }
+            Statement::Kill(name) => writeln!(buf, "sus_kill({});", name.extra.data)?,
+            Statement::Try(body, name, typ, catch) => {
+                let meeting = self.count;
+                self.count += 1;
+                writeln!(buf, "{{\n\tsus_meeting sus_meeting_{meeting};")?;
+                writeln!(buf, "\tsus_meeting_{meeting}.outer = sus_meetings;")?;
+                writeln!(buf, "\tsus_meetings = &sus_meeting_{meeting};")?;
+                writeln!(buf, "\tif (!setjmp(sus_meeting_{meeting}.jump)) {{")?;
+                self.meetings.push(meeting);
+                for s in &body.extra.data {
+                    line_directive(s, buf)?;
+                    write!(buf, "\t")?;
+                    self.gen(s, buf)?;
+                }
+                self.meetings.pop();
+                writeln!(buf, "\tsus_meetings = sus_meeting_{meeting}.outer;")?;
+                // `sus_sabotage` already ended the meeting
+                writeln!(
+                    buf,
+                    "\t}} else {{\n\t{}{} {} = sus_sabotaged;",
+                    self.volatile(),
+                    Self::typename(&typ.extra.data),
+                    name.extra.data
+                )?;
+                for s in &catch.extra.data {
+                    line_directive(s, buf)?;
+                    write!(buf, "\t")?;
+                    self.gen(s, buf)?;
+                }
+                writeln!(buf, "\t}}\n}}")?;
+            }
+            Statement::Sabotage(reason) => {
+                write!(buf, "sus_sabotage(")?;
+                self.gen(reason, buf)?;
+                writeln!(buf, ");")?;
+            }
+            Statement::Win => writeln!(buf, "exit(0);")?,
+            Statement::Lose => writeln!(buf, "exit(1);")?,
+            Statement::Afk(ms) => {
+                write!(buf, "sus_afk(")?;
+                self.gen(ms, buf)?;
+                writeln!(buf, ");")?;
+            }
             Statement::While(cond, body) => {
                 write!(buf, "while (")?;
                 self.gen(cond, buf)?;
                 writeln!(buf, ") {{")?;
+                self.loops.push(self.meetings.len());
                 for s in &body.extra.data {
+                    line_directive(s, buf)?;
                     write!(buf, "\t")?;
                     self.gen(s, buf)?;
                 }
+                self.loops.pop();
                 writeln!(buf, "}}")?;
             }
+            Statement::For(name, start, end, body) => {
+                write!(buf, "for ({}int sus_i = ", self.volatile())?;
+                self.gen(start, buf)?;
+                write!(buf, ", sus_end = ")?;
+                self.gen(end, buf)?;
+                writeln!(buf, "; sus_i < sus_end; sus_i++) {{")?;
+                writeln!(buf, "\t{}int {} = sus_i;", self.volatile(), name.extra.data)?;
+                self.loops.push(self.meetings.len());
+                for s in &body.extra.data {
+                    line_directive(s, buf)?;
+                    write!(buf, "\t")?;
+                    self.gen(s, buf)?;
+                }
+                self.loops.pop();
+                writeln!(buf, "}}")?;
+            }
+            Statement::Break | Statement::Continue => {
+                let jump = if *s == Statement::Break { "break" } else { "continue" };
+                // the meetings opened inside the loop end when leaving the turn
+                let open = self.loops.last().copied().unwrap_or_default();
+                if let Some(outer) = self.meetings.get(open) {
+                    write!(buf, "sus_meetings = sus_meeting_{outer}.outer;\n\t")?;
+                }
+                writeln!(buf, "{jump};")?;
+            }
         }
         Ok(())
     }
@@ -149,15 +385,22 @@ where
 {
     fn gen(&mut self, expr: &Expression<'a>, buf: &mut W) -> std::io::Result<()> {
         match expr {
-            Expression::Call(func, args) => {
-                let mut func = func.extra.data.clone();
-                for (name_r, replace) in NAME_REPLACE {
-                    if func == *name_r {
-                        func = replace.to_string();
-                        break;
-                    }
+            Expression::Call(func, args) if func.extra.data == "report" => {
+                // The format stays a plain literal and the strings are handed over as `char*`
+                let Some(Expression::StringLit(format)) = args.first().map(|x| &x.extra.data)
+                else {
+                    unreachable!("report is always called with a literal format")
+                };
+                write!(buf, "report({}", c_string(format).0)?;
+                for arg in &args[1..] {
+                    write!(buf, ", sus_fmt(")?;
+                    self.gen(arg, buf)?;
+                    write!(buf, ")")?;
                 }
-                write!(buf, "{func}(")?;
+                write!(buf, ")")?;
+            }
+            Expression::Call(func, args) => {
+                write!(buf, "{}(", func.extra.data)?;
                 let args_len = args.len();
                 for (i, arg) in args.iter().enumerate() {
                     self.gen(arg, buf)?;
@@ -168,7 +411,10 @@ where
                 write!(buf, ")")?;
             }
             Expression::NumLit(s) => write!(buf, "{s}")?,
-            Expression::StringLit(s) => write!(buf, "{s:?}")?,
+            Expression::StringLit(s) => {
+                let (literal, len) = c_string(s);
+                write!(buf, "sus_lit({literal}, {len})")?
+            }
             Expression::Operation(
                 LocatedSpan {
                     extra:
@@ -180,9 +426,11 @@ where
                 lhs,
                 rhs,
             ) => {
+                write!(buf, "(")?;
                 self.gen(lhs.as_ref(), buf)?;
                 write!(buf, " < ")?;
                 self.gen(rhs.as_ref(), buf)?;
+                write!(buf, ")")?;
             }
             Expression::Operation(
                 LocatedSpan {
@@ -196,9 +444,11 @@ where
                 lhs,
                 rhs,
             ) => {
+                write!(buf, "(")?;
                 self.gen(lhs.as_ref(), buf)?;
                 write!(buf, " >= ")?;
                 self.gen(rhs.as_ref(), buf)?;
+                write!(buf, ")")?;
             }
             Expression::Operation(
                 LocatedSpan {
@@ -211,9 +461,11 @@ where
                 lhs,
                 rhs,
             ) => {
+                write!(buf, "sus_eq(")?;
                 self.gen(lhs.as_ref(), buf)?;
-                write!(buf, " == ")?;
+                write!(buf, ", ")?;
                 self.gen(rhs.as_ref(), buf)?;
+                write!(buf, ")")?;
             }
             Expression::Operation(
                 LocatedSpan {
@@ -227,9 +479,11 @@ where
                 lhs,
                 rhs,
             ) => {
+                write!(buf, "sus_add(")?;
                 self.gen(lhs.as_ref(), buf)?;
-                write!(buf, " + ")?;
+                write!(buf, ", ")?;
                 self.gen(rhs.as_ref(), buf)?;
+                write!(buf, ")")?;
             }
             Expression::Operation(
                 LocatedSpan {
@@ -243,9 +497,11 @@ where
                 lhs,
                 rhs,
             ) => {
+                write!(buf, "sus_sub(")?;
                 self.gen(lhs.as_ref(), buf)?;
-                write!(buf, " - ")?;
+                write!(buf, ", ")?;
                 self.gen(rhs.as_ref(), buf)?;
+                write!(buf, ")")?;
             }
             Expression::Operation(
                 LocatedSpan {
@@ -259,13 +515,51 @@ where
                 lhs,
                 rhs,
             ) => {
+                write!(buf, "sus_mod(")?;
                 self.gen(lhs.as_ref(), buf)?;
-                write!(buf, " % ")?;
+                write!(buf, ", ")?;
                 self.gen(rhs.as_ref(), buf)?;
+                write!(buf, ")")?;
             }
             Expression::Variable(x) => write!(buf, "{x}")?,
-            Expression::BoolLit(b) => write!(buf, "{}", i32::from(*b))?,
+            Expression::BoolLit(b) => write!(buf, "{b}")?,
         };
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{c_string, holds_meeting, line_directive};
+    use crate::{
+        ast::{parse::spans::load_file_str, Ast},
+        parse_str,
+    };
+
+    #[test]
+    fn string_literals() {
+        assert_eq!(c_string("hey\n"), (r#""hey\n""#.into(), 4));
+        assert_eq!(c_string("\"ඞ\"??="), (r#""\"ඞ\"\?\?=""#.into(), 8));
+        assert_eq!(c_string("\u{1b}7"), (r#""\0337""#.into(), 2));
+    }
+
+    #[test]
+    fn line_directives_quote_the_filename() {
+        let mut buf = Vec::new();
+        line_directive(&load_file_str("ච\\\u{dd3}.sus", ""), &mut buf).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "#line 1 \"ච\\\\\u{dd3}.sus\"\n");
+    }
+
+    #[test]
+    fn meetings_are_found_in_nested_blocks() {
+        let meeting = |main: &str| {
+            let items = parse_str(main).unwrap();
+            let Ast::Func(_, _, _, body) = &items[0].extra.data else {
+                unreachable!()
+            };
+            holds_meeting(body)
+        };
+        assert!(meeting("task ඬ with ➤ void\nචsus? == 1 1\nචචfor red in 0 to 2\nචචචemergency meeting\nචචචචsabotage \"a\"ඞ\nචචචimpostor blue: string\nචචචචbreakඞ\n"));
+        assert!(!meeting("task ඬ with ➤ void\nචsus? == 1 1\nචචsabotage \"a\"ඞ\n"));
+    }
+}
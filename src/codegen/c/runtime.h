@@ -0,0 +1,333 @@
+// suslang C runtime
+// Strings are immutable and allocated in an arena that is freed at exit.
+// `split` and `openfile` return strings that also hold a list of items, so
+// `len` and `getelement` work on them like on Python lists.
+// `afk` needs `nanosleep` from POSIX, which strict C modes hide
+#ifndef _POSIX_C_SOURCE
+#define _POSIX_C_SOURCE 200809L
+#endif
+#include <setjmp.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <time.h>
+
+typedef struct sus_string *sus_str;
+
+struct sus_string {
+	size_t len;
+	const char *data;
+	size_t n_items;
+	sus_str *items;
+};
+
+static struct {
+	void **blocks;
+	size_t len;
+	size_t cap;
+} sus_arena;
+
+static inline void sus_arena_free(void) {
+	for (size_t i = 0; i < sus_arena.len; i++) {
+		free(sus_arena.blocks[i]);
+	}
+	free(sus_arena.blocks);
+	sus_arena.blocks = NULL;
+	sus_arena.len = sus_arena.cap = 0;
+}
+
+// realloc that ejects the program when there's no memory left
+static inline void *sus_realloc(void *block, size_t size) {
+	block = realloc(block, size ? size : 1);
+	if (!block) {
+		fputs("program was ejected (out of memory)\n", stderr);
+		exit(1);
+	}
+	return block;
+}
+
+static inline void *sus_alloc(size_t size) {
+	if (sus_arena.len == sus_arena.cap) {
+		if (sus_arena.cap == 0) {
+			atexit(sus_arena_free);
+		}
+		sus_arena.cap = sus_arena.cap ? sus_arena.cap * 2 : 64;
+		sus_arena.blocks = sus_realloc(sus_arena.blocks, sus_arena.cap * sizeof(void *));
+	}
+	void *block = sus_realloc(NULL, size);
+	sus_arena.blocks[sus_arena.len++] = block;
+	return block;
+}
+
+static inline sus_str sus_new(const char *data, size_t len) {
+	sus_str s = sus_alloc(sizeof(struct sus_string));
+	char *copy = sus_alloc(len + 1);
+	memcpy(copy, data, len);
+	copy[len] = '\0';
+	s->len = len;
+	s->data = copy;
+	s->n_items = 0;
+	s->items = NULL;
+	return s;
+}
+
+static inline sus_str sus_lit(const char *data, size_t len) {
+	return sus_new(data, len);
+}
+
+static inline sus_str sus_list(sus_str *items, size_t n_items) {
+	sus_str s = sus_new("", 0);
+	s->items = sus_alloc(n_items * sizeof(sus_str));
+	memcpy(s->items, items, n_items * sizeof(sus_str));
+	s->n_items = n_items;
+	return s;
+}
+
+static inline const char *sus_cstr(sus_str s) {
+	return s ? s->data : "";
+}
+
+// `emergency meeting`s a `sabotage` can jump back to, innermost first. Tasks
+// that hold one make their crewmates volatile, so they survive the `longjmp`
+typedef struct sus_meeting {
+	jmp_buf jump;
+	struct sus_meeting *outer;
+} sus_meeting;
+
+static sus_meeting *sus_meetings = NULL;
+// Reason of the last sabotage, for the impostor of the meeting it jumps to
+static sus_str sus_sabotaged;
+
+static inline _Noreturn void sus_sabotage(sus_str reason) {
+	sus_meeting *meeting = sus_meetings;
+	if (!meeting) {
+		fprintf(stderr, "program was ejected (%s)\n", sus_cstr(reason));
+		exit(1);
+	}
+	sus_meetings = meeting->outer;
+	sus_sabotaged = reason;
+	longjmp(meeting->jump, 1);
+}
+
+static inline void sus_afk(int ms) {
+	fflush(stdout);
+	if (ms <= 0) {
+		return;
+	}
+	struct timespec left = {ms / 1000, (ms % 1000) * 1000000L};
+	// a signal can wake it up early, with the time that was left
+	while (nanosleep(&left, &left) == -1) {
+	}
+}
+
+static inline int sus_int(int n) {
+	return n;
+}
+
+static inline bool sus_str_eq(sus_str a, sus_str b) {
+	return a->len == b->len && memcmp(a->data, b->data, a->len) == 0;
+}
+
+static inline bool sus_int_eq(int a, int b) {
+	return a == b;
+}
+
+static inline sus_str sus_from_int(int n) {
+	char buf[16];
+	int len = snprintf(buf, sizeof(buf), "%d", n);
+	return sus_new(buf, (size_t)len);
+}
+
+static inline bool sus_blank(char c) {
+	return c == ' ' || c == '\t' || c == '\n' || c == '\r';
+}
+
+// Decimal digits with an optional `-` and blanks around them, wrapping around
+// like the other numbers. Anything else is a sabotage
+static inline int sus_to_int(sus_str s) {
+	size_t i = 0, end = s->len;
+	while (i < end && sus_blank(s->data[i])) {
+		i++;
+	}
+	while (end > i && sus_blank(s->data[end - 1])) {
+		end--;
+	}
+	bool negative = i < end && s->data[i] == '-';
+	size_t digits = negative ? i + 1 : i;
+	unsigned n = 0;
+	for (i = digits; i < end && s->data[i] >= '0' && s->data[i] <= '9'; i++) {
+		n = n * 10 + (unsigned)(s->data[i] - '0');
+	}
+	if (i == digits || i != end) {
+		size_t size = s->len + sizeof("not a number: ");
+		char *reason = sus_alloc(size);
+		snprintf(reason, size, "not a number: %s", s->data);
+		sus_sabotage(sus_new(reason, size - 1));
+	}
+	return (int)(negative ? 0u - n : n);
+}
+
+static inline sus_str sus_concat(sus_str a, sus_str b) {
+	char *data = sus_alloc(a->len + b->len + 1);
+	memcpy(data, a->data, a->len);
+	memcpy(data + a->len, b->data, b->len);
+	return sus_new(data, a->len + b->len);
+}
+
+// Numbers wrap around and `%` truncates, as defined in `semantics.rs`
+static inline int sus_add(int a, int b) {
+	return (int)((unsigned)a + (unsigned)b);
+}
+
+static inline int sus_sub(int a, int b) {
+	return (int)((unsigned)a - (unsigned)b);
+}
+
+static inline int sus_mod(int a, int b) {
+	if (b == 0) {
+		fputs("program was ejected (division by zero)\n", stderr);
+		exit(1);
+	}
+	return b == -1 ? 0 : a % b;
+}
+
+#define sus_eq(a, b) _Generic((a), sus_str: sus_str_eq, default: sus_int_eq)((a), (b))
+#define sus_fmt(x) _Generic((x), sus_str: sus_cstr, default: sus_int)(x)
+// `kill` only forgets the string, other crewmates can still share it. The arena
+// frees it at exit
+#define sus_kill(x) ((x) = _Generic((x), sus_str: (sus_str)NULL, default: (x)))
+#define report printf
+#define tostring sus_from_int
+#define tonumber sus_to_int
+#define concat sus_concat
+
+static inline int len(sus_str s) {
+	return (int)(s->items ? s->n_items : s->len);
+}
+
+static inline size_t sus_index(sus_str s, int index) {
+	int n = len(s);
+	if (index < 0) {
+		index += n;
+	}
+	if (index < 0 || index >= n) {
+		fprintf(stderr, "program was ejected (index %d out of range)\n", index);
+		exit(1);
+	}
+	return (size_t)index;
+}
+
+static inline sus_str getelement(sus_str s, int index) {
+	size_t i = sus_index(s, index);
+	return s->items ? s->items[i] : sus_new(s->data + i, 1);
+}
+
+static inline sus_str setelement(sus_str s, int index, sus_str value) {
+	size_t i = sus_index(s, index);
+	if (s->items) {
+		sus_str list = sus_list(s->items, s->n_items);
+		list->items[i] = value;
+		return list;
+	}
+	size_t len = s->len - 1 + value->len;
+	char *data = sus_alloc(len + 1);
+	memcpy(data, s->data, i);
+	memcpy(data + i, value->data, value->len);
+	memcpy(data + i + value->len, s->data + i + 1, s->len - i - 1);
+	return sus_new(data, len);
+}
+
+static inline sus_str replace(sus_str s, sus_str old, sus_str new) {
+	if (old->len == 0) {
+		return s;
+	}
+	size_t cap = s->len + 1, len = 0;
+	char *data = sus_realloc(NULL, cap);
+	for (size_t i = 0; i < s->len;) {
+		if (i + old->len <= s->len && memcmp(s->data + i, old->data, old->len) == 0) {
+			if (len + new->len + 1 > cap) {
+				cap = (len + new->len + 1) * 2;
+				data = sus_realloc(data, cap);
+			}
+			memcpy(data + len, new->data, new->len);
+			len += new->len;
+			i += old->len;
+		} else {
+			if (len + 2 > cap) {
+				cap *= 2;
+				data = sus_realloc(data, cap);
+			}
+			data[len++] = s->data[i++];
+		}
+	}
+	sus_str result = sus_new(data, len);
+	free(data);
+	return result;
+}
+
+static inline sus_str split(sus_str s, sus_str sep) {
+	if (sep->len == 0) {
+		fputs("program was ejected (empty separator)\n", stderr);
+		exit(1);
+	}
+	size_t n_items = 0, cap = 8;
+	sus_str *items = sus_realloc(NULL, cap * sizeof(sus_str));
+	size_t start = 0;
+	for (size_t i = 0; i + sep->len <= s->len;) {
+		if (memcmp(s->data + i, sep->data, sep->len) == 0) {
+			if (n_items + 1 >= cap) {
+				cap *= 2;
+				items = sus_realloc(items, cap * sizeof(sus_str));
+			}
+			items[n_items++] = sus_new(s->data + start, i - start);
+			i += sep->len;
+			start = i;
+		} else {
+			i++;
+		}
+	}
+	items[n_items++] = sus_new(s->data + start, s->len - start);
+	sus_str list = sus_list(items, n_items);
+	free(items);
+	return list;
+}
+
+static inline sus_str openfile(sus_str path) {
+	FILE *f = fopen(path->data, "rb");
+	if (!f) {
+		size_t size = path->len + sizeof("can't open ");
+		char *reason = sus_alloc(size);
+		snprintf(reason, size, "can't open %s", path->data);
+		sus_sabotage(sus_new(reason, size - 1));
+	}
+	size_t n_items = 0, cap = 64;
+	sus_str *items = sus_realloc(NULL, cap * sizeof(sus_str));
+	char *line = NULL;
+	size_t line_cap = 0, line_len = 0;
+	int c;
+	do {
+		c = fgetc(f);
+		if (c != EOF) {
+			if (line_len + 1 >= line_cap) {
+				line_cap = line_cap ? line_cap * 2 : 128;
+				line = sus_realloc(line, line_cap);
+			}
+			line[line_len++] = (char)c;
+		}
+		if ((c == '\n' || c == EOF) && line_len > 0) {
+			if (n_items == cap) {
+				cap *= 2;
+				items = sus_realloc(items, cap * sizeof(sus_str));
+			}
+			items[n_items++] = sus_new(line, line_len);
+			line_len = 0;
+		}
+	} while (c != EOF);
+	fclose(f);
+	free(line);
+	sus_str list = sus_list(items, n_items);
+	free(items);
+	return list;
+}
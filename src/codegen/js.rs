@@ -1,32 +1,224 @@
 use std::io::Write;
 
-use crate::ast::{parse::spans::Span, Ast, Expression, Operator, Statement};
+use crate::{
+    ast::{Ast, Expression, Operator, Statement},
+    linker::Linked,
+};
 
-use super::Codegen;
+use super::{
+    rename::sanitize,
+    tailcall,
+    sourcemap::{base64, json_string, source_map_json, Tracked},
+    Codegen, Identifiers,
+};
 
-pub struct Js;
+/// Kind of program the JS backend emits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JsOutput {
+    /// Node script that can also be `require`d
+    #[default]
+    CommonJs,
+    /// ES module for Node, runs `ඬ` when it's the entrypoint
+    EsModule,
+    /// Classic browser script, output goes to the console line by line
+    Browser,
+}
+
+impl std::str::FromStr for JsOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "commonjs" | "cjs" => Ok(Self::CommonJs),
+            "esm" | "module" => Ok(Self::EsModule),
+            "browser" => Ok(Self::Browser),
+            _ => Err(format!(
+                "unknown JS output `{s}`, expected commonjs, esm or browser"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Js {
+    output: JsOutput,
+}
+
+impl Js {
+    pub fn new(output: JsOutput) -> Self {
+        Self { output }
+    }
+}
+
+const RUNTIME: &str = include_str!("js/runtime.js");
+
+const NODE_IO: &str = r#"function sus_write(s) {
+	const data = Buffer.from(s);
+	for (let written = 0; written < data.length;) {
+		try {
+			written += writeSync(1, data, written);
+		} catch (e) {
+			if (e.code !== "EAGAIN") {
+				throw e;
+			}
+		}
+	}
+}
+
+function sus_eject(reason) {
+	writeSync(2, `program was ejected (${reason})\n`);
+	process.exit(1);
+}
+
+function sus_read_file(path) {
+	try {
+		return readFileSync(path, "utf8");
+	} catch {
+		throw new SusSabotage(`can't open ${path}`);
+	}
+}
+
+function sus_exit(status) {
+	process.exit(status);
+}
+
+function sus_afk(ms) {
+	if (ms > 0) {
+		Atomics.wait(new Int32Array(new SharedArrayBuffer(4)), 0, 0, ms);
+	}
+}
+
+// Runs ඬ, ejecting the program on a sabotage no impostor handled
+function sus_main(main) {
+	try {
+		return main();
+	} catch (e) {
+		sus_eject(sus_impostor(e));
+	}
+}
+"#;
+
+const BROWSER_IO: &str = r#"let sus_line = "";
+function sus_write(s) {
+	const lines = (sus_line + s).split("\n");
+	sus_line = lines.pop();
+	for (const line of lines) {
+		console.log(line);
+	}
+}
+
+function sus_eject(reason) {
+	throw new Error(`program was ejected (${reason})`);
+}
+
+function sus_read_file(path) {
+	throw new SusSabotage(`can't open ${path}`);
+}
 
-impl<'a, W: Write> Codegen<W, [Span<'a, Ast<'a>>]> for Js {
-    #[allow(clippy::only_used_in_recursion)]
-    fn gen(&mut self, s: &[Span<'a, Ast<'a>>], buf: &mut W) -> std::io::Result<()> {
+// A page can't be closed from a script, so the error stops the rest of the program
+function sus_exit(status) {
+	if (sus_line) {
+		console.log(sus_line);
+		sus_line = "";
+	}
+	throw new Error(`program ${status ? "lost" : "won"}`);
+}
+
+// Nor can the page sleep, so it waits busily
+function sus_afk(ms) {
+	const end = Date.now() + ms;
+	while (Date.now() < end) {}
+}
+"#;
+
+impl Identifiers for Js {
+    const RESERVED: &'static [&'static str] = &[
+        "await", "break", "case", "catch", "class", "const", "continue", "debugger", "default",
+        "delete", "do", "else", "enum", "export", "extends", "false", "finally", "for",
+        "function", "if", "implements", "import", "in", "instanceof", "interface", "let", "new",
+        "null", "package", "private", "protected", "public", "return", "static", "super",
+        "switch", "this", "throw", "true", "try", "typeof", "var", "void", "while", "with",
+        "yield", "arguments", "eval", "undefined", "NaN", "Infinity", "console", "process",
+        "require", "module", "exports", "Array", "Buffer", "Error", "Number", "String",
+        "readFileSync", "writeSync", "pathToFileURL", "report", "len", "getelement",
+        "setelement", "replace", "split", "openfile", "tostring", "tonumber", "concat", "BigInt",
+        "sus_index", "sus_mod", "sus_write", "sus_eject", "sus_read_file", "sus_line", "sus_main",
+        "SusSabotage", "sus_impostor", "sus_error", "sus_exit", "sus_afk", "sus_i", "sus_end",
+        "Atomics", "Int32Array", "SharedArrayBuffer", "Date",
+    ];
+}
+
+impl<'a, W: Write> Codegen<W, Linked<'a>> for Js {
+    fn gen(&mut self, s: &Linked<'a>, buf: &mut W) -> std::io::Result<()> {
+        let mut tracked = Tracked::new(&mut *buf);
+        writeln!(tracked, "// suslang automagically generated code")?;
+        match self.output {
+            JsOutput::CommonJs => {
+                writeln!(tracked, "const {{ readFileSync, writeSync }} = require(\"node:fs\");")?
+            }
+            JsOutput::EsModule => writeln!(
+                tracked,
+                "import {{ readFileSync, writeSync }} from \"node:fs\";\nimport {{ pathToFileURL }} from \"node:url\";"
+            )?,
+            JsOutput::Browser => (),
+        }
         writeln!(
-            buf,
-            r#"// suslang automagically generated code
-function report(s, ...a) {{
-	console.log(s.trimEnd(), a);
-}}
-"#
+            tracked,
+            "\n{}\n{RUNTIME}",
+            match self.output {
+                JsOutput::CommonJs | JsOutput::EsModule => NODE_IO,
+                JsOutput::Browser => BROWSER_IO,
+            }
         )?;
-        for s in s {
-            self.gen(s, buf)?;
+        let ast = sanitize::<Self>(&tailcall::eliminate(s));
+        for s in &ast {
+            tracked.mark(s);
+            self.gen(s, &mut tracked)?;
         }
-        writeln!(buf, "ඬ()")?;
+
+        let tasks = ast
+            .iter()
+            .filter_map(|item| match &item.extra.data {
+                Ast::Func(name, ..) => Some(name.extra.data.as_str()),
+                Ast::Mod(_) | Ast::Import(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let has_main = tasks.contains(&"ඬ");
+        writeln!(tracked)?;
+        match self.output {
+            JsOutput::CommonJs => {
+                writeln!(tracked, "module.exports = {{ {} }};", tasks.join(", "))?;
+                if has_main {
+                    writeln!(tracked, "if (require.main === module) {{\n\tprocess.exitCode = sus_main(ඬ);\n}}")?;
+                }
+            }
+            JsOutput::EsModule => {
+                writeln!(tracked, "export {{ {} }};", tasks.join(", "))?;
+                if has_main {
+                    writeln!(
+                        tracked,
+                        "if (process.argv[1] && import.meta.url === pathToFileURL(process.argv[1]).href) {{\n\tprocess.exitCode = sus_main(ඬ);\n}}"
+                    )?;
+                }
+            }
+            JsOutput::Browser if has_main => {
+                writeln!(tracked, "ඬ();\nif (sus_line) {{\n\tconsole.log(sus_line);\n}}")?
+            }
+            JsOutput::Browser => (),
+        }
+
+        let source_map = source_map_json(tracked.mappings());
+        writeln!(
+            tracked,
+            "//# sourceMappingURL=data:application/json;charset=utf-8;base64,{}",
+            base64(source_map.as_bytes())
+        )?;
         Ok(())
     }
 }
 
-impl<'a, W: Write> Codegen<W, Ast<'a>> for Js {
-    fn gen(&mut self, s: &Ast<'a>, buf: &mut W) -> std::io::Result<()> {
+impl<'a, W: Write> Codegen<Tracked<'a, W>, Ast<'a>> for Js {
+    fn gen(&mut self, s: &Ast<'a>, buf: &mut Tracked<'a, W>) -> std::io::Result<()> {
         match s {
             Ast::Mod(_) => (),
             Ast::Import(_) => todo!(),
@@ -46,6 +238,7 @@ impl<'a, W: Write> Codegen<W, Ast<'a>> for Js {
                 }
                 writeln!(buf, "){{")?;
                 for s in &block.extra.data {
+                    buf.mark(s);
                     self.gen(s, buf)?;
                 }
                 writeln!(buf, "}}")?;
@@ -55,19 +248,21 @@ impl<'a, W: Write> Codegen<W, Ast<'a>> for Js {
     }
 }
 
-impl<'a, W: Write> Codegen<W, Statement<'a>> for Js {
-    fn gen(&mut self, s: &Statement, buf: &mut W) -> std::io::Result<()> {
+impl<'a, W: Write> Codegen<Tracked<'a, W>, Statement<'a>> for Js {
+    fn gen(&mut self, s: &Statement<'a>, buf: &mut Tracked<'a, W>) -> std::io::Result<()> {
         match s {
             Statement::If(cond, block, else_block) => {
                 write!(buf, "if (")?;
                 self.gen(cond, buf)?;
                 writeln!(buf, ") {{")?;
                 for s in &block.extra.data {
+                    buf.mark(s);
                     self.gen(s, buf)?;
                 }
                 if let Some(else_block) = else_block {
                     writeln!(buf, "}} else {{")?;
                     for s in &else_block.extra.data {
+                        buf.mark(s);
                         self.gen(s, buf)?;
                     }
                 }
@@ -78,10 +273,26 @@ impl<'a, W: Write> Codegen<W, Statement<'a>> for Js {
                 self.gen(cond, buf)?;
                 writeln!(buf, ") {{")?;
                 for s in &block.extra.data {
+                    buf.mark(s);
+                    self.gen(s, buf)?;
+                }
+                writeln!(buf, "}}")?;
+            }
+            Statement::For(name, start, end, block) => {
+                write!(buf, "for (let sus_i = ")?;
+                self.gen(start, buf)?;
+                write!(buf, ", sus_end = ")?;
+                self.gen(end, buf)?;
+                // a copy, so that making the crewmate doesn't change the turns
+                writeln!(buf, "; sus_i < sus_end; sus_i++) {{\nlet {} = sus_i;", name.extra.data)?;
+                for s in &block.extra.data {
+                    buf.mark(s);
                     self.gen(s, buf)?;
                 }
                 writeln!(buf, "}}")?;
             }
+            Statement::Break => writeln!(buf, "break;")?,
+            Statement::Continue => writeln!(buf, "continue;")?,
             Statement::Return(e) => {
                 write!(buf, "return ")?;
                 if let Some(e) = e {
@@ -99,13 +310,40 @@ impl<'a, W: Write> Codegen<W, Statement<'a>> for Js {
                 self.gen(e, buf)?;
                 writeln!(buf, ";")?;
             }
+            Statement::Kill(name) => writeln!(buf, "{} = undefined;", name.extra.data)?,
+            Statement::Try(body, name, _, catch) => {
+                writeln!(buf, "try {{")?;
+                for s in &body.extra.data {
+                    buf.mark(s);
+                    self.gen(s, buf)?;
+                }
+                writeln!(buf, "}} catch (sus_error) {{")?;
+                writeln!(buf, "let {} = sus_impostor(sus_error);", name.extra.data)?;
+                for s in &catch.extra.data {
+                    buf.mark(s);
+                    self.gen(s, buf)?;
+                }
+                writeln!(buf, "}}")?;
+            }
+            Statement::Sabotage(reason) => {
+                write!(buf, "throw new SusSabotage(")?;
+                self.gen(reason, buf)?;
+                writeln!(buf, ");")?;
+            }
+            Statement::Win => writeln!(buf, "sus_exit(0);")?,
+            Statement::Lose => writeln!(buf, "sus_exit(1);")?,
+            Statement::Afk(ms) => {
+                write!(buf, "sus_afk(")?;
+                self.gen(ms, buf)?;
+                writeln!(buf, ");")?;
+            }
         }
         Ok(())
     }
 }
 
-impl<'a, W: Write> Codegen<W, Expression<'a>> for Js {
-    fn gen(&mut self, s: &Expression, buf: &mut W) -> std::io::Result<()> {
+impl<'a, W: Write> Codegen<Tracked<'a, W>, Expression<'a>> for Js {
+    fn gen(&mut self, s: &Expression<'a>, buf: &mut Tracked<'a, W>) -> std::io::Result<()> {
         match s {
             Expression::Call(name, args) => {
                 write!(buf, "{}(", name.extra.data)?;
@@ -120,37 +358,49 @@ impl<'a, W: Write> Codegen<W, Expression<'a>> for Js {
             }
             Expression::Operation(op, lhs, rhs) => match op.extra.data {
                 Operator::Add => {
+                    write!(buf, "((")?;
                     self.gen(lhs.as_ref(), buf)?;
                     write!(buf, " + ")?;
                     self.gen(rhs.as_ref(), buf)?;
+                    write!(buf, ") | 0)")?;
                 }
                 Operator::Sub => {
+                    write!(buf, "((")?;
                     self.gen(lhs.as_ref(), buf)?;
                     write!(buf, " - ")?;
                     self.gen(rhs.as_ref(), buf)?;
+                    write!(buf, ") | 0)")?;
                 }
                 Operator::Mod => {
+                    write!(buf, "sus_mod(")?;
                     self.gen(lhs.as_ref(), buf)?;
-                    write!(buf, " % ")?;
+                    write!(buf, ", ")?;
                     self.gen(rhs.as_ref(), buf)?;
+                    write!(buf, ")")?;
                 }
                 Operator::Lt => {
+                    write!(buf, "(")?;
                     self.gen(lhs.as_ref(), buf)?;
                     write!(buf, " < ")?;
                     self.gen(rhs.as_ref(), buf)?;
+                    write!(buf, ")")?;
                 }
                 Operator::GEt => {
+                    write!(buf, "(")?;
                     self.gen(lhs.as_ref(), buf)?;
                     write!(buf, " >= ")?;
                     self.gen(rhs.as_ref(), buf)?;
+                    write!(buf, ")")?;
                 }
                 Operator::Eq => {
+                    write!(buf, "(")?;
                     self.gen(lhs.as_ref(), buf)?;
                     write!(buf, " == ")?;
                     self.gen(rhs.as_ref(), buf)?;
+                    write!(buf, ")")?;
                 }
             },
-            Expression::StringLit(s) => write!(buf, "{s:?}")?,
+            Expression::StringLit(s) => write!(buf, "{}", json_string(s))?,
             Expression::NumLit(n) => write!(buf, "{n}")?,
             Expression::BoolLit(b) => write!(buf, "{b}")?,
             Expression::Variable(n) => write!(buf, "{n}")?,
@@ -0,0 +1,104 @@
+// Same semantics as `printf` for the conversions the typechecker accepts
+function report(format, ...args) {
+	let out = "";
+	for (let i = 0; i < format.length; i++) {
+		if (format[i] !== "%") {
+			out += format[i];
+			continue;
+		}
+		i++;
+		switch (format[i]) {
+			case "d":
+				out += String(Number(args.shift()));
+				break;
+			case "s":
+				out += String(args.shift());
+				break;
+			case "%":
+				out += "%";
+				break;
+		}
+	}
+	sus_write(out);
+}
+
+// Thrown by `sabotage`, the reason is the message
+class SusSabotage extends Error {}
+
+// Reason of a sabotage caught by an impostor, anything else goes on
+function sus_impostor(e) {
+	if (!(e instanceof SusSabotage)) {
+		throw e;
+	}
+	return e.message;
+}
+
+// Numbers follow `semantics.rs`: `| 0` wraps them to 32 bits and `%` already truncates
+function sus_mod(a, b) {
+	if (b === 0) {
+		sus_eject("division by zero");
+	}
+	return (a % b) | 0;
+}
+
+function len(s) {
+	return s.length;
+}
+
+function sus_index(s, index) {
+	const n = s.length;
+	if (index < 0) {
+		index += n;
+	}
+	if (index < 0 || index >= n) {
+		sus_eject(`index ${index} out of range`);
+	}
+	return index;
+}
+
+function getelement(s, index) {
+	return s[sus_index(s, index)];
+}
+
+function setelement(s, index, value) {
+	const i = sus_index(s, index);
+	if (Array.isArray(s)) {
+		const list = s.slice();
+		list[i] = value;
+		return list;
+	}
+	return s.slice(0, i) + value + s.slice(i + 1);
+}
+
+function replace(s, old, with_) {
+	return old === "" ? s : s.split(old).join(with_);
+}
+
+function split(s, sep) {
+	if (sep === "") {
+		sus_eject("empty separator");
+	}
+	return s.split(sep);
+}
+
+function tostring(n) {
+	return String(n);
+}
+
+// Decimal digits with an optional `-` and blanks around them, anything else is a sabotage
+function tonumber(s) {
+	const digits = s.replace(/^[ \t\n\r]+|[ \t\n\r]+$/g, "");
+	if (!/^-?[0-9]+$/.test(digits)) {
+		throw new SusSabotage(`not a number: ${s}`);
+	}
+	return Number(BigInt.asIntN(32, BigInt(digits)));
+}
+
+function concat(a, b) {
+	return a + b;
+}
+
+// Lines keep their "\n", like in the C runtime
+function openfile(path) {
+	return sus_read_file(path).match(/[^\n]*\n|[^\n]+/g) ?? [];
+}
@@ -1,8 +1,16 @@
 use std::io::Write;
 
-use crate::ast::{parse::spans::Span, Ast, Expression, Operator, Statement, Typ};
+use crate::{
+    ast::{parse::spans::Span, Ast, Expression, Operator, Statement, Typ},
+    linker::Linked,
+};
 
-use super::Codegen;
+use super::{
+    rename::sanitize,
+    tailcall,
+    sourcemap::{json_string, Tracked},
+    Codegen, Identifiers,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct Py {
@@ -14,6 +22,23 @@ impl Py {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Writes the statements of a block, indented one level deeper than its header
+    fn block<'a, W: Write>(
+        &mut self,
+        statements: &[Span<'a, Statement<'a>>],
+        buf: &mut Tracked<'a, W>,
+    ) -> std::io::Result<()> {
+        for s in statements {
+            buf.mark(s);
+            self.gen(s, buf)?;
+        }
+        // the optimizer can leave a block empty, which Python does not allow
+        if statements.is_empty() {
+            writeln!(buf, "{}pass", "\t".repeat(self.tab_count))?;
+        }
+        Ok(())
+    }
 }
 
 const fn default_value(typ: &Typ) -> &'static str {
@@ -25,6 +50,19 @@ const fn default_value(typ: &Typ) -> &'static str {
     }
 }
 
+impl Identifiers for Py {
+    const RESERVED: &'static [&'static str] = &[
+        "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class",
+        "continue", "def", "del", "elif", "else", "except", "finally", "for", "from", "global",
+        "if", "import", "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return",
+        "try", "while", "with", "yield", "match", "case", "sys", "print", "len", "open", "str",
+        "int", "report", "getelement", "setelement", "length", "replace", "split", "openfile",
+        "_sus_lines", "_sus_excepthook", "_sus_eject", "_sus_wrap", "_sus_add", "_sus_sub",
+        "_sus_mod", "_SusSabotage", "_sus_impostor", "time", "_sus_afk",
+        "range", "re", "tostring", "tonumber", "concat",
+    ];
+}
+
 // static TAB_COUNT: AtomicUsize = AtomicUsize::new(0);
 
 // fn add_tabs() {
@@ -39,15 +77,60 @@ const fn default_value(typ: &Typ) -> &'static str {
 // 	TAB_COUNT.load(Ordering::SeqCst)
 // }
 
-impl<'a, W> Codegen<W, [Span<'a, Ast<'a>>]> for Py
+impl<'a, W> Codegen<W, Linked<'a>> for Py
 where
     W: Write,
 {
-    fn gen(&mut self, s: &[Span<'a, Ast<'a>>], buf: &mut W) -> std::io::Result<()> {
+    fn gen(&mut self, s: &Linked<'a>, buf: &mut W) -> std::io::Result<()> {
+        let mut buf = Tracked::new(buf);
         writeln!(
             buf,
             r#"# Python code generated from suslang
+import re
 import sys
+import time
+
+# Raised by `sabotage` and caught by `impostor`
+class _SusSabotage(Exception):
+	pass
+
+def _sus_excepthook(kind, value, tb):
+	if kind is _SusSabotage:
+		sys.stderr.write(f"program was ejected ({{value}})\n")
+		return
+	# Point the frames of this file back to the suslang code
+	import traceback
+	frames = []
+	for frame in traceback.extract_tb(tb):
+		if frame.filename == _sus_excepthook.__code__.co_filename and frame.lineno in _sus_lines:
+			filename, lineno = _sus_lines[frame.lineno]
+			frame = traceback.FrameSummary(filename, lineno, frame.name)
+		frames.append(frame)
+	sys.stderr.write("Traceback (most recent call last):\n")
+	sys.stderr.write("".join(traceback.format_list(frames)))
+	sys.stderr.write("".join(traceback.format_exception_only(kind, value)))
+sys.excepthook = _sus_excepthook
+
+def _sus_eject(reason):
+	sys.stderr.write(f"program was ejected ({{reason}})\n")
+	sys.exit(1)
+
+# Numbers follow semantics.rs: 32 bits wrapping around, truncated modulo
+def _sus_wrap(n):
+	return ((n + 0x80000000) & 0xFFFFFFFF) - 0x80000000
+def _sus_add(a, b):
+	return _sus_wrap(a + b)
+def _sus_sub(a, b):
+	return _sus_wrap(a - b)
+def _sus_mod(a, b):
+	if b == 0:
+		_sus_eject("division by zero")
+	r = abs(a) % abs(b)
+	return _sus_wrap(-r if a < 0 else r)
+
+def _sus_afk(ms):
+	sys.stdout.flush()
+	time.sleep(max(ms, 0) / 1000)
 
 def report(s, *args):
 	# print(repr(s), args)
@@ -63,32 +146,55 @@ def replace(s, old, new):
 	return s.replace(old, new)
 def split(s, sep):
 	return s.split(sep)
+def tostring(n):
+	return str(n)
+# Decimal digits with an optional `-` and blanks around them, anything else is a sabotage
+def tonumber(s):
+	if not re.fullmatch(r"-?[0-9]+", s.strip(" \t\n\r")):
+		raise _SusSabotage(f"not a number: {{s}}")
+	return _sus_wrap(int(s))
+def concat(a, b):
+	return a + b
 def openfile(file):
-	f = open(file)
+	try:
+		f = open(file)
+	except OSError:
+		raise _SusSabotage(f"can't open {{file}}") from None
 	l = f.readlines()
 	f.close()
 	return l
 "#
         )?;
-        for ast in s {
-            self.gen(ast, buf)?;
+        for ast in &sanitize::<Self>(&tailcall::eliminate(s)) {
+            buf.mark(ast);
+            self.gen(ast, &mut buf)?;
         }
+        write!(buf, "\n_sus_lines = {{")?;
+        for mapping in buf.mappings().to_vec() {
+            write!(
+                buf,
+                "{}: ({}, {}), ",
+                mapping.generated_line + 1,
+                json_string(&mapping.source.display().to_string()),
+                mapping.source_line
+            )?;
+        }
+        writeln!(buf, "}}")?;
         writeln!(
             buf,
             r#"
 if __name__ == "__main__":
-	sys.setrecursionlimit(1000000000)
 	ඬ()"#
         )?;
         Ok(())
     }
 }
 
-impl<'a, W> Codegen<W, Ast<'a>> for Py
+impl<'a, W> Codegen<Tracked<'a, W>, Ast<'a>> for Py
 where
     W: Write,
 {
-    fn gen(&mut self, s: &Ast, buf: &mut W) -> std::io::Result<()> {
+    fn gen(&mut self, s: &Ast<'a>, buf: &mut Tracked<'a, W>) -> std::io::Result<()> {
         // let mut var_tab_count: &usize = &0;
         match s {
             Ast::Mod(_) => Ok(()),
@@ -110,9 +216,7 @@ where
                 writeln!(buf, "):")?;
                 // var_tab_count = var_tab_count + 1;
                 self.tab_count += 1;
-                for line in &block.extra.data {
-                    self.gen(line, buf)?;
-                }
+                self.block(&block.extra.data, buf)?;
                 self.tab_count -= 1;
                 Ok(())
             }
@@ -120,11 +224,11 @@ where
     }
 }
 
-impl<'a, W> Codegen<W, Statement<'a>> for Py
+impl<'a, W> Codegen<Tracked<'a, W>, Statement<'a>> for Py
 where
     W: Write,
 {
-    fn gen(&mut self, s: &Statement, buf: &mut W) -> std::io::Result<()> {
+    fn gen(&mut self, s: &Statement<'a>, buf: &mut Tracked<'a, W>) -> std::io::Result<()> {
         match s {
             Statement::Return(n) => {
                 write!(buf, "{}return ", "\t".repeat(self.tab_count))?;
@@ -143,16 +247,12 @@ where
                 self.gen(cond, buf)?;
                 writeln!(buf, ":")?;
                 self.tab_count += 1;
-                for s in &b.extra.data {
-                    self.gen(s, buf)?;
-                }
+                self.block(&b.extra.data, buf)?;
                 // tab_count = tab_count - 1;
                 if let Some(e) = e {
                     // tab_count = tab_count + 1;
                     writeln!(buf, "{}else:", "\t".repeat(self.tab_count - 1))?;
-                    for s in &e.extra.data {
-                        self.gen(s, buf)?;
-                    }
+                    self.block(&e.extra.data, buf)?;
                 }
                 self.tab_count -= 1;
                 writeln!(buf)?;
@@ -162,12 +262,23 @@ where
                 self.gen(cond, buf)?;
                 writeln!(buf, ":")?;
                 self.tab_count += 1;
-                for s in &b.extra.data {
-                    self.gen(s, buf)?;
-                }
+                self.block(&b.extra.data, buf)?;
+                self.tab_count -= 1;
+                writeln!(buf)?;
+            }
+            Statement::For(name, start, end, b) => {
+                write!(buf, "{}for {} in range(", "\t".repeat(self.tab_count), name.extra.data)?;
+                self.gen(start, buf)?;
+                write!(buf, ", ")?;
+                self.gen(end, buf)?;
+                writeln!(buf, "):")?;
+                self.tab_count += 1;
+                self.block(&b.extra.data, buf)?;
                 self.tab_count -= 1;
                 writeln!(buf)?;
             }
+            Statement::Break => writeln!(buf, "{}break", "\t".repeat(self.tab_count))?,
+            Statement::Continue => writeln!(buf, "{}continue", "\t".repeat(self.tab_count))?,
             Statement::Declare(name, typ) => writeln!(
                 buf,
                 "{}{} = {}",
@@ -181,16 +292,47 @@ where
                 self.gen(expr, buf)?;
                 writeln!(buf)?;
             }
+            Statement::Kill(name) => {
+                writeln!(buf, "{}del {}", "\t".repeat(self.tab_count), name.extra.data)?
+            }
+            Statement::Try(body, name, _, catch) => {
+                writeln!(buf, "{}try:", "\t".repeat(self.tab_count))?;
+                self.tab_count += 1;
+                self.block(&body.extra.data, buf)?;
+                writeln!(buf, "{}except _SusSabotage as _sus_impostor:", "\t".repeat(self.tab_count - 1))?;
+                // `as` names are deleted after the `except`, so the impostor gets a copy
+                writeln!(
+                    buf,
+                    "{}{} = _sus_impostor.args[0]",
+                    "\t".repeat(self.tab_count),
+                    name.extra.data
+                )?;
+                self.block(&catch.extra.data, buf)?;
+                self.tab_count -= 1;
+                writeln!(buf)?;
+            }
+            Statement::Sabotage(reason) => {
+                write!(buf, "{}raise _SusSabotage(", "\t".repeat(self.tab_count))?;
+                self.gen(reason, buf)?;
+                writeln!(buf, ")")?;
+            }
+            Statement::Win => writeln!(buf, "{}sys.exit(0)", "\t".repeat(self.tab_count))?,
+            Statement::Lose => writeln!(buf, "{}sys.exit(1)", "\t".repeat(self.tab_count))?,
+            Statement::Afk(ms) => {
+                write!(buf, "{}_sus_afk(", "\t".repeat(self.tab_count))?;
+                self.gen(ms, buf)?;
+                writeln!(buf, ")")?;
+            }
         }
         Ok(())
     }
 }
 
-impl<'a, W> Codegen<W, Expression<'a>> for Py
+impl<'a, W> Codegen<Tracked<'a, W>, Expression<'a>> for Py
 where
     W: Write,
 {
-    fn gen(&mut self, expr: &Expression, buf: &mut W) -> std::io::Result<()> {
+    fn gen(&mut self, expr: &Expression<'a>, buf: &mut Tracked<'a, W>) -> std::io::Result<()> {
         match expr {
             Expression::Call(func, args) => {
                 write!(buf, "{}(", func.extra.data)?;
@@ -205,34 +347,46 @@ where
             }
             Expression::Operation(op, lhs, rhs) => match op.extra.data {
                 Operator::Add => {
+                    write!(buf, "_sus_add(")?;
                     self.gen(lhs.as_ref(), buf)?;
-                    write!(buf, " + ")?;
+                    write!(buf, ", ")?;
                     self.gen(rhs.as_ref(), buf)?;
+                    write!(buf, ")")?;
                 }
                 Operator::Sub => {
+                    write!(buf, "_sus_sub(")?;
                     self.gen(lhs.as_ref(), buf)?;
-                    write!(buf, " - ")?;
+                    write!(buf, ", ")?;
                     self.gen(rhs.as_ref(), buf)?;
+                    write!(buf, ")")?;
                 }
                 Operator::Mod => {
+                    write!(buf, "_sus_mod(")?;
                     self.gen(lhs.as_ref(), buf)?;
-                    write!(buf, " % ")?;
+                    write!(buf, ", ")?;
                     self.gen(rhs.as_ref(), buf)?;
+                    write!(buf, ")")?;
                 }
                 Operator::Lt => {
+                    write!(buf, "(")?;
                     self.gen(lhs.as_ref(), buf)?;
                     write!(buf, " < ")?;
                     self.gen(rhs.as_ref(), buf)?;
+                    write!(buf, ")")?;
                 }
                 Operator::GEt => {
+                    write!(buf, "(")?;
                     self.gen(lhs.as_ref(), buf)?;
                     write!(buf, " >= ")?;
                     self.gen(rhs.as_ref(), buf)?;
+                    write!(buf, ")")?;
                 }
                 Operator::Eq => {
+                    write!(buf, "(")?;
                     self.gen(lhs.as_ref(), buf)?;
                     write!(buf, " == ")?;
                     self.gen(rhs.as_ref(), buf)?;
+                    write!(buf, ")")?;
                 }
             },
             Expression::StringLit(s) => write!(buf, "{s:?}")?,
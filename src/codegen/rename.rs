@@ -0,0 +1,167 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    ast::{
+        parse::spans::{MapExt, Span},
+        visit::{fold_expression, Fold},
+        Ast, Expression,
+    },
+    linker::Linked,
+    resolve::{Resolution, SymbolKind},
+};
+
+use super::Identifiers;
+
+/// Names declared by the program: every task, argument and crewmate
+#[derive(Debug, Default)]
+struct Declared {
+    tasks: BTreeSet<String>,
+    all: BTreeSet<String>,
+}
+
+impl Declared {
+    fn collect(resolution: &Resolution) -> Self {
+        let mut declared = Self::default();
+        for (_, symbol) in resolution.symbols() {
+            match symbol.kind {
+                SymbolKind::Builtin => continue,
+                SymbolKind::Task => {
+                    declared.tasks.insert(symbol.name.clone());
+                }
+                SymbolKind::Argument | SymbolKind::Crewmate => (),
+            }
+            declared.all.insert(symbol.name.clone());
+        }
+        declared
+    }
+}
+
+struct Renames<'r> {
+    names: HashMap<String, String>,
+    resolution: &'r Resolution,
+}
+
+impl<'r> Renames<'r> {
+    fn new<I: Identifiers>(resolution: &'r Resolution) -> Self {
+        let declared = Declared::collect(resolution);
+        let mut taken = declared.all.clone();
+        let mut names = HashMap::new();
+        for name in &declared.all {
+            if name == "ඬ" && declared.tasks.contains(name) {
+                if I::ENTRY_POINT != name {
+                    names.insert(name.clone(), I::ENTRY_POINT.to_string());
+                }
+                continue;
+            }
+            let escaped = I::escape(name);
+            if escaped == *name && !I::RESERVED.contains(&name.as_str()) {
+                continue;
+            }
+            let mut new_name = escaped.into_owned();
+            while I::RESERVED.contains(&new_name.as_str())
+                || new_name == I::ENTRY_POINT
+                || taken.contains(&new_name)
+            {
+                new_name.push('_');
+            }
+            taken.insert(new_name.clone());
+            names.insert(name.clone(), new_name);
+        }
+        Self { names, resolution }
+    }
+
+    fn name(&self, name: String) -> String {
+        self.names.get(&name).cloned().unwrap_or(name)
+    }
+}
+
+/// Names that refer to the backend prelude are left untouched, even if a crewmate
+/// of the program shares them
+impl<'a> Fold<'a> for Renames<'_> {
+    fn fold_name(&mut self, name: Span<'a, String>) -> Span<'a, String> {
+        match self.resolution.symbol_at(&name) {
+            Some(symbol) if symbol.kind != SymbolKind::Builtin => name.map(|name| self.name(name)),
+            _ => name,
+        }
+    }
+
+    fn fold_expression(&mut self, expr: Span<'a, Expression<'a>>) -> Span<'a, Expression<'a>> {
+        match &expr.extra.data {
+            Expression::Variable(_) => match self.resolution.symbol_at(&expr) {
+                Some(symbol) if symbol.kind != SymbolKind::Builtin => {
+                    expr.map(|_| Expression::Variable(self.name(symbol.name.clone())))
+                }
+                _ => expr,
+            },
+            _ => fold_expression(self, expr),
+        }
+    }
+}
+
+/// Renames every identifier of a linked program that the backend `I` cannot emit verbatim,
+/// keeping declarations and references consistent
+pub fn sanitize<'a, I: Identifiers>(program: &Linked<'a>) -> Vec<Span<'a, Ast<'a>>> {
+    let mut renames = Renames::new::<I>(&program.resolution);
+    program.items.iter().cloned().map(|item| renames.fold_item(item)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::{
+            parse::{error::ParseError, items::parse_items, spans::load_file_str},
+            Ast, Statement,
+        },
+        linker::Linked,
+        resolve::resolve_items,
+    };
+
+    use super::super::Identifiers;
+
+    struct Test;
+
+    impl Identifiers for Test {
+        const RESERVED: &'static [&'static str] = &["int", "int_", "report"];
+        const ENTRY_POINT: &'static str = "main";
+    }
+
+    const TEST_DATA: &str = r#"task int with crewmate report: number ➤ number
+චcrewmate int_: numberඞ
+චmake int_ complete int with reportඞ
+චcomplete report with "%d" and reportඞ
+චeject int_ඞ
+"#;
+
+    #[test]
+    fn renames_reserved() {
+        let data = load_file_str(&"test.sus", TEST_DATA);
+        let (_, items) = parse_items::<ParseError<_>>(data).unwrap();
+        let resolution = resolve_items(&items);
+        let items = super::sanitize::<Test>(&Linked { items, resolution });
+        let Ast::Func(name, _, args, body) = &items[0].extra.data else {
+            panic!()
+        };
+        assert_eq!(name.extra.data, "int__");
+        assert_eq!(args[0].extra.data.0.extra.data, "report_");
+        let Statement::Declare(local, _) = &body.extra.data[0].extra.data else {
+            panic!()
+        };
+        assert_eq!(local.extra.data, "int___");
+        let Statement::Define(_, call) = &body.extra.data[1].extra.data else {
+            panic!()
+        };
+        assert!(matches!(
+            &call.extra.data,
+            crate::ast::Expression::Call(name, _) if name.extra.data == "int__"
+        ));
+        let Statement::Expr(report) = &body.extra.data[2].extra.data else {
+            panic!()
+        };
+        assert!(matches!(
+            &report.extra.data,
+            crate::ast::Expression::Call(name, args)
+                if name.extra.data == "report"
+                    && args[1].extra.data == crate::ast::Expression::Variable("report_".into())
+        ));
+    }
+}
@@ -1,33 +1,105 @@
 use std::io::Write;
 
-use crate::ast::{parse::spans::Span, Ast, Expression, Operator, Statement, Typ};
+use crate::{
+    ast::{self, parse::spans::Span, Ast, Expression, Operator, Statement, Typ},
+    linker::Linked,
+};
 
-use super::{Codegen, Codegeneable};
+use super::{rename::sanitize, Codegen, Codegeneable, Identifiers};
 
 pub struct Scm;
 
+impl Identifiers for Scm {
+    const RESERVED: &'static [&'static str] = &[
+        "define", "lambda", "if", "cond", "else", "begin", "let", "letrec", "set", "quote",
+        "quasiquote", "unquote", "and", "or", "not", "case", "do", "delay", "when", "unless",
+        "while", "list", "display", "newline", "exit", "void", "modulo", "remainder", "length",
+        "string", "car", "cdr", "cons", "apply", "error", "vector", "report", "sus-eject",
+        "sus-wrap", "sus-add", "sus-sub", "sus-mod", "catch", "throw", "sus-sabotage", "sus-key",
+        "usleep", "max", "force-output", "call/cc", "break", "continue", "sus-for", "sus-i",
+        "sus-end", "return", "reverse", "substring", "len", "getelement", "setelement",
+        "replace", "split", "openfile", "sus-show", "sus-index", "sus-exit", "sus-status",
+        "tostring", "tonumber", "concat",
+    ];
+}
+
 const fn default_value(typ: Typ) -> &'static str {
     match typ {
         Typ::Num => "0",
         Typ::Str => r#""""#,
-        Typ::Bool => "'false",
-        Typ::Void => "void",
+        Typ::Bool => "#f",
+        Typ::Void => "(if #f #f)",
+    }
+}
+
+/// Quotes `s` as a Scheme string literal
+fn scm_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            // Guile reads exactly two digits after `\x`
+            c if c.is_ascii_control() => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+            c => quoted.push(c),
+        }
     }
+    quoted.push('"');
+    quoted
 }
 
-pub struct Block<T>(pub T);
+/// Whether an `eject` among `statements` isn't the last thing its task does, so
+/// that it has to leave through the `return` continuation. The last statement is
+/// the last thing the task does if `tail` is.
+fn ejects_early(statements: &[Span<Statement>], tail: bool) -> bool {
+    let last = statements.len().saturating_sub(1);
+    statements.iter().enumerate().any(|(i, s)| {
+        let tail = tail && i == last;
+        match &s.extra.data {
+            Statement::Return(_) => !tail,
+            Statement::If(_, then, otherwise) => {
+                ejects_early(&then.extra.data, tail)
+                    || otherwise.as_ref().is_some_and(|b| ejects_early(&b.extra.data, tail))
+            }
+            Statement::While(_, body) | Statement::For(_, _, _, body) => {
+                ejects_early(&body.extra.data, false)
+            }
+            Statement::Try(body, _, _, catch) => {
+                ejects_early(&body.extra.data, false) || ejects_early(&catch.extra.data, false)
+            }
+            Statement::Expr(_)
+            | Statement::Declare(_, _)
+            | Statement::Define(_, _)
+            | Statement::Kill(_)
+            | Statement::Sabotage(_)
+            | Statement::Win
+            | Statement::Lose
+            | Statement::Afk(_)
+            | Statement::Break
+            | Statement::Continue => false,
+        }
+    })
+}
 
-// impl<T> From<T> for Block<T> {
-//     fn from(v: T) -> Self {
-//         Self(v)
-//     }
-// }
+/// Statements in a scope of their own, the last one being the last thing the task
+/// does if `tail` is
+struct Block<'s, 'a> {
+    statements: &'s [Span<'a, Statement<'a>>],
+    tail: bool,
+}
 
-// impl<T> From<Block<T>> for T {
-//     fn from(v: Block<T>) -> Self {
-//         v.0
-//     }
-// }
+impl<'s, 'a> Block<'s, 'a> {
+    fn new(block: &'s Span<'a, ast::Block<'a>>, tail: bool) -> Self {
+        Self {
+            statements: &block.extra.data,
+            tail,
+        }
+    }
+}
 
 fn write_eval<W>(
     operator: &str,
@@ -46,30 +118,139 @@ where
     Ok(())
 }
 
-impl<'a, W> Codegen<W, [Span<'a, Ast<'a>>]> for Scm
+impl<'a, W> Codegen<W, Linked<'a>> for Scm
 where
     W: Write,
 {
     // main one
-    fn gen(&mut self, s: &[Span<'a, Ast<'a>>], buf: &mut W) -> std::io::Result<()> {
+    fn gen(&mut self, s: &Linked<'a>, buf: &mut W) -> std::io::Result<()> {
         writeln!(
             buf,
             r#"; scheme code generated from suslang
-( define ( report f )
-    ( display f ) )"#
+; Booleans are reported as numbers, like C does
+( define ( sus-show x )
+    ( cond ( ( eq? x #t ) 1 ) ( ( eq? x #f ) 0 ) ( else x ) ) )
+; report formats like printf: `%%` is a `%`, any other `%x` shows the next argument
+( define ( report f . args )
+    ( let loop ( ( cs ( string->list f ) ) ( args args ) )
+        ( cond
+            ( ( null? cs ) #t )
+            ( ( and ( char=? ( car cs ) #\% ) ( pair? ( cdr cs ) ) )
+                ( if ( char=? ( car ( cdr cs ) ) #\% )
+                    ( begin ( display #\% ) ( loop ( cdr ( cdr cs ) ) args ) )
+                    ( begin
+                        ( display ( sus-show ( car args ) ) )
+                        ( loop ( cdr ( cdr cs ) ) ( cdr args ) ) ) ) )
+            ( else ( display ( car cs ) ) ( loop ( cdr cs ) args ) ) ) ) )
+( define ( sus-exit status )
+    ( force-output )
+    ( exit status ) )
+( define ( sus-eject reason )
+    ( force-output )
+    ( display ( string-append "program was ejected (" reason ")\n" ) ( current-error-port ) )
+    ( exit 1 ) )
+; Numbers follow semantics.rs: 32 bits wrapping around, truncated modulo
+( define ( sus-wrap n )
+    ( - ( modulo ( + n 2147483648 ) 4294967296 ) 2147483648 ) )
+( define ( sus-add a b ) ( sus-wrap ( + a b ) ) )
+( define ( sus-sub a b ) ( sus-wrap ( - a b ) ) )
+( define ( sus-mod a b )
+    ( if ( = b 0 )
+        ( sus-eject "division by zero" )
+        ( sus-wrap ( remainder a b ) ) ) )
+; Lists, made by split and openfile, are vectors
+( define ( len s )
+    ( if ( vector? s ) ( vector-length s ) ( string-length s ) ) )
+( define ( sus-index s index )
+    ( let* ( ( n ( len s ) ) ( i ( if ( < index 0 ) ( + index n ) index ) ) )
+        ( if ( or ( < i 0 ) ( >= i n ) )
+            ( sus-eject ( string-append "index " ( number->string i ) " out of range" ) )
+            i ) ) )
+( define ( getelement s index )
+    ( let ( ( i ( sus-index s index ) ) )
+        ( if ( vector? s ) ( vector-ref s i ) ( substring s i ( + i 1 ) ) ) ) )
+( define ( setelement s index value )
+    ( let ( ( i ( sus-index s index ) ) )
+        ( if ( vector? s )
+            ( let ( ( copy ( vector-copy s ) ) ) ( vector-set! copy i value ) copy )
+            ( string-append ( substring s 0 i ) value ( substring s ( + i 1 ) ) ) ) ) )
+( define ( replace s old new )
+    ( if ( string-null? old )
+        s
+        ( let loop ( ( start 0 ) ( parts '() ) )
+            ( let ( ( i ( string-contains s old start ) ) )
+                ( if i
+                    ( loop ( + i ( string-length old ) ) ( cons new ( cons ( substring s start i ) parts ) ) )
+                    ( apply string-append ( reverse ( cons ( substring s start ) parts ) ) ) ) ) ) ) )
+( define ( split s sep )
+    ( if ( string-null? sep )
+        ( sus-eject "empty separator" )
+        ( let loop ( ( start 0 ) ( items '() ) )
+            ( let ( ( i ( string-contains s sep start ) ) )
+                ( if i
+                    ( loop ( + i ( string-length sep ) ) ( cons ( substring s start i ) items ) )
+                    ( list->vector ( reverse ( cons ( substring s start ) items ) ) ) ) ) ) ) )
+( define ( tostring n ) ( number->string n ) )
+; Decimal digits with an optional `-` and blanks around them, anything else is a sabotage
+( define ( tonumber s )
+    ( let* ( ( t ( string-trim-both s ( lambda ( c ) ( memv c '( #\space #\tab #\newline #\return ) ) ) ) )
+            ( digits ( if ( string-prefix? "-" t ) ( substring t 1 ) t ) ) )
+        ( if ( and ( not ( string-null? digits ) )
+                ( string-every ( lambda ( c ) ( and ( char>=? c #\0 ) ( char<=? c #\9 ) ) ) digits ) )
+            ( sus-wrap ( string->number t 10 ) )
+            ( throw 'sus-sabotage ( string-append "not a number: " s ) ) ) ) )
+( define ( concat a b ) ( string-append a b ) )
+; Lines keep their "\n", like in the C runtime
+( define ( openfile path )
+    ( let ( ( port ( catch #t
+                ( lambda () ( open-input-file path ) )
+                ( lambda _ ( throw 'sus-sabotage ( string-append "can't open " path ) ) ) ) ) )
+        ( let loop ( ( line '() ) ( lines '() ) )
+            ( let ( ( c ( read-char port ) ) )
+                ( cond
+                    ( ( eof-object? c )
+                        ( close-port port )
+                        ( list->vector ( reverse
+                            ( if ( null? line ) lines ( cons ( list->string ( reverse line ) ) lines ) ) ) ) )
+                    ( ( char=? c #\newline )
+                        ( loop '() ( cons ( list->string ( reverse ( cons c line ) ) ) lines ) ) )
+                    ( else ( loop ( cons c line ) lines ) ) ) ) ) ) )"#
         )?;
-        for ast in s {
+        for ast in &sanitize::<Self>(s) {
             self.gen(ast, buf)?;
         }
+        // ඬ ejects the exit status, or nothing for 0
         writeln!(
             buf,
-            r#"(ඬ)
-(exit)"#
+            r#"( let ( ( sus-status ( catch 'sus-sabotage ඬ ( lambda ( sus-key reason ) ( sus-eject reason ) ) ) ) )
+    ( sus-exit ( if ( integer? sus-status ) sus-status 0 ) ) )"#
         )?; // TODO change so that everything has to be inside ඬ to keep C standard
         Ok(())
     }
 }
 
+impl Scm {
+    /// Writes `statements` as the body of a task or of a `let`, which has to end
+    /// with an expression
+    fn body<W: Write>(
+        &mut self,
+        statements: &[Span<Statement>],
+        tail: bool,
+        buf: &mut W,
+    ) -> std::io::Result<()> {
+        let last = statements.len().saturating_sub(1);
+        for (i, line) in statements.iter().enumerate() {
+            write!(buf, "\t")?;
+            self.statement(&line.extra.data, tail && i == last, buf)?;
+        }
+        let last_statement = statements.last().map(|s| &s.extra.data);
+        if matches!(last_statement, None | Some(Statement::Declare(..))) {
+            write!(buf, "\t(if #f #f)")?;
+        }
+        Ok(())
+    }
+}
+
 impl<'a, W> Codegen<W, Ast<'a>> for Scm
 where
     W: Write,
@@ -86,11 +267,16 @@ where
                 }
                 writeln!(buf, ")")?;
 
-                for line in &blocks.extra.data {
-                    write!(buf, "\t")?;
-                    self.gen(line, buf)?;
+                // an `eject` before the end leaves through a continuation, the
+                // others stay tail calls
+                if ejects_early(&blocks.extra.data, true) {
+                    writeln!(buf, "\t( call/cc ( lambda ( return ) ")?;
+                    self.body(&blocks.extra.data, true, buf)?;
+                    writeln!(buf, ") ) )")?;
+                } else {
+                    self.body(&blocks.extra.data, true, buf)?;
+                    writeln!(buf, ")")?;
                 }
-                writeln!(buf, ")")?;
             }
         }
         Ok(())
@@ -126,15 +312,13 @@ where
     }
 }
 
-impl<'a, W> Codegen<W, Block<&[Span<'a, Statement<'a>>]>> for Scm
+impl<W> Codegen<W, Block<'_, '_>> for Scm
 where
     W: Write,
 {
-    fn gen(&mut self, s: &Block<&[Span<'a, Statement<'a>>]>, buf: &mut W) -> std::io::Result<()> {
-        write!(buf, " ( begin ")?;
-        for c in s.0 {
-            c.gen(self, buf)?;
-        }
+    fn gen(&mut self, s: &Block, buf: &mut W) -> std::io::Result<()> {
+        write!(buf, " ( let () ")?;
+        self.body(s.statements, s.tail, buf)?;
         write!(buf, " ) ")?;
         Ok(())
     }
@@ -145,12 +329,26 @@ where
     W: Write,
 {
     fn gen(&mut self, s: &Statement, buf: &mut W) -> std::io::Result<()> {
+        self.statement(s, false, buf)
+    }
+}
+
+impl Scm {
+    /// Writes `s`, which is the last thing its task does if `tail` is
+    fn statement<W: Write>(
+        &mut self,
+        s: &Statement,
+        tail: bool,
+        buf: &mut W,
+    ) -> std::io::Result<()> {
         match s {
             Statement::Return(s) => {
-                if let Some(r) = s {
-                    self.gen(r, buf)?;
+                let value = Expression::Variable("(if #f #f)".into());
+                let value = s.as_ref().map_or(&value, |r| &r.extra.data);
+                if tail {
+                    self.gen(value, buf)?;
                 } else {
-                    write!(buf, "(void)")?;
+                    write_eval("return", &[value], buf)?;
                 }
             }
 
@@ -160,23 +358,28 @@ where
 
             Statement::If(cond, b, e) => {
                 if let Some(b2) = e {
-                    write_eval(
-                        "if",
-                        &[
-                            cond,
-                            &Block(b.extra.data.as_slice()),
-                            &Block(b2.extra.data.as_slice()),
-                        ],
-                        buf,
-                    )?;
+                    write_eval("if", &[cond, &Block::new(b, tail), &Block::new(b2, tail)], buf)?;
                 } else {
-                    write_eval("if", &[cond, &Block(b.extra.data.as_slice())], buf)?;
+                    write_eval("if", &[cond, &Block::new(b, tail)], buf)?;
                 }
             }
 
             Statement::While(cond, b) => {
-                write_eval("while", &[cond, &Block(b.extra.data.as_slice())], buf)?;
+                write_eval("while", &[cond, &Block::new(b, false)], buf)?;
+            }
+            // `break` and `continue` are continuations named like those `while` binds
+            Statement::For(name, start, end, b) => {
+                write!(buf, "( call/cc ( lambda ( break ) ( let sus-for ( ( sus-i ")?;
+                start.gen(self, buf)?;
+                write!(buf, " ) ( sus-end ")?;
+                end.gen(self, buf)?;
+                write!(buf, " ) ) ( when ( < sus-i sus-end ) ( call/cc ( lambda ( continue ) ")?;
+                write!(buf, "( let ( ( {} sus-i ) ) ", name.extra.data)?;
+                Block::new(b, false).gen(self, buf)?;
+                writeln!(buf, ") ) ) ( sus-for ( + sus-i 1 ) sus-end ) ) ) ) )")?;
             }
+            Statement::Break => writeln!(buf, "( break )")?,
+            Statement::Continue => writeln!(buf, "( continue )")?,
 
             Statement::Declare(name, typ) => write_eval(
                 "define",
@@ -188,7 +391,28 @@ where
             )?,
             Statement::Define(name, val) => {
                 write_eval("set!", &[&Expression::Variable(name.to_string()), val], buf)?
-            } //x => todo!("{:?}", x)
+            }
+            // nothing to free with a garbage collector
+            Statement::Kill(_) => write!(buf, "(if #f #f)")?,
+            Statement::Try(body, name, _, catch) => {
+                write!(buf, "( catch 'sus-sabotage ( lambda () ")?;
+                Block::new(body, false).gen(self, buf)?;
+                write!(buf, ") ( lambda ( sus-key {} ) ", name.extra.data)?;
+                Block::new(catch, false).gen(self, buf)?;
+                writeln!(buf, ") )")?;
+            }
+            Statement::Sabotage(reason) => {
+                let key = Expression::Variable("'sus-sabotage".into());
+                write_eval("throw", &[&key, reason], buf)?
+            }
+            Statement::Win => writeln!(buf, "( sus-exit 0 )")?,
+            Statement::Lose => writeln!(buf, "( sus-exit 1 )")?,
+            Statement::Afk(ms) => {
+                write!(buf, "( begin ( force-output ) ( usleep ( * ( max 0 ")?;
+                ms.gen(self, buf)?;
+                writeln!(buf, " ) 1000 ) ) )")?;
+            }
+            //x => todo!("{:?}", x)
         }
         Ok(())
     }
@@ -217,19 +441,20 @@ where
             }
 
             Expression::NumLit(s) => write!(buf, "{s}")?,
-            Expression::StringLit(s) => write!(buf, "{s:?}")?,
+            Expression::StringLit(s) => write!(buf, "{}", scm_string(s))?,
 
             Expression::Variable(v) => write!(buf, " {v} ")?,
 
-            Expression::BoolLit(b) => write!(buf, "{}", if *b { "'true" } else { "'false" })?,
+            Expression::BoolLit(b) => write!(buf, "{}", if *b { "#t" } else { "#f" })?,
 
             Expression::Operation(op, b1, b2) => match op.extra.data {
-                Operator::Add => write_eval("+", &[b1.as_ref(), b2.as_ref()], buf)?,
-                Operator::Sub => write_eval("-", &[b1.as_ref(), b2.as_ref()], buf)?,
-                Operator::Mod => write_eval("modulo", &[b1.as_ref(), b2.as_ref()], buf)?,
+                Operator::Add => write_eval("sus-add", &[b1.as_ref(), b2.as_ref()], buf)?,
+                Operator::Sub => write_eval("sus-sub", &[b1.as_ref(), b2.as_ref()], buf)?,
+                Operator::Mod => write_eval("sus-mod", &[b1.as_ref(), b2.as_ref()], buf)?,
                 Operator::Lt => write_eval("<", &[b1.as_ref(), b2.as_ref()], buf)?,
                 Operator::GEt => write_eval(">=", &[b1.as_ref(), b2.as_ref()], buf)?,
-                Operator::Eq => write_eval("=", &[b1.as_ref(), b2.as_ref()], buf)?,
+                // numbers, strings and booleans alike
+                Operator::Eq => write_eval("equal?", &[b1.as_ref(), b2.as_ref()], buf)?,
 
                 #[allow(unreachable_patterns)]
                 x => todo!("{:?}", x),
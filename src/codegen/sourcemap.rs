@@ -0,0 +1,216 @@
+use std::{io::Write, path::Path};
+
+use crate::ast::parse::spans::Span;
+
+/// A generated position pointing back to a place in a suslang file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mapping<'a> {
+    /// 0-based line in the generated code
+    pub generated_line: usize,
+    /// 0-based column in the generated code
+    pub generated_column: usize,
+    pub source: &'a Path,
+    pub source_contents: &'a str,
+    /// 1-based line in the suslang file, as in [`Span::location_line`]
+    pub source_line: usize,
+    /// 0-based column in the suslang file
+    pub source_column: usize,
+}
+
+/// Writer that keeps track of the line and column being written so that the
+/// backends can record where each statement came from
+pub struct Tracked<'a, W> {
+    inner: W,
+    line: usize,
+    column: usize,
+    mappings: Vec<Mapping<'a>>,
+}
+
+impl<'a, W: Write> Tracked<'a, W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            line: 0,
+            column: 0,
+            mappings: Vec::new(),
+        }
+    }
+
+    /// Records that the code written next comes from `span`
+    pub fn mark<T>(&mut self, span: &Span<'a, T>) {
+        self.mappings.push(Mapping {
+            generated_line: self.line,
+            generated_column: self.column,
+            source: span.extra.filename,
+            source_contents: span.extra.file_contents,
+            source_line: span.location_line() as usize,
+            source_column: span.get_utf8_column() - 1,
+        });
+    }
+
+    pub fn mappings(&self) -> &[Mapping<'a>] {
+        &self.mappings
+    }
+}
+
+impl<'a, W: Write> Write for Tracked<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        for b in &buf[..written] {
+            match b {
+                b'\n' => {
+                    self.line += 1;
+                    self.column = 0;
+                }
+                // UTF-8 continuation bytes don't start a new column
+                0x80..=0xBF => (),
+                _ => self.column += 1,
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Builds a [source map v3](https://sourcemaps.info/spec.html) out of `mappings`
+pub fn source_map_json(mappings: &[Mapping]) -> String {
+    let mut sources: Vec<(&Path, &str)> = Vec::new();
+    let mut encoded = String::new();
+    let mut line = 0;
+    // Every field but the generated column is relative to the previous segment in the file,
+    // the generated column is relative to the previous segment in the same line
+    let mut previous = [0i64; 4];
+    for mapping in mappings {
+        let source = match sources.iter().position(|(s, _)| *s == mapping.source) {
+            Some(i) => i,
+            None => {
+                sources.push((mapping.source, mapping.source_contents));
+                sources.len() - 1
+            }
+        };
+        if line != mapping.generated_line {
+            while line < mapping.generated_line {
+                encoded.push(';');
+                line += 1;
+            }
+            previous[0] = 0;
+        } else if !encoded.is_empty() && !encoded.ends_with(';') {
+            encoded.push(',');
+        }
+        let segment = [
+            mapping.generated_column as i64,
+            source as i64,
+            mapping.source_line as i64 - 1,
+            mapping.source_column as i64,
+        ];
+        for (value, previous) in segment.into_iter().zip(previous.iter_mut()) {
+            encode_vlq(value - *previous, &mut encoded);
+            *previous = value;
+        }
+    }
+
+    let sources_json = sources
+        .iter()
+        .map(|(path, _)| json_string(&path.display().to_string()))
+        .collect::<Vec<_>>()
+        .join(",");
+    let contents_json = sources
+        .iter()
+        .map(|(_, contents)| json_string(contents))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(
+        r#"{{"version":3,"sources":[{sources_json}],"sourcesContent":[{contents_json}],"names":[],"mappings":"{encoded}"}}"#
+    )
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn encode_vlq(value: i64, buf: &mut String) {
+    let mut value = if value < 0 {
+        ((-value) << 1) | 1
+    } else {
+        value << 1
+    };
+    loop {
+        let mut digit = value & 0b11111;
+        value >>= 5;
+        if value > 0 {
+            digit |= 0b100000;
+        }
+        buf.push(BASE64[digit as usize] as char);
+        if value == 0 {
+            break;
+        }
+    }
+}
+
+pub fn base64(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64[(n >> (18 - 6 * i)) as usize & 0b111111] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Quotes `s` as a JSON string literal, which is also valid Python and JS
+pub fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64, encode_vlq};
+
+    #[test]
+    fn vlq() {
+        let encode = |n| {
+            let mut s = String::new();
+            encode_vlq(n, &mut s);
+            s
+        };
+        assert_eq!(encode(0), "A");
+        assert_eq!(encode(1), "C");
+        assert_eq!(encode(-1), "D");
+        assert_eq!(encode(16), "gB");
+        assert_eq!(encode(123), "2H");
+    }
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b"sus"), "c3Vz");
+        assert_eq!(base64(b"amogus"), "YW1vZ3Vz");
+        assert_eq!(base64("ඞ".as_bytes()), "4Lae");
+        assert_eq!(base64(b"su"), "c3U=");
+        assert_eq!(base64(b"s"), "cw==");
+    }
+}
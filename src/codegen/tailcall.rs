@@ -0,0 +1,285 @@
+//! Self tail calls turned into loops.
+//!
+//! `eject complete f with ...` inside `f` becomes an update of the arguments of
+//! `f` followed by another turn of a loop around its body, so that recursions as
+//! deep as the `loop` of `examples/day1.sus` run in constant stack. Scheme has
+//! proper tail calls and doesn't need it.
+
+use nom::Slice;
+
+use crate::{
+    ast::{
+        parse::spans::{MapExt, Span},
+        Ast, Block, Expression, Statement, Typ,
+    },
+    linker::Linked,
+    resolve::{Resolution, Symbol, SymbolKind},
+};
+
+type Params<'a> = [Span<'a, (Span<'a, String>, Span<'a, Typ>)>];
+
+/// Empty span at the start of `span`, so that a new node doesn't share the
+/// location of an existing one in the side tables
+fn at<'a, T: Clone, U>(span: &Span<'a, T>, data: U) -> Span<'a, U> {
+    span.clone().map(|_| ()).slice(..0).map(|()| data)
+}
+
+fn declares(statements: &[Span<Statement>], name: &str) -> bool {
+    statements.iter().any(|s| match &s.extra.data {
+        Statement::If(_, then, otherwise) => {
+            declares(&then.extra.data, name)
+                || otherwise.as_ref().is_some_and(|b| declares(&b.extra.data, name))
+        }
+        Statement::While(_, body) => declares(&body.extra.data, name),
+        Statement::For(crewmate, _, _, body) => {
+            crewmate.extra.data == name || declares(&body.extra.data, name)
+        }
+        Statement::Try(body, impostor, _, catch) => {
+            declares(&body.extra.data, name)
+                || impostor.extra.data == name
+                || declares(&catch.extra.data, name)
+        }
+        Statement::Declare(declared, _) => declared.extra.data == name,
+        Statement::Return(_)
+        | Statement::Expr(_)
+        | Statement::Define(_, _)
+        | Statement::Kill(_)
+        | Statement::Sabotage(_)
+        | Statement::Win
+        | Statement::Lose
+        | Statement::Afk(_)
+        | Statement::Break
+        | Statement::Continue => false,
+    })
+}
+
+struct Task<'t, 'a> {
+    name: &'t Span<'a, String>,
+    params: &'t Params<'a>,
+    void: bool,
+    /// Where the temporaries made for the arguments are declared
+    resolution: &'t mut Resolution,
+}
+
+impl<'a> Task<'_, 'a> {
+    fn is_tail_call(&self, statement: &Statement) -> bool {
+        matches!(
+            statement,
+            Statement::Return(Some(e))
+                if matches!(&e.extra.data, Expression::Call(name, _) if name.extra.data == self.name.extra.data)
+        )
+    }
+
+    fn has_tail_call(&self, statements: &[Span<Statement>]) -> bool {
+        statements.iter().any(|s| match &s.extra.data {
+            Statement::If(_, then, otherwise) => {
+                self.has_tail_call(&then.extra.data)
+                    || otherwise.as_ref().is_some_and(|b| self.has_tail_call(&b.extra.data))
+            }
+            Statement::While(_, body) | Statement::For(_, _, _, body) => {
+                self.has_tail_call(&body.extra.data)
+            }
+            // a call in an `emergency meeting` has to come back to it, so the
+            // whole statement stays as written
+            Statement::Try(..) => false,
+            s => self.is_tail_call(s),
+        })
+    }
+
+    /// Statements giving the arguments of the task the values of `args`, which
+    /// are all computed before any argument changes
+    fn updates(&mut self, args: Vec<Span<'a, Expression<'a>>>) -> Vec<Span<'a, Statement<'a>>> {
+        if let ([param], [arg]) = (self.params, args.as_slice()) {
+            let name = param.extra.data.0.clone();
+            return vec![at(arg, Statement::Define(name, arg.clone()))];
+        }
+        let mut computed = Vec::new();
+        let mut updates = Vec::new();
+        for (param, arg) in self.params.iter().zip(args) {
+            let (name, typ) = &param.extra.data;
+            // `ඞ` can't be part of a name in suslang, so nothing can shadow it
+            let here = at(&arg, ());
+            let temp = here.map(|()| format!("ඞ{}", name.extra.data));
+            let param = self.resolution.symbol_at(name).expect("linked programs are resolved");
+            let symbol = Symbol {
+                name: temp.extra.data.clone(),
+                kind: SymbolKind::Crewmate,
+                module: param.module.clone(),
+            };
+            self.resolution.declare(&temp, symbol);
+            let variable = temp.clone().map(Expression::Variable);
+            computed.push(here.map(|()| Statement::Declare(temp.clone(), *typ)));
+            computed.push(here.map(|()| Statement::Define(temp, arg)));
+            updates.push(here.map(|()| Statement::Define(name.clone(), variable)));
+        }
+        computed.append(&mut updates);
+        computed
+    }
+
+    /// Rewrites `statements` into the body of the loop, where every path either
+    /// ejects or updates the arguments for the next turn. `None` if a task that
+    /// returns a value could run off its end.
+    fn body(&mut self, statements: Vec<Span<'a, Statement<'a>>>) -> Option<Vec<Span<'a, Statement<'a>>>> {
+        let mut body = Vec::new();
+        let mut statements = statements.into_iter();
+        while let Some(statement) = statements.next() {
+            match &statement.extra.data {
+                s if self.is_tail_call(s) => {
+                    let Statement::Return(Some(call)) = statement.extra.data else {
+                        unreachable!("checked by is_tail_call")
+                    };
+                    let Expression::Call(_, args) = call.extra.data else {
+                        unreachable!("checked by is_tail_call")
+                    };
+                    body.extend(self.updates(args));
+                    return Some(body);
+                }
+                Statement::Return(_) => {
+                    body.push(statement);
+                    return Some(body);
+                }
+                // the next turn would have to leave the inner loop first
+                Statement::While(_, inner) | Statement::For(_, _, _, inner)
+                    if self.has_tail_call(&inner.extra.data) =>
+                {
+                    return None
+                }
+                Statement::If(_, _, _) if self.has_tail_call(std::slice::from_ref(&statement)) => {
+                    // what follows the `if` moves into the branches that reach it
+                    let rest: Vec<_> = statements.collect();
+                    let empty = at(&statement, Vec::new());
+                    let statement = statement.map(|s| {
+                        let Statement::If(cond, then, otherwise) = s else {
+                            unreachable!("matched above")
+                        };
+                        let then = self.branch(then, &rest)?;
+                        let otherwise = self.branch(otherwise.unwrap_or(empty), &rest)?;
+                        Some(Statement::If(cond, then, Some(otherwise)))
+                    });
+                    statement.extra.data.as_ref()?;
+                    body.push(statement.map(|s| s.expect("checked above")));
+                    return Some(body);
+                }
+                _ => body.push(statement),
+            }
+        }
+        self.void.then(|| {
+            body.push(at(self.name, Statement::Return(None)));
+            body
+        })
+    }
+
+    fn branch(&mut self, block: Span<'a, Block<'a>>, rest: &[Span<'a, Statement<'a>>]) -> Option<Span<'a, Block<'a>>> {
+        let block = block.map(|mut statements| {
+            if !matches!(statements.last().map(|s| &s.extra.data), Some(Statement::Return(_))) {
+                statements.extend(rest.iter().cloned());
+            }
+            self.body(statements)
+        });
+        block.extra.data.as_ref()?;
+        Some(block.map(|s| s.expect("checked above")))
+    }
+}
+
+/// Puts the body of every task that ejects a call to itself in a loop
+pub fn eliminate<'a>(program: &Linked<'a>) -> Linked<'a> {
+    let mut resolution = program.resolution.clone();
+    let items = program
+        .items
+        .iter()
+        .cloned()
+        .map(|item| {
+            item.map(|item| match item {
+                Ast::Func(name, ret, params, body) => {
+                    let mut task = Task {
+                        name: &name,
+                        params: &params,
+                        void: ret.extra.data == Typ::Void,
+                        resolution: &mut resolution,
+                    };
+                    let shadowed = params
+                        .iter()
+                        .any(|p| declares(&body.extra.data, &p.extra.data.0.extra.data));
+                    let looped = (!shadowed && task.has_tail_call(&body.extra.data))
+                        .then(|| task.body(body.extra.data.clone()))
+                        .flatten();
+                    let body = match looped {
+                        Some(statements) => {
+                            let cond = at(&name, Expression::BoolLit(true));
+                            let inner = at(&name, statements);
+                            body.map(|_| vec![at(&name, Statement::While(cond, inner))])
+                        }
+                        None => body,
+                    };
+                    Ast::Func(name, ret, params, body)
+                }
+                x @ (Ast::Mod(_) | Ast::Import(_)) => x,
+            })
+        })
+        .collect();
+    Linked { items, resolution }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::{parse::spans::Span, Ast, Statement},
+        linker::Linked,
+        parse_str,
+        resolve::resolve_items,
+    };
+
+    fn eliminate(program: &str) -> Vec<Span<'_, Ast<'_>>> {
+        let items = parse_str(program).unwrap();
+        let resolution = resolve_items(&items);
+        super::eliminate(&Linked { items, resolution }).items
+    }
+
+    fn looped(program: &str) -> Vec<Statement<'_>> {
+        let ast = eliminate(program);
+        let Ast::Func(_, _, _, body) = &ast[0].extra.data else {
+            panic!()
+        };
+        let [line] = body.extra.data.as_slice() else {
+            panic!("{body:?}")
+        };
+        let Statement::While(_, body) = &line.extra.data else {
+            panic!("{line:?}")
+        };
+        body.extra.data.iter().map(|s| s.extra.data.clone()).collect()
+    }
+
+    #[test]
+    fn tail_calls_become_loops() {
+        let body = looped("task count with crewmate n: number and crewmate total: number ➤ number\nචsus? < n 1\nචචeject totalඞ\nචeject complete count with - n 1 and + total nඞ\n");
+        // the `if`, then two temporaries computed and made and the two arguments updated
+        assert_eq!(body.len(), 7, "{body:?}");
+        assert!(matches!(body[0], Statement::If(_, _, None)));
+        assert!(body[1..].iter().all(|s| matches!(s, Statement::Declare(_, _) | Statement::Define(_, _))));
+    }
+
+    #[test]
+    fn code_after_a_tail_call_moves_to_the_other_branch() {
+        let body = looped("task down with crewmate n: number ➤ number\nචsus? >= n 1\nචචeject complete down with - n 1ඞ\nචeject nඞ\n");
+        let [Statement::If(_, then, Some(otherwise))] = body.as_slice() else {
+            panic!("{body:?}")
+        };
+        assert!(matches!(then.extra.data.as_slice(), [s] if matches!(s.extra.data, Statement::Define(_, _))));
+        assert!(matches!(otherwise.extra.data.as_slice(), [s] if matches!(s.extra.data, Statement::Return(Some(_)))));
+    }
+
+    #[test]
+    fn other_calls_are_left_alone() {
+        let ast = eliminate("task f with crewmate n: number ➤ number\nචeject + 1 complete f with nඞ\n");
+        let Ast::Func(_, _, _, body) = &ast[0].extra.data else {
+            panic!()
+        };
+        assert!(matches!(body.extra.data[0].extra.data, Statement::Return(Some(_))));
+
+        let ast = eliminate("task f with crewmate n: number ➤ number\nචemergency meeting\nචචeject complete f with nඞ\nචimpostor red: string\nචචeject nඞ\n");
+        let Ast::Func(_, _, _, body) = &ast[0].extra.data else {
+            panic!()
+        };
+        assert!(matches!(body.extra.data[0].extra.data, Statement::Try(..)));
+    }
+}
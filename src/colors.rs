@@ -0,0 +1,71 @@
+//! Names that crewmates and arguments may have under the
+//! [`crate::lint::Lint::NonColorName`] lint, since "solo se pueden usar colores".
+
+use std::collections::BTreeSet;
+
+use crate::suggest;
+
+const BUNDLED: [&str; 2] = [include_str!("../colors.txt"), include_str!("../css-colors.txt")];
+
+/// Dictionary of colors, `colors.txt` and `css-colors.txt` unless extended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Colors {
+    names: BTreeSet<String>,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        let mut colors = Self {
+            names: BTreeSet::new(),
+        };
+        for list in BUNDLED {
+            colors.extend(list.lines());
+        }
+        colors
+    }
+}
+
+/// Colors are compared without case or underscores, so `light_blue` is `lightblue`
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|&c| c != '_')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+impl Colors {
+    /// Adds names, one per item, ignoring blank ones
+    pub fn extend<'n>(&mut self, names: impl IntoIterator<Item = &'n str>) {
+        self.names.extend(
+            names
+                .into_iter()
+                .map(normalize)
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty()),
+        );
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.contains(&normalize(name))
+    }
+
+    /// Color that `name` is most likely a typo of
+    pub fn closest(&self, name: &str) -> Option<&str> {
+        suggest::closest(&normalize(name), self.names.iter().map(String::as_str))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Colors;
+
+    #[test]
+    fn bundled_and_extended() {
+        let mut colors = Colors::default();
+        assert!(colors.contains("red") && colors.contains("aliceblue") && colors.contains("Light_Blue"));
+        assert!(!colors.contains("n"));
+        assert_eq!(colors.closest("gren"), Some("green"));
+        colors.extend("\nimpostor\n".lines());
+        assert!(colors.contains("impostor"));
+    }
+}
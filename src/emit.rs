@@ -0,0 +1,339 @@
+//! JSON forms of the stages of the compiler, so that other tools can read what
+//! the compiler sees without linking to it.
+
+use serde::Serialize;
+
+use crate::{
+    ast::{parse::spans::Span, Ast, Block, Expression, Operator, Typ},
+    module::Module,
+    typecheck::{ExprTypes, Type},
+};
+
+/// Stages that can be emitted with `build --emit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Items of every module as parsed
+    Ast,
+    /// The single list of items given to the backends
+    Linked,
+    /// Parsed items with the type of every expression
+    Types,
+    /// The linked program lowered to [`crate::mir`], written as text
+    Ir,
+}
+
+impl std::str::FromStr for Stage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ast" => Ok(Self::Ast),
+            "linked" => Ok(Self::Linked),
+            "types" => Ok(Self::Types),
+            "ir" => Ok(Self::Ir),
+            _ => Err(format!("unknown stage `{s}`, expected ast, linked, types or ir")),
+        }
+    }
+}
+
+/// A node along with where it comes from
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Spanned<T> {
+    pub file: String,
+    /// Byte offset in the file
+    pub offset: usize,
+    pub line: u32,
+    /// 1-based column, in chars
+    pub column: usize,
+    /// Length in bytes
+    pub length: usize,
+    /// Type of the node, only present for expressions in [`Stage::Types`]
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub typ: Option<Type>,
+    pub node: T,
+}
+
+impl<T> Spanned<T> {
+    fn new<D>(span: &Span<D>, node: T) -> Self {
+        Self {
+            file: span.extra.filename.display().to_string(),
+            offset: span.location_offset(),
+            line: span.location_line(),
+            column: span.get_utf8_column(),
+            length: span.len(),
+            typ: None,
+            node,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Item {
+    Func {
+        name: Spanned<String>,
+        returns: Spanned<Typ>,
+        args: Vec<Spanned<Argument>>,
+        body: Box<Spanned<Vec<Spanned<Statement>>>>,
+    },
+    Mod {
+        name: Spanned<String>,
+    },
+    Import {
+        path: Spanned<Vec<String>>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Argument {
+    pub name: Spanned<String>,
+    #[serde(rename = "type")]
+    pub typ: Spanned<Typ>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Statement {
+    If {
+        condition: Spanned<Expr>,
+        then: Spanned<Vec<Spanned<Self>>>,
+        #[serde(rename = "else")]
+        otherwise: Option<Spanned<Vec<Spanned<Self>>>>,
+    },
+    While {
+        condition: Spanned<Expr>,
+        body: Spanned<Vec<Spanned<Self>>>,
+    },
+    For {
+        crewmate: Spanned<String>,
+        start: Spanned<Expr>,
+        end: Spanned<Expr>,
+        body: Box<Spanned<Vec<Spanned<Self>>>>,
+    },
+    Break,
+    Continue,
+    Return {
+        value: Option<Spanned<Expr>>,
+    },
+    Expr {
+        expr: Spanned<Expr>,
+    },
+    Declare {
+        name: Spanned<String>,
+        #[serde(rename = "type")]
+        typ: Spanned<Typ>,
+    },
+    Define {
+        name: Spanned<String>,
+        value: Spanned<Expr>,
+    },
+    Kill {
+        name: Spanned<String>,
+    },
+    Try {
+        body: Spanned<Vec<Spanned<Self>>>,
+        impostor: Spanned<String>,
+        #[serde(rename = "type")]
+        typ: Spanned<Typ>,
+        catch: Spanned<Vec<Spanned<Self>>>,
+    },
+    Sabotage {
+        reason: Spanned<Expr>,
+    },
+    Win,
+    Lose,
+    Afk {
+        ms: Spanned<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Expr {
+    Call {
+        name: Spanned<String>,
+        args: Vec<Spanned<Self>>,
+    },
+    Operation {
+        operator: Spanned<Operator>,
+        lhs: Box<Spanned<Self>>,
+        rhs: Box<Spanned<Self>>,
+    },
+    StringLit(String),
+    NumLit(i32),
+    BoolLit(bool),
+    Variable(String),
+}
+
+/// Turns the AST into its serializable form, annotating expressions with `types` if given
+struct Converter<'t> {
+    types: Option<&'t ExprTypes>,
+}
+
+impl Converter<'_> {
+    fn copy<T: Clone>(&self, span: &Span<T>) -> Spanned<T> {
+        Spanned::new(span, span.extra.data.clone())
+    }
+
+    fn item(&self, item: &Span<Ast>) -> Spanned<Item> {
+        let node = match &item.extra.data {
+            Ast::Func(name, returns, args, body) => Item::Func {
+                name: self.copy(name),
+                returns: self.copy(returns),
+                args: args
+                    .iter()
+                    .map(|arg| {
+                        let (name, typ) = &arg.extra.data;
+                        Spanned::new(
+                            arg,
+                            Argument {
+                                name: self.copy(name),
+                                typ: self.copy(typ),
+                            },
+                        )
+                    })
+                    .collect(),
+                body: Box::new(self.block(body)),
+            },
+            Ast::Mod(name) => Item::Mod {
+                name: self.copy(name),
+            },
+            Ast::Import(path) => Item::Import {
+                path: self.copy(path),
+            },
+        };
+        Spanned::new(item, node)
+    }
+
+    fn block(&self, block: &Span<Block>) -> Spanned<Vec<Spanned<Statement>>> {
+        Spanned::new(
+            block,
+            block
+                .extra
+                .data
+                .iter()
+                .map(|line| self.statement(line))
+                .collect(),
+        )
+    }
+
+    fn statement(&self, line: &Span<crate::ast::Statement>) -> Spanned<Statement> {
+        use crate::ast::Statement as S;
+        let node = match &line.extra.data {
+            S::If(condition, then, otherwise) => Statement::If {
+                condition: self.expression(condition),
+                then: self.block(then),
+                otherwise: otherwise.as_ref().map(|x| self.block(x)),
+            },
+            S::While(condition, body) => Statement::While {
+                condition: self.expression(condition),
+                body: self.block(body),
+            },
+            S::For(crewmate, start, end, body) => Statement::For {
+                crewmate: self.copy(crewmate),
+                start: self.expression(start),
+                end: self.expression(end),
+                body: Box::new(self.block(body)),
+            },
+            S::Break => Statement::Break,
+            S::Continue => Statement::Continue,
+            S::Return(value) => Statement::Return {
+                value: value.as_ref().map(|x| self.expression(x)),
+            },
+            S::Expr(expr) => Statement::Expr {
+                expr: self.expression(expr),
+            },
+            S::Declare(name, typ) => Statement::Declare {
+                name: self.copy(name),
+                typ: self.copy(typ),
+            },
+            S::Define(name, value) => Statement::Define {
+                name: self.copy(name),
+                value: self.expression(value),
+            },
+            S::Kill(name) => Statement::Kill {
+                name: self.copy(name),
+            },
+            S::Try(body, impostor, typ, catch) => Statement::Try {
+                body: self.block(body),
+                impostor: self.copy(impostor),
+                typ: self.copy(typ),
+                catch: self.block(catch),
+            },
+            S::Sabotage(reason) => Statement::Sabotage {
+                reason: self.expression(reason),
+            },
+            S::Win => Statement::Win,
+            S::Lose => Statement::Lose,
+            S::Afk(ms) => Statement::Afk {
+                ms: self.expression(ms),
+            },
+        };
+        Spanned::new(line, node)
+    }
+
+    fn expression(&self, expr: &Span<Expression>) -> Spanned<Expr> {
+        let node = match &expr.extra.data {
+            Expression::Call(name, args) => Expr::Call {
+                name: self.copy(name),
+                args: args.iter().map(|x| self.expression(x)).collect(),
+            },
+            Expression::Operation(operator, lhs, rhs) => Expr::Operation {
+                operator: self.copy(operator),
+                lhs: Box::new(self.expression(lhs)),
+                rhs: Box::new(self.expression(rhs)),
+            },
+            Expression::StringLit(s) => Expr::StringLit(s.clone()),
+            Expression::NumLit(n) => Expr::NumLit(*n),
+            Expression::BoolLit(b) => Expr::BoolLit(*b),
+            Expression::Variable(name) => Expr::Variable(name.clone()),
+        };
+        Spanned {
+            typ: self.types.and_then(|types| types.get(expr)).cloned(),
+            ..Spanned::new(expr, node)
+        }
+    }
+}
+
+/// Items of a single module
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModuleItems {
+    /// Path used to `vent` from the module, empty for the main one
+    pub path: Vec<String>,
+    pub items: Vec<Spanned<Item>>,
+}
+
+/// Every module of `module_graph`, with the types of the expressions if `types` is given
+pub fn modules(module_graph: &Module, types: Option<&ExprTypes>) -> Vec<ModuleItems> {
+    let converter = Converter { types };
+    module_graph
+        .iter()
+        .map(|module| ModuleItems {
+            path: module.path.clone(),
+            items: module.items.iter().map(|item| converter.item(item)).collect(),
+        })
+        .collect()
+}
+
+/// Serializable form of a list of items, like the one made by the linker
+pub fn items(items: &[Span<Ast>]) -> Vec<Spanned<Item>> {
+    let converter = Converter { types: None };
+    items.iter().map(|item| converter.item(item)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse_str;
+
+    #[test]
+    fn spans_and_names() {
+        let ast = parse_str("task ඬ with ➤ number\nචeject + 1 2ඞ\n").unwrap();
+        let json = serde_json::to_value(super::items(&ast)).unwrap();
+        let func = &json[0]["node"]["Func"];
+        assert_eq!(func["name"]["node"], "ඬ");
+        assert_eq!(func["returns"]["node"], "Num");
+        let eject = &func["body"]["node"][0];
+        assert_eq!(eject["line"], 2);
+        assert_eq!(eject["file"], "<input>");
+        let value = &eject["node"]["Return"]["value"];
+        assert_eq!(value["node"]["Operation"]["operator"]["node"], "Add");
+        assert_eq!(value["node"]["Operation"]["rhs"]["node"]["NumLit"], 2);
+    }
+}
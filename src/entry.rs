@@ -0,0 +1,91 @@
+//! The `ඬ` task every program starts at.
+
+use std::path::Path;
+
+use crate::{
+    ast::{parse::spans::Span, Ast, Typ},
+    error::{DuplicateEntryPoint, EntryPointInSubRoom, InvalidEntryPoint, MissingEntryPoint, TypeCheckError},
+    module::Module,
+};
+
+const NAME: &str = "ඬ";
+
+fn entry_points<'m, 'a>(module: &'m Module<'a>) -> impl Iterator<Item = &'m Span<'a, Ast<'a>>> {
+    module.items.iter().filter(|item| {
+        matches!(&item.extra.data, Ast::Func(name, ..) if name.extra.data == NAME)
+    })
+}
+
+/// Checks that the main file of the module graph, found at `main`, defines `ඬ`
+/// once, without arguments and returning a number or nothing, and that no room
+/// defines it
+pub fn check(module_graph: &Module, main: &Path) -> Vec<TypeCheckError> {
+    let mut errors = Vec::new();
+    let mut first: Option<&Span<String>> = None;
+    for item in entry_points(module_graph) {
+        let Ast::Func(name, ret, params, _) = &item.extra.data else {
+            unreachable!("filtered by entry_points")
+        };
+        match first {
+            Some(first) => errors.push(DuplicateEntryPoint::new(first, name).into()),
+            None => first = Some(name),
+        }
+        if let (Some(first), Some(last)) = (params.first(), params.last()) {
+            errors.push(InvalidEntryPoint::new(first, last, "can't take arguments").into());
+        }
+        if !matches!(ret.extra.data, Typ::Num | Typ::Void) {
+            errors.push(InvalidEntryPoint::new(ret, ret, "has to eject a number or nothing").into());
+        }
+    }
+    if first.is_none() {
+        errors.push(MissingEntryPoint::new(main).into());
+    }
+    for room in module_graph.iter().skip(1) {
+        for item in entry_points(room) {
+            if let Ast::Func(name, ..) = &item.extra.data {
+                errors.push(EntryPointInSubRoom::from(name.clone()).into());
+            }
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{error::TypeCheckError, session::tests::errors, Diagnostic};
+
+    fn check(main: &str, room: &str) -> Vec<Diagnostic> {
+        errors(&format!("room libඞ\n{main}"), &[("lib.sus", room)])
+    }
+
+    #[test]
+    fn one_entry_point_in_the_main_file() {
+        assert!(check("task ඬ with ➤ number\nචeject 0ඞ\n", "").is_empty());
+        assert!(check("task ඬ with ➤ void\nචcomplete report with \"hi\"ඞ\n", "").is_empty());
+        assert!(matches!(
+            check("task f with ➤ number\nචeject 0ඞ\n", "")[..],
+            [Diagnostic::TypeCheck(TypeCheckError::MissingEntryPoint(_))]
+        ));
+        assert!(matches!(
+            check("task ඬ with ➤ number\nචeject 0ඞ\ntask ඬ with ➤ number\nචeject 1ඞ\n", "")[..],
+            [Diagnostic::TypeCheck(TypeCheckError::DuplicateEntryPoint(_))]
+        ));
+        assert!(matches!(
+            check("task ඬ with ➤ number\nචeject 0ඞ\n", "task ඬ with ➤ number\nචeject 1ඞ\n")[..],
+            [Diagnostic::TypeCheck(TypeCheckError::EntryPointInSubRoom(_))]
+        ));
+    }
+
+    #[test]
+    fn entry_point_signature() {
+        let errors = check("task ඬ with crewmate n: number ➤ string\nචeject \"a\"ඞ\n", "");
+        let messages: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            [
+                "Task `ඬ` can't take arguments",
+                "Task `ඬ` has to eject a number or nothing"
+            ]
+        );
+    }
+}
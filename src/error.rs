@@ -1,7 +1,12 @@
-use miette::{Diagnostic, NamedSource, SourceSpan};
+use miette::{Diagnostic, LabeledSpan, NamedSource, Severity, SourceSpan};
+use nom_supreme::error::GenericErrorTree;
 use thiserror::Error;
 
-use crate::{ast::parse::spans::Span, typecheck};
+use crate::{
+    ast::parse::{error::ParseError, spans::Span},
+    lint::Lint,
+    typecheck,
+};
 
 #[derive(Error, Debug, Diagnostic)]
 pub enum TypeCheckError {
@@ -23,6 +28,45 @@ pub enum TypeCheckError {
     #[error(transparent)]
     #[diagnostic(transparent)]
     FunctionArgumentTypeError(#[from] FunctionArgumentTypeError),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    VariableNotFound(#[from] VariableNotFound),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    NotCallable(#[from] NotCallable),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ReportFormatError(#[from] ReportFormatError),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    MissingEject(#[from] MissingEject),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    UseBeforeMake(#[from] UseBeforeMake),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    UseAfterKill(#[from] UseAfterKill),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    DoubleKill(#[from] DoubleKill),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    NotKillable(#[from] NotKillable),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    LoopControlOutsideLoop(#[from] LoopControlOutsideLoop),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    MissingEntryPoint(#[from] MissingEntryPoint),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    DuplicateEntryPoint(#[from] DuplicateEntryPoint),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    EntryPointInSubRoom(#[from] EntryPointInSubRoom),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    InvalidEntryPoint(#[from] InvalidEntryPoint),
 }
 
 #[derive(Error, Debug, Diagnostic)]
@@ -33,6 +77,8 @@ pub struct ModuleNotFoundError {
     src: NamedSource,
     #[label]
     bad_bit: SourceSpan,
+    #[help]
+    help: Option<String>,
 }
 
 impl<'a> From<Span<'a>> for ModuleNotFoundError {
@@ -43,10 +89,19 @@ impl<'a> From<Span<'a>> for ModuleNotFoundError {
                 value.extra.file_contents.to_string(),
             ),
             bad_bit: (value.location_offset(), value.len()).into(),
+            help: None,
         }
     }
 }
 
+impl ModuleNotFoundError {
+    /// Adds what the name most likely was
+    pub fn with_help(mut self, help: Option<String>) -> Self {
+        self.help = help;
+        self
+    }
+}
+
 #[derive(Error, Debug, Diagnostic)]
 #[error("Item `{item}` not found")]
 #[diagnostic(code(suslang::item::not_found), url(docsrs), severity = "error")]
@@ -55,6 +110,8 @@ pub struct ItemNotFound {
     src: NamedSource,
     #[label]
     bad_bit: SourceSpan,
+    #[help]
+    help: Option<String>,
     item: String,
 }
 
@@ -66,11 +123,20 @@ impl<'a> From<Span<'a, &str>> for ItemNotFound {
                 value.extra.file_contents.to_string(),
             ),
             bad_bit: (value.location_offset(), value.len()).into(),
+            help: None,
             item: value.extra.data.to_string(),
         }
     }
 }
 
+impl ItemNotFound {
+    /// Adds what the name most likely was
+    pub fn with_help(mut self, help: Option<String>) -> Self {
+        self.help = help;
+        self
+    }
+}
+
 #[derive(Error, Debug, Diagnostic)]
 #[error("Expected type {type_found:?} on expression {type_expected:?}")]
 #[diagnostic(code(suslang::expression::type_error), url(docsrs))]
@@ -105,6 +171,8 @@ pub struct FunctionNotFound {
     src: NamedSource,
     #[label]
     bad_bit: SourceSpan,
+    #[help]
+    help: Option<String>,
     item: String,
 }
 
@@ -116,11 +184,20 @@ impl<'a> From<Span<'a, String>> for FunctionNotFound {
                 value.extra.file_contents.to_string(),
             ),
             bad_bit: (value.location_offset(), value.len()).into(),
+            help: None,
             item: value.extra.data,
         }
     }
 }
 
+impl FunctionNotFound {
+    /// Adds what the name most likely was
+    pub fn with_help(mut self, help: Option<String>) -> Self {
+        self.help = help;
+        self
+    }
+}
+
 #[derive(Error, Debug, Diagnostic)]
 #[error("Function `{item}` expected {expected} argmuents but found {found} not found")]
 #[diagnostic(code(suslang::function::argument::number), url(docsrs), severity = "error")]
@@ -157,3 +234,509 @@ pub struct FunctionArgumentTypeError {
     pub related : Vec<ExpressionTypeError>,
     pub function_name: String,
 }
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("Crewmate `{item}` not declared")]
+#[diagnostic(code(suslang::variable::not_found), url(docsrs), severity = "error")]
+pub struct VariableNotFound {
+    #[source_code]
+    src: NamedSource,
+    #[label]
+    bad_bit: SourceSpan,
+    #[help]
+    help: Option<String>,
+    item: String,
+}
+
+impl<'a> From<Span<'a, String>> for VariableNotFound {
+    fn from(value: Span<'a, String>) -> Self {
+        Self {
+            src: NamedSource::new(
+                value.extra.filename.display().to_string(),
+                value.extra.file_contents.to_string(),
+            ),
+            bad_bit: (value.location_offset(), value.len()).into(),
+            help: None,
+            item: value.extra.data,
+        }
+    }
+}
+
+impl VariableNotFound {
+    /// Adds what the name most likely was
+    pub fn with_help(mut self, help: Option<String>) -> Self {
+        self.help = help;
+        self
+    }
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("`{item}` is not a task")]
+#[diagnostic(code(suslang::function::not_callable), url(docsrs), severity = "error")]
+pub struct NotCallable {
+    #[source_code]
+    src: NamedSource,
+    #[label]
+    bad_bit: SourceSpan,
+    item: String,
+}
+
+impl<'a> From<Span<'a, String>> for NotCallable {
+    fn from(value: Span<'a, String>) -> Self {
+        Self {
+            src: NamedSource::new(
+                value.extra.filename.display().to_string(),
+                value.extra.file_contents.to_string(),
+            ),
+            bad_bit: (value.location_offset(), value.len()).into(),
+            item: value.extra.data,
+        }
+    }
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("Invalid report: {reason}")]
+#[diagnostic(code(suslang::report::format), url(docsrs), severity = "error")]
+pub struct ReportFormatError {
+    #[source_code]
+    src: NamedSource,
+    #[label]
+    bad_bit: SourceSpan,
+    reason: String,
+}
+
+impl<'a> From<Span<'a, String>> for ReportFormatError {
+    fn from(value: Span<'a, String>) -> Self {
+        Self {
+            src: NamedSource::new(
+                value.extra.filename.display().to_string(),
+                value.extra.file_contents.to_string(),
+            ),
+            bad_bit: (value.location_offset(), value.len()).into(),
+            reason: value.extra.data,
+        }
+    }
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("Task `{task}` may finish without eject")]
+#[diagnostic(
+    code(suslang::flow::missing_eject),
+    url(docsrs),
+    severity = "error",
+    help("every path through a task that returns a value has to eject one")
+)]
+pub struct MissingEject {
+    #[source_code]
+    src: NamedSource,
+    #[label]
+    bad_bit: SourceSpan,
+    task: String,
+}
+
+impl<'a> From<Span<'a, String>> for MissingEject {
+    fn from(value: Span<'a, String>) -> Self {
+        Self {
+            src: NamedSource::new(
+                value.extra.filename.display().to_string(),
+                value.extra.file_contents.to_string(),
+            ),
+            bad_bit: (value.location_offset(), value.len()).into(),
+            task: value.extra.data,
+        }
+    }
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("Crewmate `{item}` may be used before it is made")]
+#[diagnostic(
+    code(suslang::variable::use_before_make),
+    url(docsrs),
+    severity = "error",
+    help("`make` it on every path that leads here")
+)]
+pub struct UseBeforeMake {
+    #[source_code]
+    src: NamedSource,
+    #[label("declared here")]
+    declaration: SourceSpan,
+    #[label("used here")]
+    bad_bit: SourceSpan,
+    item: String,
+}
+
+impl UseBeforeMake {
+    pub fn new<T>(declaration: &Span<String>, used: &Span<T>) -> Self {
+        Self {
+            src: NamedSource::new(
+                used.extra.filename.display().to_string(),
+                used.extra.file_contents.to_string(),
+            ),
+            declaration: (declaration.location_offset(), declaration.len()).into(),
+            bad_bit: (used.location_offset(), used.len()).into(),
+            item: declaration.extra.data.clone(),
+        }
+    }
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("Crewmate `{item}` may be dead here")]
+#[diagnostic(
+    code(suslang::variable::use_after_kill),
+    url(docsrs),
+    severity = "error",
+    help("dead body reported, `make` it again before using it")
+)]
+pub struct UseAfterKill {
+    #[source_code]
+    src: NamedSource,
+    #[label("killed here")]
+    kill: SourceSpan,
+    #[label("used here")]
+    bad_bit: SourceSpan,
+    item: String,
+}
+
+impl UseAfterKill {
+    pub fn new<T>(kill: &Span<String>, used: &Span<T>) -> Self {
+        Self {
+            src: NamedSource::new(
+                used.extra.filename.display().to_string(),
+                used.extra.file_contents.to_string(),
+            ),
+            kill: (kill.location_offset(), kill.len()).into(),
+            bad_bit: (used.location_offset(), used.len()).into(),
+            item: kill.extra.data.clone(),
+        }
+    }
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("stop! `{item}` is already dead")]
+#[diagnostic(code(suslang::variable::double_kill), url(docsrs), severity = "error")]
+pub struct DoubleKill {
+    #[source_code]
+    src: NamedSource,
+    #[label("killed here")]
+    first: SourceSpan,
+    #[label("killed again here")]
+    bad_bit: SourceSpan,
+    item: String,
+}
+
+impl DoubleKill {
+    pub fn new(first: &Span<String>, again: &Span<String>) -> Self {
+        Self {
+            src: NamedSource::new(
+                again.extra.filename.display().to_string(),
+                again.extra.file_contents.to_string(),
+            ),
+            first: (first.location_offset(), first.len()).into(),
+            bad_bit: (again.location_offset(), again.len()).into(),
+            item: again.extra.data.clone(),
+        }
+    }
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("`{item}` is not a crewmate, only crewmates can be killed")]
+#[diagnostic(code(suslang::variable::not_killable), url(docsrs), severity = "error")]
+pub struct NotKillable {
+    #[source_code]
+    src: NamedSource,
+    #[label]
+    bad_bit: SourceSpan,
+    item: String,
+}
+
+impl<'a> From<Span<'a, String>> for NotKillable {
+    fn from(value: Span<'a, String>) -> Self {
+        Self {
+            src: NamedSource::new(
+                value.extra.filename.display().to_string(),
+                value.extra.file_contents.to_string(),
+            ),
+            bad_bit: (value.location_offset(), value.len()).into(),
+            item: value.extra.data,
+        }
+    }
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("`{item}` outside of a loop")]
+#[diagnostic(
+    code(suslang::statement::outside_loop),
+    url(docsrs),
+    severity = "error",
+    help("`break` and `continue` only work inside a `while` or `for` block")
+)]
+pub struct LoopControlOutsideLoop {
+    #[source_code]
+    src: NamedSource,
+    #[label]
+    bad_bit: SourceSpan,
+    item: String,
+}
+
+impl<'a> From<Span<'a, String>> for LoopControlOutsideLoop {
+    fn from(value: Span<'a, String>) -> Self {
+        Self {
+            src: NamedSource::new(
+                value.extra.filename.display().to_string(),
+                value.extra.file_contents.to_string(),
+            ),
+            bad_bit: (value.location_offset(), value.len()).into(),
+            item: value.extra.data,
+        }
+    }
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("No task `ඬ` in `{file}`")]
+#[diagnostic(
+    code(suslang::entry::missing),
+    url(docsrs),
+    severity = "error",
+    help("the program starts at `task ඬ with ➤ number` in its main file")
+)]
+pub struct MissingEntryPoint {
+    file: String,
+}
+
+impl MissingEntryPoint {
+    pub fn new(file: &std::path::Path) -> Self {
+        Self {
+            file: file.display().to_string(),
+        }
+    }
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("Task `ඬ` is defined more than once")]
+#[diagnostic(code(suslang::entry::duplicate), url(docsrs), severity = "error")]
+pub struct DuplicateEntryPoint {
+    #[source_code]
+    src: NamedSource,
+    #[label("first defined here")]
+    first: SourceSpan,
+    #[label("defined again here")]
+    bad_bit: SourceSpan,
+}
+
+impl DuplicateEntryPoint {
+    pub fn new(first: &Span<String>, again: &Span<String>) -> Self {
+        Self {
+            src: NamedSource::new(
+                again.extra.filename.display().to_string(),
+                again.extra.file_contents.to_string(),
+            ),
+            first: (first.location_offset(), first.len()).into(),
+            bad_bit: (again.location_offset(), again.len()).into(),
+        }
+    }
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("Task `ඬ` is defined in a room")]
+#[diagnostic(
+    code(suslang::entry::in_room),
+    url(docsrs),
+    severity = "error",
+    help("only the main file can define `ඬ`, rename this task and `vent` it")
+)]
+pub struct EntryPointInSubRoom {
+    #[source_code]
+    src: NamedSource,
+    #[label]
+    bad_bit: SourceSpan,
+}
+
+impl<'a> From<Span<'a, String>> for EntryPointInSubRoom {
+    fn from(value: Span<'a, String>) -> Self {
+        Self {
+            src: NamedSource::new(
+                value.extra.filename.display().to_string(),
+                value.extra.file_contents.to_string(),
+            ),
+            bad_bit: (value.location_offset(), value.len()).into(),
+        }
+    }
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("Task `ඬ` {reason}")]
+#[diagnostic(
+    code(suslang::entry::signature),
+    url(docsrs),
+    severity = "error",
+    help("write it as `task ඬ with ➤ number` or `task ඬ with ➤ void`")
+)]
+pub struct InvalidEntryPoint {
+    #[source_code]
+    src: NamedSource,
+    #[label]
+    bad_bit: SourceSpan,
+    reason: &'static str,
+}
+
+impl InvalidEntryPoint {
+    /// `first` to `last` are what is wrong with the signature
+    pub fn new<A, B>(first: &Span<A>, last: &Span<B>, reason: &'static str) -> Self {
+        let start = first.location_offset();
+        Self {
+            src: NamedSource::new(
+                first.extra.filename.display().to_string(),
+                first.extra.file_contents.to_string(),
+            ),
+            bad_bit: (start, last.location_offset() + last.len() - start).into(),
+            reason,
+        }
+    }
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("Unreachable code after eject")]
+#[diagnostic(code(suslang::flow::unreachable), url(docsrs), severity = "warning")]
+pub struct UnreachableCode {
+    #[source_code]
+    src: NamedSource,
+    #[label("ejects on every path")]
+    eject: SourceSpan,
+    #[label("never runs")]
+    bad_bit: SourceSpan,
+}
+
+impl UnreachableCode {
+    /// `first` to `last` are the statements of a block that follow `eject`
+    pub fn new<A, B, C>(eject: &Span<A>, first: &Span<B>, last: &Span<C>) -> Self {
+        let start = first.location_offset();
+        Self {
+            src: NamedSource::new(
+                eject.extra.filename.display().to_string(),
+                eject.extra.file_contents.to_string(),
+            ),
+            eject: (eject.location_offset(), eject.len()).into(),
+            bad_bit: (start, last.location_offset() + last.len() - start).into(),
+        }
+    }
+}
+
+/// What a [`Lint`] found, a warning unless the lint is denied
+#[derive(Error, Debug)]
+#[error("{message}")]
+pub struct LintWarning {
+    src: NamedSource,
+    labels: Vec<LabeledSpan>,
+    message: String,
+    lint: Lint,
+    severity: Severity,
+}
+
+impl LintWarning {
+    pub fn new<T>(lint: Lint, message: String, span: &Span<T>, label: &str) -> Self {
+        Self {
+            src: NamedSource::new(
+                span.extra.filename.display().to_string(),
+                span.extra.file_contents.to_string(),
+            ),
+            labels: Vec::new(),
+            message,
+            lint,
+            severity: Severity::Warning,
+        }
+        .label(span, label)
+    }
+
+    pub fn label<T>(mut self, span: &Span<T>, label: &str) -> Self {
+        self.labels.push(LabeledSpan::new(
+            Some(label.to_string()),
+            span.location_offset(),
+            span.len(),
+        ));
+        self
+    }
+
+    pub fn lint(&self) -> Lint {
+        self.lint
+    }
+
+    /// Makes it stop the compilation
+    pub fn deny(mut self) -> Self {
+        self.severity = Severity::Error;
+        self
+    }
+}
+
+impl Diagnostic for LintWarning {
+    fn code<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        Some(Box::new(self.lint.code()))
+    }
+
+    fn severity(&self) -> Option<Severity> {
+        Some(self.severity)
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        Some(Box::new(format!(
+            "`(* allow {0} *)` on the line silences it, `--deny {0}` makes it an error",
+            self.lint
+        )))
+    }
+
+    fn url<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        Some(Box::new(format!(
+            "https://docs.rs/{}/{}/suslang/lint/enum.Lint.html",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        )))
+    }
+
+    fn source_code(&self) -> Option<&dyn miette::SourceCode> {
+        Some(&self.src)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        Some(Box::new(self.labels.iter().cloned()))
+    }
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("Could not parse `{}`", src.name())]
+#[diagnostic(code(suslang::parse), url(docsrs), severity = "error")]
+pub struct ParseFailed {
+    #[source_code]
+    src: NamedSource,
+    #[label("{expected}")]
+    bad_bit: SourceSpan,
+    expected: String,
+}
+
+impl<'a> From<&nom::Err<ParseError<Span<'a>>>> for ParseFailed {
+    fn from(value: &nom::Err<ParseError<Span<'a>>>) -> Self {
+        let (span, expected) = match value {
+            nom::Err::Error(e) | nom::Err::Failure(e) => furthest(e),
+            nom::Err::Incomplete(_) => unreachable!("the parsers are complete"),
+        };
+        Self {
+            src: NamedSource::new(
+                span.extra.filename.display().to_string(),
+                span.extra.file_contents.to_string(),
+            ),
+            bad_bit: (span.location_offset(), 0).into(),
+            expected,
+        }
+    }
+}
+
+/// Place where the parser got the furthest, which is where the error most likely is
+fn furthest<'a, 'b>(e: &'b ParseError<Span<'a>>) -> (&'b Span<'a>, String) {
+    match e {
+        GenericErrorTree::Base { location, kind } => (location, kind.to_string()),
+        GenericErrorTree::Stack { base, .. } => furthest(base),
+        GenericErrorTree::Alt(alternatives) => alternatives
+            .iter()
+            .map(furthest)
+            .max_by_key(|(span, _)| span.location_offset())
+            .expect("alternatives are never empty"),
+    }
+}
@@ -0,0 +1,470 @@
+//! Control flow of the tasks: whether they always eject, what never runs and
+//! which crewmates may be read before they are made or after they are killed.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{parse::spans::Span, Ast, Expression, Statement, Typ},
+    error::{DoubleKill, MissingEject, NotKillable, TypeCheckError, UnreachableCode, UseAfterKill, UseBeforeMake},
+    optimize::evaluate,
+    resolve::{Location, Resolution, SymbolId, SymbolKind},
+};
+
+/// What [`check`] found in a list of items
+#[derive(Debug, Default)]
+pub struct Flow {
+    /// Tasks that return a value but can run off their end
+    pub missing_ejects: Vec<MissingEject>,
+    pub unreachable: Vec<UnreachableCode>,
+    /// First use of a crewmate that may not be made yet, once for each crewmate
+    pub uses_before_make: Vec<UseBeforeMake>,
+    /// Crewmates used or killed after they may have been killed, and kills of
+    /// anything that is not a crewmate
+    pub kills: Vec<TypeCheckError>,
+}
+
+/// Whether `body` has a `break` out of the loop it is the body of
+fn breaks(body: &[Span<Statement>]) -> bool {
+    body.iter().any(|s| match &s.extra.data {
+        Statement::Break => true,
+        Statement::If(_, then, otherwise) => {
+            breaks(&then.extra.data) || otherwise.as_ref().is_some_and(|b| breaks(&b.extra.data))
+        }
+        Statement::Try(body, _, _, catch) => breaks(&body.extra.data) || breaks(&catch.extra.data),
+        // a `break` in an inner loop leaves that one
+        _ => false,
+    })
+}
+
+/// Whether a `while` over `cond` and `body` never finishes
+fn endless(cond: &Span<Expression>, body: &[Span<Statement>]) -> bool {
+    evaluate(&cond.extra.data) == Some(Expression::BoolLit(true)) && !breaks(body)
+}
+
+/// How far every path through a block jumps, ordered from shortest to longest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Leaves {
+    /// Some path runs off its end
+    Nothing,
+    /// Every path breaks, continues or ejects
+    Turn,
+    /// Every path ejects
+    Task,
+}
+
+impl Flow {
+    /// How far every path through `body` jumps, recording what follows a jump
+    fn block(&mut self, body: &[Span<Statement>]) -> Leaves {
+        for (i, statement) in body.iter().enumerate() {
+            let leaves = match &statement.extra.data {
+                Statement::Return(_)
+                | Statement::Sabotage(_)
+                | Statement::Win
+                | Statement::Lose => Leaves::Task,
+                Statement::Break | Statement::Continue => Leaves::Turn,
+                Statement::If(_, then, Some(otherwise)) => {
+                    // both branches are looked at for what they hide
+                    let then = self.block(&then.extra.data);
+                    self.block(&otherwise.extra.data).min(then)
+                }
+                Statement::Try(body, _, _, catch) => {
+                    let body = self.block(&body.extra.data);
+                    self.block(&catch.extra.data).min(body)
+                }
+                // only an `eject` in the body leaves a loop that never finishes
+                Statement::While(cond, body) if endless(cond, &body.extra.data) => {
+                    self.block(&body.extra.data);
+                    Leaves::Task
+                }
+                // the body may not run, and a loop keeps its breaks and continues
+                Statement::If(_, body, None)
+                | Statement::While(_, body)
+                | Statement::For(_, _, _, body) => {
+                    self.block(&body.extra.data);
+                    Leaves::Nothing
+                }
+                Statement::Expr(_)
+                | Statement::Declare(_, _)
+                | Statement::Define(_, _)
+                | Statement::Kill(_)
+                | Statement::Afk(_) => Leaves::Nothing,
+            };
+            if leaves != Leaves::Nothing {
+                if let (Some(first), Some(last)) = (body.get(i + 1), body.last()) {
+                    self.unreachable.push(UnreachableCode::new(statement, first, last));
+                }
+                return leaves;
+            }
+        }
+        Leaves::Nothing
+    }
+}
+
+/// Crewmates made on every path to a point and those killed on some path to it
+#[derive(Debug, Clone, Default)]
+struct Alive<'a> {
+    made: HashSet<SymbolId>,
+    killed: HashMap<SymbolId, &'a Span<'a, String>>,
+}
+
+/// `None` where nothing runs
+type State<'a> = Option<Alive<'a>>;
+
+fn meet<'a>(a: State<'a>, b: State<'a>) -> State<'a> {
+    match (a, b) {
+        (Some(mut a), Some(b)) => {
+            a.made.retain(|symbol| b.made.contains(symbol));
+            for (symbol, kill) in b.killed {
+                a.killed.entry(symbol).or_insert(kill);
+            }
+            Some(a)
+        }
+        (state, None) | (None, state) => state,
+    }
+}
+
+/// States that `break` and `continue` leave a loop in
+#[derive(Debug, Default)]
+struct Jumps<'a> {
+    breaks: State<'a>,
+    continues: State<'a>,
+}
+
+/// Definite assignment and lifetime of the crewmates of a task
+struct Assignments<'r, 'a> {
+    resolution: &'r Resolution,
+    declarations: HashMap<SymbolId, &'a Span<'a, String>>,
+    reported: HashSet<SymbolId>,
+    /// Uses and kills already reported, since loop bodies are looked at twice
+    reported_kills: HashSet<Location>,
+    /// States a sabotage can leave each open `emergency meeting` in, innermost last
+    meetings: Vec<State<'a>>,
+    /// Jumps out of each loop being looked at, innermost last
+    loops: Vec<Jumps<'a>>,
+}
+
+impl<'a> Assignments<'_, 'a> {
+    /// Reports a read of `symbol` at `used` if it may not be alive there
+    fn read<T>(&mut self, symbol: SymbolId, used: &Span<T>, alive: &Alive, flow: &mut Flow) {
+        if self.resolution.symbol(symbol).kind != SymbolKind::Crewmate {
+            return;
+        }
+        if let Some(kill) = alive.killed.get(&symbol) {
+            if self.reported_kills.insert(Location::of(used)) {
+                flow.kills.push(UseAfterKill::new(kill, used).into());
+            }
+        } else if !alive.made.contains(&symbol) && self.reported.insert(symbol) {
+            if let Some(declaration) = self.declarations.get(&symbol) {
+                flow.uses_before_make.push(UseBeforeMake::new(declaration, used));
+            }
+        }
+    }
+
+    fn expression(&mut self, e: &Span<Expression>, state: &State, flow: &mut Flow) {
+        match &e.extra.data {
+            Expression::Call(_, args) => {
+                for arg in args {
+                    self.expression(arg, state, flow);
+                }
+            }
+            Expression::Operation(_, a, b) => {
+                self.expression(a, state, flow);
+                self.expression(b, state, flow);
+            }
+            Expression::Variable(_) => {
+                if let (Some(symbol), Some(alive)) = (self.resolution.get(e), state) {
+                    self.read(symbol, e, alive, flow);
+                }
+            }
+            Expression::StringLit(_) | Expression::NumLit(_) | Expression::BoolLit(_) => (),
+        }
+    }
+
+    fn kill(&mut self, name: &'a Span<'a, String>, state: &mut State<'a>, flow: &mut Flow) {
+        let Some(symbol) = self.resolution.get(name) else {
+            return;
+        };
+        if self.resolution.symbol(symbol).kind != SymbolKind::Crewmate {
+            if self.reported_kills.insert(Location::of(name)) {
+                flow.kills.push(NotKillable::from(name.clone()).into());
+            }
+            return;
+        }
+        let Some(alive) = state else {
+            return;
+        };
+        match alive.killed.get(&symbol) {
+            Some(first) => {
+                if self.reported_kills.insert(Location::of(name)) {
+                    flow.kills.push(DoubleKill::new(first, name).into());
+                }
+            }
+            None => {
+                self.read(symbol, name, alive, flow);
+                alive.killed.insert(symbol, name);
+            }
+        }
+    }
+
+    /// State after a loop that starts in `state`, checking `cond` before each turn
+    /// and making the crewmate `name` at the start of it. Only its `break`s leave
+    /// a loop whose condition is always sus.
+    fn repeat(
+        &mut self,
+        cond: Option<&Span<Expression>>,
+        name: Option<&Span<String>>,
+        body: &'a [Span<'a, Statement<'a>>],
+        state: State<'a>,
+        flow: &mut Flow,
+    ) -> State<'a> {
+        let symbol = name.and_then(|name| self.resolution.get(name));
+        let mut turn = state;
+        let mut broken = None;
+        // later turns start with what the ones before killed, and the body
+        // may not run at all
+        for _ in 0..2 {
+            if let Some(cond) = cond {
+                self.expression(cond, &turn, flow);
+            }
+            let mut start = turn.clone();
+            if let (Some(symbol), Some(alive)) = (symbol, &mut start) {
+                alive.made.insert(symbol);
+                alive.killed.remove(&symbol);
+            }
+            self.loops.push(Jumps::default());
+            let end = self.block(body, start, flow);
+            let jumps = self.loops.pop().unwrap_or_default();
+            broken = meet(broken, jumps.breaks);
+            turn = meet(turn, meet(end, jumps.continues));
+        }
+        match cond {
+            Some(cond) if endless(cond, body) => broken,
+            _ => meet(turn, broken),
+        }
+    }
+
+    /// State after running `body` from a point in `state`
+    fn block(&mut self, body: &'a [Span<'a, Statement<'a>>], mut state: State<'a>, flow: &mut Flow) -> State<'a> {
+        for statement in body {
+            // any statement can sabotage, if only through a call
+            if let Some(meeting) = self.meetings.last_mut() {
+                *meeting = meet(meeting.take(), state.clone());
+            }
+            match &statement.extra.data {
+                Statement::If(cond, then, otherwise) => {
+                    self.expression(cond, &state, flow);
+                    let then = self.block(&then.extra.data, state.clone(), flow);
+                    let otherwise = match otherwise {
+                        Some(otherwise) => self.block(&otherwise.extra.data, state, flow),
+                        None => state,
+                    };
+                    state = meet(then, otherwise);
+                }
+                Statement::While(cond, body) => {
+                    state = self.repeat(Some(cond), None, &body.extra.data, state, flow);
+                }
+                Statement::For(name, start, end, body) => {
+                    self.expression(start, &state, flow);
+                    self.expression(end, &state, flow);
+                    state = self.repeat(None, Some(name), &body.extra.data, state, flow);
+                }
+                Statement::Break | Statement::Continue => {
+                    if let Some(jumps) = self.loops.last_mut() {
+                        let to = match statement.extra.data {
+                            Statement::Break => &mut jumps.breaks,
+                            _ => &mut jumps.continues,
+                        };
+                        *to = meet(to.take(), state.take());
+                    }
+                }
+                Statement::Return(value) => {
+                    if let Some(value) = value {
+                        self.expression(value, &state, flow);
+                    }
+                    state = None;
+                }
+                Statement::Expr(e) => self.expression(e, &state, flow),
+                Statement::Declare(name, _) => {
+                    if let Some(symbol) = self.resolution.get(name) {
+                        self.declarations.insert(symbol, name);
+                        // declared again on every turn of a loop
+                        if let Some(alive) = &mut state {
+                            alive.made.remove(&symbol);
+                            alive.killed.remove(&symbol);
+                        }
+                    }
+                }
+                Statement::Define(name, e) => {
+                    self.expression(e, &state, flow);
+                    if let (Some(symbol), Some(alive)) = (self.resolution.get(name), &mut state) {
+                        alive.made.insert(symbol);
+                        alive.killed.remove(&symbol);
+                    }
+                }
+                Statement::Kill(name) => self.kill(name, &mut state, flow),
+                Statement::Try(body, name, _, catch) => {
+                    self.meetings.push(None);
+                    let after = self.block(&body.extra.data, state, flow);
+                    let mut caught = self.meetings.pop().flatten();
+                    if let (Some(symbol), Some(alive)) = (self.resolution.get(name), &mut caught) {
+                        alive.made.insert(symbol);
+                    }
+                    let handled = self.block(&catch.extra.data, caught, flow);
+                    state = meet(after, handled);
+                }
+                Statement::Sabotage(reason) => {
+                    self.expression(reason, &state, flow);
+                    state = None;
+                }
+                Statement::Win | Statement::Lose => state = None,
+                Statement::Afk(ms) => self.expression(ms, &state, flow),
+            }
+        }
+        state
+    }
+}
+
+/// Looks for tasks that may finish without eject, for code after an eject and
+/// for crewmates used before they are made or after they are killed
+pub fn check(items: &[Span<Ast>], resolution: &Resolution) -> Flow {
+    let mut flow = Flow::default();
+    let mut assignments = Assignments {
+        resolution,
+        declarations: HashMap::new(),
+        reported: HashSet::new(),
+        reported_kills: HashSet::new(),
+        meetings: Vec::new(),
+        loops: Vec::new(),
+    };
+    for item in items {
+        if let Ast::Func(name, ret, _, body) = &item.extra.data {
+            if flow.block(&body.extra.data) != Leaves::Task && ret.extra.data != Typ::Void {
+                flow.missing_ejects.push(MissingEject::from(name.clone()));
+            }
+            assignments.block(&body.extra.data, Some(Alive::default()), &mut flow);
+        }
+    }
+    flow
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{codegen, parse_str, resolve::resolve_items, Diagnostic, Session};
+
+    #[test]
+    fn every_path_has_to_eject() {
+        let ast = parse_str("task fibo with crewmate n: number ➤ number\nචsus? < n 2\nචචeject nඞ\nචclean?\nචචeject 0ඞ\ntask f with crewmate n: number ➤ number\nචsus? < n 2\nචචeject nඞ\nචwhile < n 2\nචචeject 1ඞ\ntask g with ➤ void\nචcomplete report with \"hi\"ඞ\n").unwrap();
+        let flow = super::check(&ast, &resolve_items(&ast));
+        let tasks: Vec<_> = flow.missing_ejects.iter().map(ToString::to_string).collect();
+        assert_eq!(tasks, ["Task `f` may finish without eject"]);
+        assert!(flow.unreachable.is_empty());
+    }
+
+    #[test]
+    fn endless_loops_never_finish() {
+        let ast = parse_str("task f with crewmate n: number ➤ number\nචwhile == 1 1\nචචsus? < n 10\nචචචeject nඞ\nචචmake n - n 1ඞ\ntask g with crewmate n: number ➤ number\nචwhile == 2 2\nචචsus? < n 10\nචචචbreakඞ\nචwhile == 1 2\nචචeject nඞ\nචcomplete report with \"b\"ඞ\n").unwrap();
+        let flow = super::check(&ast, &resolve_items(&ast));
+        let tasks: Vec<_> = flow.missing_ejects.iter().map(ToString::to_string).collect();
+        assert_eq!(tasks, ["Task `g` may finish without eject"]);
+        assert!(flow.unreachable.is_empty());
+    }
+
+    #[test]
+    fn code_after_eject_is_unreachable() {
+        let ast = parse_str("task f with ➤ number\nචsus? == 1 1\nචචeject 1ඞ\nචචcomplete report with \"a\"ඞ\nචclean?\nචචeject 2ඞ\nචcomplete report with \"b\"ඞ\nචcomplete report with \"c\"ඞ\n").unwrap();
+        let flow = super::check(&ast, &resolve_items(&ast));
+        assert!(flow.missing_ejects.is_empty());
+        assert_eq!(flow.unreachable.len(), 2);
+    }
+
+    #[test]
+    fn unreachable_code_is_typechecked_but_only_warned_about() {
+        let mut session = Session::new();
+        session.add_source("main.sus", "task ඬ with ➤ number\nචeject 0ඞ\nචcomplete report with \"%d\" and \"a\"ඞ\n");
+        assert!(session.check().iter().any(Diagnostic::is_error));
+
+        let mut session = Session::new();
+        session.add_source("main.sus", "task ඬ with ➤ number\nචeject 0ඞ\nචcomplete report with \"%d\" and 1ඞ\n");
+        let diagnostics = session.check();
+        assert!(matches!(diagnostics[..], [Diagnostic::Unreachable(_)]));
+        assert!(!diagnostics[0].is_error());
+        assert!(session.build(&mut codegen::Py::new()).is_ok());
+    }
+
+    #[test]
+    fn winning_or_losing_ends_the_program() {
+        let ast = parse_str("task f with crewmate n: number ➤ number\nචsus? < n 2\nචචwinඞ\nචචcomplete report with \"a\"ඞ\nචafk nඞ\nචloseඞ\nචeject nඞ\n").unwrap();
+        let flow = super::check(&ast, &resolve_items(&ast));
+        assert!(flow.missing_ejects.is_empty());
+        assert_eq!(flow.unreachable.len(), 2);
+    }
+
+    #[test]
+    fn break_and_continue_leave_the_turn() {
+        let ast = parse_str("task f with crewmate n: number ➤ number\nචcrewmate a: numberඞ\nචfor red in 0 to n\nචචsus? < red 2\nචචචbreakඞ\nචචmake a redඞ\nචeject aඞ\ntask g with crewmate n: number ➤ number\nචcrewmate b: numberඞ\nචmake b 1ඞ\nචwhile < n 2\nචචkill bඞ\nචචcontinueඞ\nචචmake b 2ඞ\nචeject bඞ\n").unwrap();
+        let flow = super::check(&ast, &resolve_items(&ast));
+        assert!(flow.missing_ejects.is_empty());
+        assert_eq!(flow.unreachable.len(), 1);
+        assert_eq!(flow.uses_before_make.len(), 1);
+        let kills: Vec<_> = flow.kills.iter().map(ToString::to_string).collect();
+        assert_eq!(kills, ["stop! `b` is already dead", "Crewmate `b` may be dead here"]);
+    }
+
+    #[test]
+    fn crewmates_are_made_before_use() {
+        let ast = parse_str("task f with crewmate n: number ➤ number\nචcrewmate a: numberඞ\nචcrewmate b: numberඞ\nචcrewmate c: numberඞ\nචsus? < n 2\nචචmake a 1ඞ\nචචmake b 1ඞ\nචclean?\nචචmake a 2ඞ\nචwhile < n 2\nචචmake c 1ඞ\nචcomplete report with \"%d %d %d %d\" and a and b and c and bඞ\nචeject nඞ\n").unwrap();
+        let flow = super::check(&ast, &resolve_items(&ast));
+        let crewmates: Vec<_> = flow.uses_before_make.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            crewmates,
+            [
+                "Crewmate `b` may be used before it is made",
+                "Crewmate `c` may be used before it is made"
+            ]
+        );
+    }
+
+    #[test]
+    fn each_loop_turn_declares_again() {
+        let ast = parse_str("task f with crewmate n: number ➤ void\nචwhile < n 2\nචචcrewmate a: numberඞ\nචචsus? < n 1\nචචචmake a 1ඞ\nචචmake n + n aඞ\nචcrewmate b: numberඞ\nචmake b nඞ\nචmake b + b 1ඞ\n").unwrap();
+        let flow = super::check(&ast, &resolve_items(&ast));
+        assert_eq!(flow.uses_before_make.len(), 1);
+        assert_eq!(flow.uses_before_make[0].to_string(), "Crewmate `a` may be used before it is made");
+    }
+
+    #[test]
+    fn dead_crewmates_are_not_used_or_killed_again() {
+        let ast = parse_str("task f with crewmate n: number ➤ void\nචcrewmate a: numberඞ\nචmake a 1ඞ\nචsus? < n 2\nචචkill aඞ\nචcomplete report with \"%d\" and aඞ\nචkill aඞ\nචkill nඞ\nචmake a 2ඞ\nචkill aඞ\nචcrewmate b: numberඞ\nචmake b 1ඞ\nචwhile < n 2\nචචcomplete report with \"%d\" and bඞ\nචචkill bඞ\n").unwrap();
+        let flow = super::check(&ast, &resolve_items(&ast));
+        let kills: Vec<_> = flow.kills.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            kills,
+            [
+                "Crewmate `a` may be dead here",
+                "stop! `a` is already dead",
+                "`n` is not a crewmate, only crewmates can be killed",
+                "Crewmate `b` may be dead here",
+                "stop! `b` is already dead"
+            ]
+        );
+        assert!(flow.uses_before_make.is_empty());
+    }
+
+    #[test]
+    fn killed_crewmates_can_be_made_again() {
+        let mut session = Session::new();
+        session.add_source("main.sus", "task ඬ with ➤ void\nචcrewmate a: numberඞ\nචmake a 1ඞ\nචkill aඞ\nචmake a 2ඞ\nචcomplete report with \"%d\" and aඞ\nචkill aඞ\n");
+        assert!(session.check().is_empty());
+        assert!(session.build(&mut codegen::Py::new()).is_ok());
+    }
+
+    #[test]
+    fn impostors_see_what_the_meeting_did_so_far() {
+        let ast = parse_str("task f with crewmate n: number ➤ number\nචcrewmate a: numberඞ\nචmake a 1ඞ\nචemergency meeting\nචචkill aඞ\nචචmake n complete f with nඞ\nචචmake a 2ඞ\nචimpostor red: string\nචචcomplete report with \"%s %d\" and red and aඞ\nචeject aඞ\n").unwrap();
+        let flow = super::check(&ast, &resolve_items(&ast));
+        let kills: Vec<_> = flow.kills.iter().map(ToString::to_string).collect();
+        // in the impostor, then after the meeting since the impostor didn't make it again
+        assert_eq!(kills, ["Crewmate `a` may be dead here", "Crewmate `a` may be dead here"]);
+        assert!(flow.missing_ejects.is_empty() && flow.uses_before_make.is_empty());
+    }
+}
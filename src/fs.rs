@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     marker::PhantomData,
     path::{Path, PathBuf},
 };
@@ -9,6 +10,8 @@ where
     Self: 'a,
 {
     files: Vec<(*mut Path, *mut str)>,
+    /// Files that are read from memory instead of disk
+    sources: HashMap<PathBuf, String>,
     lifetime: PhantomData<&'a ()>,
 }
 
@@ -19,11 +22,23 @@ impl<'a> Filesystem<'a> {
         Self::default()
     }
 
+    /// Makes `path` read as `contents` instead of whatever is on disk
+    pub fn add_source(&mut self, path: PathBuf, contents: String) {
+        self.sources.insert(path, contents);
+    }
+
+    pub fn exists(&self, path: &Path) -> bool {
+        self.sources.contains_key(path) || path.is_file()
+    }
+
     pub fn load<'c, 'b>(&'b mut self, p: PathBuf) -> std::io::Result<(&'c Path, &'c str)>
     where
         'a: 'c,
     {
-        let s = std::fs::read_to_string(&p)?;
+        let s = match self.sources.get(&p) {
+            Some(s) => s.clone(),
+            None => std::fs::read_to_string(&p)?,
+        };
         let p = Box::into_raw(p.into_boxed_path());
         let b = Box::into_raw(s.into_boxed_str());
 
@@ -35,8 +50,7 @@ impl<'a> Filesystem<'a> {
 
 impl<'a> Drop for Filesystem<'a> {
     fn drop(&mut self) {
-        while !self.files.is_empty() {
-            let (p, c) = self.files.pop().unwrap();
+        while let Some((p, c)) = self.files.pop() {
             let (p, b) = unsafe { (Box::from_raw(p), Box::from_raw(c)) };
             drop(p);
             drop(b);
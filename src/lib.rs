@@ -1,25 +1,46 @@
-use std::{
-    fs::File,
-    io::{BufWriter, Write},
-    path::Path,
+use ast::{
+    parse::{
+        error::ParseError,
+        items::parse_items,
+        spans::{load_file_str, Span},
+    },
+    Ast,
 };
-
-use ast::{parse::spans::Span, Ast};
-use codegen::Codegen;
+use error::ParseFailed;
 
 // use crate::tokens::{tokenize, Token};
 
 pub mod ast;
 pub mod codegen;
+pub mod colors;
+pub mod emit;
+pub mod entry;
 pub mod error;
+pub mod flow;
 pub mod fs;
 pub mod linker;
+pub mod lint;
+pub mod mir;
 pub mod module;
+pub mod optimize;
+pub mod resolve;
 mod scope;
+pub mod semantics;
+pub mod session;
+mod suggest;
 // mod tokens;
+pub mod toolchain;
 mod typecheck;
 
-pub use typecheck::typecheck;
+pub use session::{Diagnostic, Session};
+pub use typecheck::{infer_types, typecheck, ExprTypes};
+
+/// Parses the items of a single suslang file, without loading its modules
+pub fn parse_str(text: &str) -> Result<Vec<Span<'_, Ast<'_>>>, ParseFailed> {
+    parse_items::<ParseError<_>>(load_file_str("<input>", text))
+        .map(|(_, items)| items)
+        .map_err(|e| ParseFailed::from(&e))
+}
 
 // pub fn parse_str(text: &str) -> Vec<Ast> {
 //     let tok = tokenize(text);
@@ -47,14 +68,3 @@ pub use typecheck::typecheck;
 //     std::fs::File::open(file)?.read_to_string(&mut s)?;
 //     Ok(parse_str(&s))
 // }
-
-pub fn codegen_file<'a, C, P>(file: P, cod: &mut C, ast: &[Span<'a, Ast<'a>>])
-where
-    C: Codegen<BufWriter<File>, [Span<'a, Ast<'a>>]> + ?Sized,
-    P: AsRef<Path>,
-{
-    let f = File::create(file).unwrap();
-    let mut buf = BufWriter::new(f);
-    cod.gen(ast, &mut buf).unwrap();
-    buf.flush().unwrap();
-}
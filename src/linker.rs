@@ -1,11 +1,11 @@
-use std::collections::HashMap;
-
 use crate::{
     ast::{
         parse::spans::{MapExt, Span},
-        Ast, Block, Expression,
+        visit::Fold,
+        Ast,
     },
     module::{Module, ModuleUsePath},
+    resolve::{Resolution, Symbol, SymbolId, SymbolKind},
 };
 
 fn get_items_needed(module_graph: &Module) -> Vec<ModuleUsePath> {
@@ -58,128 +58,56 @@ pub fn get_path<'a, 'b>(
         })
 }
 
-fn mangle_name(id: usize, path: &ModuleUsePath) -> String {
-    if ["ඬ"] != path.as_slice() {
-        format!("{}_{id}", path.join("_"))
-    } else {
+fn mangle_name(id: SymbolId, symbol: &Symbol) -> String {
+    if symbol.module.is_empty() && symbol.name == "ඬ" {
         "ඬ".to_string()
+    } else {
+        let mut path = symbol.module.clone();
+        path.push(symbol.name.clone());
+        format!("{}_{id}", path.join("_"))
     }
 }
 
-fn replace_body<'a>(items_in_scope: &HashMap<String, String>, body: Block<'a>) -> Block<'a> {
-    body.into_iter()
-        .map(|x| {
-            x.map(|x| {
-                use crate::ast::Statement::*;
-                match x {
-                    If(expr, a, b) => If(
-                        expr.map(|expr| replace_expression(items_in_scope, expr)),
-                        a.map(|body| replace_body(items_in_scope, body)),
-                        b.map(|x| x.map(|body| replace_body(items_in_scope, body))),
-                    ),
-                    While(expr, body) => While(
-                        expr.map(|expr| replace_expression(items_in_scope, expr)),
-                        body.map(|body| replace_body(items_in_scope, body)),
-                    ),
-                    Return(expr) => Return(
-                        expr.map(|expr| expr.map(|expr| replace_expression(items_in_scope, expr))),
-                    ),
-                    Expr(expr) => Expr(expr.map(|expr| replace_expression(items_in_scope, expr))),
-                    Define(name, expr) => Define(
-                        name,
-                        expr.map(|expr| replace_expression(items_in_scope, expr)),
-                    ),
-                    x => x,
-                }
-            })
-        })
-        .collect()
+/// A linked program, along with the symbol of each of its names.
+///
+/// The passes that run after the linker keep the resolution up to date with the
+/// names they make, so that the backends never resolve the program again.
+#[derive(Debug, Clone)]
+pub struct Linked<'a> {
+    pub items: Vec<Span<'a, Ast<'a>>>,
+    pub resolution: Resolution,
 }
 
-fn replace_expression<'a>(
-    items_in_scope: &HashMap<String, String>,
-    expr: Expression<'a>,
-) -> Expression<'a> {
-    use Expression::*;
-    match expr {
-        Call(name, args) => Call(
-            name.map(|name| replace_name(items_in_scope, name)),
-            args.into_iter()
-                .map(|arg| arg.map(|expr| replace_expression(items_in_scope, expr)))
-                .collect(),
-        ),
-        Operation(op, expr_a, expr_b) => Operation(
-            op,
-            Box::new(expr_a.map(|expr| replace_expression(items_in_scope, expr))),
-            Box::new(expr_b.map(|expr| replace_expression(items_in_scope, expr))),
-        ),
-        x => x,
-    }
-}
-
-fn replace_name(items_in_scope: &HashMap<String, String>, name: String) -> String {
-    items_in_scope.get(&name).cloned().unwrap_or(name)
+/// Gives the tasks their mangled names, other names are kept as they are
+struct Linker<'r> {
+    resolution: &'r Resolution,
 }
 
-fn load_items_in_scope<'a: 'c, 'b, 'c>(
-    module: &'a Module<'b>,
-    cache: &'c mut HashMap<&'a Module<'b>, HashMap<String, String>>,
-    items_needed: &[ModuleUsePath],
-) -> &'c HashMap<String, String> {
-    cache.entry(module).or_insert_with(|| {
-        let mut hm = HashMap::new();
-        for item in &module.items {
-            match &item.extra.data {
-                Ast::Func(name, _, _, _) => {
-                    let mut path = module.path.clone();
-                    path.push(name.extra.data.clone());
-                    if let Some(id) = items_needed
-                        .iter()
-                        .enumerate()
-                        .find(|(_, x)| x == &&path)
-                        .map(|(i, _)| i)
-                    {
-                        let name = mangle_name(id, &path);
-                        hm.insert(path.last().unwrap().clone(), name);
-                    }
-                }
-                Ast::Mod(_) => (),
-                Ast::Import(path) => {
-                    if let Some(id) = items_needed
-                        .iter()
-                        .enumerate()
-                        .find(|(_, x)| x == &&path.extra.data)
-                        .map(|(i, _)| i)
-                    {
-                        let name = mangle_name(id, &path.extra.data);
-                        hm.insert(path.extra.data.last().unwrap().clone(), name);
-                    }
-                }
-            }
+impl<'a> Fold<'a> for Linker<'_> {
+    fn fold_name(&mut self, name: Span<'a, String>) -> Span<'a, String> {
+        match self.resolution.symbol_at(&name) {
+            Some(symbol) if symbol.kind == SymbolKind::Task => name.map(|_| symbol.name.clone()),
+            _ => name,
         }
-        hm
-    })
+    }
 }
 
-pub fn link<'a>(module_graph: &Module<'a>) -> Vec<Span<'a, Ast<'a>>> {
-    let items_needed = get_items_needed(module_graph);
-    let mut cache = HashMap::new();
-    items_needed
-        .clone()
+/// Every task used by the main module, with the tasks and the calls to them going
+/// by their mangled names
+pub fn link<'a>(module_graph: &Module<'a>, resolution: &Resolution) -> Linked<'a> {
+    let mut resolution = resolution.clone();
+    let tasks: Vec<_> = resolution
+        .symbols()
+        .filter(|(_, symbol)| symbol.kind == SymbolKind::Task)
+        .map(|(id, symbol)| (id, mangle_name(id, symbol)))
+        .collect();
+    for (id, name) in tasks {
+        resolution.rename(id, name);
+    }
+    let items = get_items_needed(module_graph)
         .into_iter()
-        .enumerate()
-        .filter_map(|(i, item)| get_path(module_graph, item).map(|x| (i, x)))
-        .map(|(_, (_, module, item))| {
-            let items_in_scope = load_items_in_scope(module, &mut cache, &items_needed);
-            item.clone().map(|item| match item {
-                Ast::Func(a, b, c, d) => Ast::Func(
-                    a.map(|name| replace_name(items_in_scope, name)),
-                    b,
-                    c,
-                    d.map(|body| replace_body(items_in_scope, body)),
-                ),
-                _ => unreachable!(),
-            })
-        })
-        .collect()
+        .filter_map(|item| get_path(module_graph, item))
+        .map(|(_, _, item)| Linker { resolution: &resolution }.fold_item(item.clone()))
+        .collect();
+    Linked { items, resolution }
 }
@@ -0,0 +1,400 @@
+//! Lints: code that compiles but is most likely a mistake.
+//!
+//! Every lint has a [`Level`], which `--warn` and `--deny` change for the whole
+//! program, and an `(* allow <lint> *)` comment turns off on its own line, or on
+//! the next one when the comment is alone on its line.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use crate::{
+    ast::{
+        parse::spans::{MapExt, Span},
+        visit::{walk_block, walk_expression, walk_statement, Visit},
+        Ast, Block, Expression, Statement,
+    },
+    colors::Colors,
+    error::LintWarning,
+    resolve::{Resolution, SymbolId},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Lint {
+    /// Crewmate that is never read
+    UnusedCrewmate,
+    /// Argument that is never read
+    UnusedArgument,
+    /// `vent` of a task that is never completed
+    UnusedImport,
+    /// Crewmate or argument with the name of something else in scope, allowed by
+    /// default
+    ShadowedName,
+    /// `sus?` or `while` whose condition doesn't depend on any crewmate or task
+    ConstantCondition,
+    /// Task, `sus?`, `clean?` or `while` without any statement
+    EmptyBlock,
+    /// Crewmate or argument whose name isn't in the [`Colors`], allowed by default
+    NonColorName,
+}
+
+impl Lint {
+    pub const ALL: [Self; 7] = [
+        Self::UnusedCrewmate,
+        Self::UnusedArgument,
+        Self::UnusedImport,
+        Self::ShadowedName,
+        Self::ConstantCondition,
+        Self::EmptyBlock,
+        Self::NonColorName,
+    ];
+
+    /// Name given to `--warn`, `--deny` and the pragmas
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::UnusedCrewmate => "unused_crewmate",
+            Self::UnusedArgument => "unused_argument",
+            Self::UnusedImport => "unused_import",
+            Self::ShadowedName => "shadowed_name",
+            Self::ConstantCondition => "constant_condition",
+            Self::EmptyBlock => "empty_block",
+            Self::NonColorName => "non_color_name",
+        }
+    }
+
+    /// Code of its diagnostics, which doesn't change between versions
+    pub fn code(self) -> String {
+        format!("suslang::lint::{}", self.name())
+    }
+
+    pub fn default_level(self) -> Level {
+        match self {
+            Self::ShadowedName | Self::NonColorName => Level::Allow,
+            _ => Level::Warn,
+        }
+    }
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::str::FromStr for Lint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|lint| lint.name() == s).ok_or_else(|| {
+            let names: Vec<_> = Self::ALL.iter().map(|lint| lint.name()).collect();
+            format!("unknown lint `{s}`, expected one of {}", names.join(", "))
+        })
+    }
+}
+
+/// What is done with what a lint finds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+/// Lints turned off by the comments of a line
+fn pragmas(line: &str) -> impl Iterator<Item = &str> {
+    line.split("(*").skip(1).flat_map(|comment| {
+        let comment = comment.split_once("*)").map_or("", |(comment, _)| comment);
+        comment
+            .trim()
+            .strip_prefix("allow ")
+            .unwrap_or("")
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|name| !name.is_empty())
+    })
+}
+
+/// Whether an `(* allow *)` comment turns `lint` off where `span` starts
+fn allowed<T>(lint: Lint, span: &Span<T>) -> bool {
+    let contents = span.extra.file_contents;
+    let offset = span.location_offset();
+    let start = contents[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let end = contents[offset..].find('\n').map_or(contents.len(), |i| offset + i);
+    let previous = contents[..start.saturating_sub(1)]
+        .rsplit('\n')
+        .next()
+        .filter(|line| line.trim().starts_with("(*") && line.trim().ends_with("*)"))
+        .unwrap_or("");
+    pragmas(&contents[start..end])
+        .chain(pragmas(previous))
+        .any(|name| name == lint.name())
+}
+
+/// Whether `e` has the same value every time it is computed
+fn is_constant(e: &Expression) -> bool {
+    match e {
+        Expression::Operation(_, a, b) => is_constant(&a.extra.data) && is_constant(&b.extra.data),
+        Expression::StringLit(_) | Expression::NumLit(_) | Expression::BoolLit(_) => true,
+        Expression::Variable(_) | Expression::Call(_, _) => false,
+    }
+}
+
+struct Linter<'r, 'a> {
+    resolution: &'r Resolution,
+    colors: &'r Colors,
+    /// Crewmates and tasks that are read or completed
+    used: HashSet<SymbolId>,
+    /// Crewmates and arguments, with where they are declared
+    declared: Vec<(Lint, SymbolId, Span<'a, String>)>,
+    /// Names in scope, the tasks and `vent`s of the room first
+    scopes: Vec<HashMap<String, Span<'a, String>>>,
+    found: Vec<LintWarning>,
+}
+
+impl<'a> Linter<'_, 'a> {
+    fn report<T>(&mut self, warning: LintWarning, span: &Span<T>) {
+        if !allowed(warning.lint(), span) {
+            self.found.push(warning);
+        }
+    }
+
+    fn declare(&mut self, lint: Lint, name: &Span<'a, String>) {
+        let shadowed = self.scopes.iter().rev().find_map(|scope| scope.get(&name.extra.data));
+        if let Some(shadowed) = shadowed {
+            let message = format!("`{}` shadows an earlier declaration", name.extra.data);
+            let warning = LintWarning::new(Lint::ShadowedName, message, name, "declared again here")
+                .label(shadowed, "first declared here");
+            self.report(warning, name);
+        }
+        if !self.colors.contains(&name.extra.data) {
+            let message = format!("`{}` is not a color", name.extra.data);
+            let label = match self.colors.closest(&name.extra.data) {
+                Some(color) => format!("did you mean `{color}`?"),
+                None => "not in the colors".to_string(),
+            };
+            self.report(LintWarning::new(Lint::NonColorName, message, name, &label), name);
+        }
+        if let Some(symbol) = self.resolution.get(name) {
+            self.declared.push((lint, symbol, name.clone()));
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.extra.data.clone(), name.clone());
+        }
+    }
+
+    fn empty_block<T>(&mut self, block: &Span<'a, Block<'a>>, what: &str, span: &Span<T>, label: &str) {
+        if block.extra.data.is_empty() {
+            let message = format!("Empty {what}");
+            self.report(LintWarning::new(Lint::EmptyBlock, message, span, label), span);
+        }
+    }
+}
+
+impl<'a> Visit<'a> for Linter<'_, 'a> {
+    fn visit_item(&mut self, item: &Span<'a, Ast<'a>>) {
+        if let Ast::Func(name, _, args, body) = &item.extra.data {
+            self.scopes.push(HashMap::new());
+            for arg in args {
+                self.declare(Lint::UnusedArgument, &arg.extra.data.0);
+            }
+            self.empty_block(body, "task", name, "does nothing");
+            self.visit_block(body);
+            self.scopes.pop();
+        }
+    }
+
+    fn visit_block(&mut self, block: &Span<'a, Block<'a>>) {
+        self.scopes.push(HashMap::new());
+        walk_block(self, block);
+        self.scopes.pop();
+    }
+
+    fn visit_statement(&mut self, statement: &Span<'a, Statement<'a>>) {
+        match &statement.extra.data {
+            Statement::If(cond, then, otherwise) => {
+                self.empty_block(then, "`sus?` block", cond, "nothing runs when this is sus");
+                if let Some(otherwise) = otherwise {
+                    self.empty_block(otherwise, "`clean?` block", cond, "nothing runs when this is clean");
+                }
+            }
+            Statement::While(cond, body) => {
+                self.empty_block(body, "`while` block", cond, "nothing runs while this is sus");
+            }
+            Statement::For(name, start, end, body) => {
+                self.empty_block(body, "`for` block", name, "nothing runs for each number");
+                self.visit_expression(start);
+                self.visit_expression(end);
+                // the crewmate only lives in the block of the loop
+                self.scopes.push(HashMap::new());
+                self.declare(Lint::UnusedCrewmate, name);
+                self.visit_block(body);
+                self.scopes.pop();
+                return;
+            }
+            Statement::Declare(name, _) => self.declare(Lint::UnusedCrewmate, name),
+            Statement::Try(body, name, _, catch) => {
+                self.empty_block(body, "`emergency meeting`", statement, "nothing can be sabotaged");
+                self.visit_block(body);
+                // the impostor only lives in the block that handles it
+                self.scopes.push(HashMap::new());
+                self.declare(Lint::UnusedCrewmate, name);
+                self.visit_block(catch);
+                self.scopes.pop();
+                return;
+            }
+            Statement::Return(_)
+            | Statement::Expr(_)
+            | Statement::Define(_, _)
+            | Statement::Kill(_)
+            | Statement::Sabotage(_)
+            | Statement::Win
+            | Statement::Lose
+            | Statement::Afk(_)
+            | Statement::Break
+            | Statement::Continue => (),
+        }
+        if let Statement::If(cond, _, _) | Statement::While(cond, _) = &statement.extra.data {
+            if is_constant(&cond.extra.data) {
+                let warning = LintWarning::new(
+                    Lint::ConstantCondition,
+                    "Constant condition".to_string(),
+                    cond,
+                    "always has the same value",
+                );
+                self.report(warning, cond);
+            }
+        }
+        walk_statement(self, statement)
+    }
+
+    fn visit_expression(&mut self, expression: &Span<'a, Expression<'a>>) {
+        let symbol = match &expression.extra.data {
+            Expression::Call(name, _) => self.resolution.get(name),
+            Expression::Variable(_) => self.resolution.get(expression),
+            _ => None,
+        };
+        self.used.extend(symbol);
+        walk_expression(self, expression)
+    }
+}
+
+/// Runs every lint over the items of a room, including the allowed ones but
+/// not the ones turned off by a comment
+pub fn check(items: &[Span<Ast>], resolution: &Resolution, colors: &Colors) -> Vec<LintWarning> {
+    let mut room = HashMap::new();
+    for item in items {
+        match &item.extra.data {
+            Ast::Func(name, ..) => {
+                room.insert(name.extra.data.clone(), name.clone());
+            }
+            Ast::Import(path) => {
+                if let Some(name) = path.extra.data.last() {
+                    room.insert(name.clone(), path.clone().map(|_| name.clone()));
+                }
+            }
+            Ast::Mod(_) => (),
+        }
+    }
+    let mut linter = Linter {
+        resolution,
+        colors,
+        used: HashSet::new(),
+        declared: Vec::new(),
+        scopes: vec![room],
+        found: Vec::new(),
+    };
+    for item in items {
+        linter.visit_item(item);
+    }
+    let unused: Vec<_> = linter
+        .declared
+        .iter()
+        .filter(|(_, symbol, _)| !linter.used.contains(symbol))
+        .map(|(lint, _, name)| {
+            let what = if *lint == Lint::UnusedArgument { "Argument" } else { "Crewmate" };
+            let message = format!("{what} `{}` is never used", name.extra.data);
+            (LintWarning::new(*lint, message, name, "never read"), name.clone())
+        })
+        .collect();
+    for (warning, name) in unused {
+        linter.report(warning, &name);
+    }
+    for item in items {
+        if let Ast::Import(path) = &item.extra.data {
+            if resolution.get(path).is_some_and(|task| !linter.used.contains(&task)) {
+                let message = format!("`{}` is vented but never completed", path.fragment());
+                linter.report(LintWarning::new(Lint::UnusedImport, message, path, "unused"), path);
+            }
+        }
+    }
+    linter.found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pragmas, Lint};
+    use crate::{colors::Colors, parse_str, resolve::resolve_items};
+
+    fn lints(program: &str) -> Vec<(Lint, String)> {
+        let ast = parse_str(program).unwrap();
+        super::check(&ast, &resolve_items(&ast), &Colors::default())
+            .into_iter()
+            .filter(|warning| warning.lint() != Lint::NonColorName)
+            .map(|warning| (warning.lint(), warning.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn unused_names() {
+        let found = lints("task f with crewmate a: number and crewmate b: number ➤ number\nචcrewmate x: numberඞ\nචmake x aඞ\nචcrewmate y: numberඞ\nචmake y xඞ\nචeject complete f with y and aඞ\n");
+        assert_eq!(
+            found,
+            [(Lint::UnusedArgument, "Argument `b` is never used".to_string())]
+        );
+    }
+
+    #[test]
+    fn shadowing_conditions_and_empty_blocks() {
+        let found = lints("task f with crewmate n: number ➤ void\nචsus? < 1 2\nචclean?\nචචcrewmate n: numberඞ\nචචmake n 1ඞ\nචචcomplete f with nඞ\nචwhile < n 2\n");
+        let lints: Vec<_> = found.iter().map(|(lint, _)| *lint).collect();
+        assert_eq!(
+            lints,
+            [
+                Lint::EmptyBlock,
+                Lint::ConstantCondition,
+                Lint::ShadowedName,
+                Lint::EmptyBlock
+            ]
+        );
+    }
+
+    #[test]
+    fn allowed_by_comments() {
+        assert!(lints("task f with crewmate n: number ➤ void (* allow unused_argument, empty_block *)\n").is_empty());
+        assert!(lints("(* allow unused_argument *)\ntask f with crewmate n: number ➤ void\nචcomplete report with \"hi\"ඞ\n").is_empty());
+        assert_eq!(
+            pragmas("a (* allow x y *) b (* allow z *) (* other *)").collect::<Vec<_>>(),
+            ["x", "y", "z"]
+        );
+    }
+
+    #[test]
+    fn names_have_to_be_colors() {
+        let ast = parse_str("task f with crewmate red: number and crewmate gren: number ➤ number\nචcrewmate n: numberඞ\nචmake n + red grenඞ\nචeject nඞ\n").unwrap();
+        let labels: Vec<_> = super::check(&ast, &resolve_items(&ast), &Colors::default())
+            .into_iter()
+            .filter(|warning| warning.lint() == Lint::NonColorName)
+            .map(|warning| {
+                let label = miette::Diagnostic::labels(&warning).unwrap().next().unwrap();
+                format!("{warning}: {}", label.label().unwrap())
+            })
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                "`gren` is not a color: did you mean `green`?",
+                "`n` is not a color: not in the colors"
+            ]
+        );
+    }
+}
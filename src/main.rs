@@ -1,72 +1,180 @@
 use std::{
     fs::File,
-    io::BufWriter,
+    io::{BufWriter, IsTerminal},
     path::{Path, PathBuf},
 };
 
 use clap::Parser;
-use miette::GraphicalReportHandler;
+use miette::{GraphicalReportHandler, GraphicalTheme};
 use suslang::{
-    ast::{parse::spans::Span, Ast},
     codegen::{self, Codegen},
-    codegen_file,
-    fs::Filesystem,
-    linker::{self},
-    module::Module,
+    emit::Stage,
+    linker::Linked,
+    lint::{Level, Lint},
+    optimize::OptLevel,
+    toolchain::{CCompiler, Interpreter},
+    Diagnostic, Session,
 };
 
-fn check_module_graph(module_graph: &Module) {
-    for module in module_graph.iter() {
-        if let Err(report) = suslang::typecheck(&module.items, module_graph) {
-            let handler = GraphicalReportHandler::new();
-            let mut buf = String::new();
-            handler.render_report(&mut buf, &report).unwrap();
-            println!("{buf}");
-            // match report {
-            //     TypeCheckError::ItemNotFound(e) => {
-            //         let r = miette::Report::from(e);
-            //         eprintln!("{r:?}");
-            //     }
-            //     e => eprintln!("{e}"),
-            // }
-            std::process::exit(1);
+/// Writes `report` to stderr, so that it doesn't mix with what a program run
+/// prints, in colors and with links only if someone reads it in a terminal
+fn render_report(report: &dyn miette::Diagnostic) {
+    let handler = if std::io::stderr().is_terminal() {
+        GraphicalReportHandler::new()
+    } else {
+        GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor()).with_links(false)
+    };
+    let mut buf = String::new();
+    handler.render_report(&mut buf, report).unwrap();
+    eprintln!("{buf}");
+}
+
+/// Renders `diagnostics` and exits if there's any error among them
+fn report_diagnostics(diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        render_report(diagnostic);
+    }
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        std::process::exit(1);
+    }
+}
+
+// Levels of the lints given on the command line, not a doc comment since clap
+// would show it as the about of the whole program
+#[derive(Debug, Clone, clap::Args)]
+struct Lints {
+    /// Report what a lint finds as a warning, for the ones allowed by default like shadowed_name
+    #[arg(long, global = true, value_name = "LINT")]
+    warn: Vec<Lint>,
+    /// Make what a lint finds an error
+    #[arg(long, global = true, value_name = "LINT")]
+    deny: Vec<Lint>,
+    /// File of extra colors for non_color_name, one per line
+    #[arg(long, global = true, value_name = "FILE")]
+    colors: Vec<PathBuf>,
+}
+
+impl Lints {
+    fn session<A: AsRef<Path>>(&self, input: &A) -> Session {
+        let mut session = Session::new();
+        session.main_file(input);
+        for &lint in &self.warn {
+            session.lint_level(lint, Level::Warn);
         }
+        for &lint in &self.deny {
+            session.lint_level(lint, Level::Deny);
+        }
+        for path in &self.colors {
+            match std::fs::read_to_string(path) {
+                Ok(colors) => session.colors(colors.lines()),
+                Err(source) => {
+                    report_diagnostics(&[Diagnostic::Read {
+                        path: path.clone(),
+                        source,
+                    }]);
+                    continue;
+                }
+            };
+        }
+        session
     }
-    
 }
 
-fn compile_file<
-    'a,
-    A: AsRef<Path>,
-    B: AsRef<Path>,
-    C: Codegen<BufWriter<File>, [Span<'a, Ast<'a>>]> + ?Sized,
->(
+fn compile_file<A: AsRef<Path>, B: AsRef<Path>, C>(
     input: &A,
     output: &B,
     codegen: &mut C,
-) {
-    let mut fs = Filesystem::new();
-    let module = Module::new(input.as_ref().into(), &mut fs).unwrap();
-
-    check_module_graph(&module);
+    opt_level: OptLevel,
+    lints: &Lints,
+) where
+    C: for<'a> Codegen<BufWriter<File>, Linked<'a>> + ?Sized,
+{
+    let mut session = lints.session(input);
+    session.opt_level(opt_level);
+    let diagnostics = session.check();
+    report_diagnostics(&diagnostics);
 
-    let ast = linker::link(&module);
+    let file = match File::create(output) {
+        Ok(file) => file,
+        Err(source) => {
+            report_diagnostics(&[Diagnostic::Write(source)]);
+            return;
+        }
+    };
+    if let Err(diagnostics) = session.emit(codegen, &mut BufWriter::new(file)) {
+        report_diagnostics(&diagnostics);
+    }
+}
 
-    codegen_file(output, codegen, ast.as_slice());
-    drop(module);
-    drop(fs);
+fn emit<A: AsRef<Path>, B: AsRef<Path>>(
+    input: &A,
+    output: &B,
+    stage: Stage,
+    opt_level: OptLevel,
+    lints: &Lints,
+) {
+    let mut session = lints.session(input);
+    session.opt_level(opt_level);
+    match session.dump(stage) {
+        Ok(json) => {
+            if let Err(source) = std::fs::write(output, json) {
+                report_diagnostics(&[Diagnostic::Write(source)]);
+            }
+        }
+        Err(diagnostics) => report_diagnostics(&diagnostics),
+    }
 }
 
-fn check<A: AsRef<Path>>(input: &A) {
-    let mut fs = Filesystem::new();
-    let module = Module::new(input.as_ref().into(), &mut fs).unwrap();
+fn check<A: AsRef<Path>>(input: &A, lints: &Lints) {
+    report_diagnostics(&lints.session(input).check());
+}
 
-    check_module_graph(&module);
+fn compile_exe<A: AsRef<Path>, B: AsRef<Path>>(input: &A, output: &B, opt_level: OptLevel, lints: &Lints) {
+    // the C is only kept while the compiler reads it, as `run` does
+    let source = std::env::temp_dir().join(format!("suslang-{}.c", std::process::id()));
+    compile_file(input, &source, &mut codegen::C::new(), opt_level, lints);
+    let compiled = CCompiler::new().compile(&source, output);
+    std::fs::remove_file(&source).ok();
+    if let Err(report) = compiled {
+        render_report(&report);
+        std::process::exit(1);
+    }
+}
 
-    drop(module);
-    drop(fs);
+fn run<A: AsRef<Path>>(input: &A, backend: Backends, lints: &Lints) {
+    let file = std::env::temp_dir().join(format!(
+        "suslang-{}.{}",
+        std::process::id(),
+        backend.extension()
+    ));
+    let status = match backend.interpreter() {
+        Some(interpreter) => {
+            compile_file(input, &file, backend.codegen().as_mut(), OptLevel::O0, lints);
+            interpreter.run(&file)
+        }
+        None => {
+            let exe = file.with_extension(std::env::consts::EXE_EXTENSION);
+            compile_exe(input, &exe, OptLevel::O0, lints);
+            let status = std::process::Command::new(&exe).status();
+            std::fs::remove_file(&exe).ok();
+            status.map_err(|source| suslang::toolchain::ToolchainError::Spawn {
+                program: exe.display().to_string(),
+                source,
+            })
+        }
+    };
+    std::fs::remove_file(&file).ok();
+    match status {
+        Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+        Err(report) => {
+            render_report(&report);
+            std::process::exit(1);
+        }
+    }
 }
 
+type DynCodegen = dyn for<'a> Codegen<BufWriter<File>, Linked<'a>>;
+
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum, Debug)]
 enum Backends {
     #[cfg(feature = "backend-c")]
@@ -83,6 +191,48 @@ enum Backends {
     Scm,
 }
 
+impl Backends {
+    fn codegen(self) -> Box<DynCodegen> {
+        match self {
+            #[cfg(feature = "backend-c")]
+            Self::C => Box::new(codegen::C::new()),
+            #[cfg(feature = "backend-js")]
+            Self::Js | Self::Javascript => Box::new(codegen::Js::default()),
+            #[cfg(feature = "backend-python")]
+            Self::Py | Self::Python => Box::new(codegen::Py::new()),
+            #[cfg(feature = "backend-scm")]
+            Self::Scm => Box::new(codegen::Scm),
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            #[cfg(feature = "backend-c")]
+            Self::C => "c",
+            #[cfg(feature = "backend-js")]
+            Self::Js | Self::Javascript => "js",
+            #[cfg(feature = "backend-python")]
+            Self::Py | Self::Python => "py",
+            #[cfg(feature = "backend-scm")]
+            Self::Scm => "scm",
+        }
+    }
+
+    /// Interpreter that runs the generated code, `None` for compiled backends
+    fn interpreter(self) -> Option<Interpreter> {
+        match self {
+            #[cfg(feature = "backend-c")]
+            Self::C => None,
+            #[cfg(feature = "backend-js")]
+            Self::Js | Self::Javascript => Some(Interpreter::Node),
+            #[cfg(feature = "backend-python")]
+            Self::Py | Self::Python => Some(Interpreter::Python),
+            #[cfg(feature = "backend-scm")]
+            Self::Scm => Some(Interpreter::Guile),
+        }
+    }
+}
+
 #[derive(Debug, clap::Parser)]
 struct Arguments {
     input: PathBuf,
@@ -99,12 +249,36 @@ struct Arguments {
     // backend: Option<Backends>,
     #[command(subcommand)]
     subcommand: Subcommands,
+    #[command(flatten)]
+    lints: Lints,
 }
 
 #[derive(Debug, Clone, clap::Subcommand)]
 enum Subcommands {
     Check,
-    Build { output: PathBuf, backend: Backends },
+    Build {
+        output: PathBuf,
+        #[arg(required_unless_present = "emit")]
+        backend: Option<Backends>,
+        /// Write a stage of the compiler instead of code: ast, linked or types as JSON, or ir
+        #[arg(long, conflicts_with_all = ["backend", "exe"])]
+        emit: Option<Stage>,
+        /// Compile the generated C code into an executable with `CC` and `CFLAGS`
+        #[arg(long)]
+        exe: bool,
+        /// Optimization level: 0 keeps the program as written, 1 folds constants and removes dead code
+        #[arg(short = 'O', value_name = "LEVEL", default_value = "0")]
+        opt_level: OptLevel,
+        /// Kind of program emitted by the JS backend: commonjs, esm or browser
+        #[cfg(feature = "backend-js")]
+        #[arg(long, default_value = "commonjs")]
+        js_output: codegen::JsOutput,
+    },
+    /// Build to a temporary file and run it
+    Run {
+        #[arg(long, short)]
+        backend: Backends,
+    },
 }
 
 fn main() {
@@ -114,27 +288,57 @@ fn main() {
     // let helloworld = include_str!("../examples/day1.sus");
     match args.subcommand {
         Subcommands::Check => {
-            check(&args.input);
+            check(&args.input, &args.lints);
             println!("OK");
         },
-        Subcommands::Build { output, backend } => {
-            let mut codegen: Box<dyn Codegen<BufWriter<File>, [Span<Ast>]>> = match backend {
-                #[cfg(feature = "backend-c")]
-                Backends::C => Box::new(codegen::C),
-                #[cfg(feature = "backend-js")]
-                Backends::Js | Backends::Javascript => Box::new(codegen::Js),
-                #[cfg(feature = "backend-python")]
-                Backends::Py | Backends::Python => Box::new(codegen::Py::new()),
-                #[cfg(feature = "backend-scm")]
-                Backends::Scm => Box::new(codegen::Scm),
-            };
-            compile_file(&args.input, &output, codegen.as_mut())
+        Subcommands::Build {
+            output,
+            emit: Some(stage),
+            opt_level,
+            ..
+        } => emit(&args.input, &output, stage, opt_level, &args.lints),
+        Subcommands::Build {
+            output,
+            backend: Some(backend),
+            exe: true,
+            opt_level,
+            ..
+        } => {
+            if backend.interpreter().is_some() {
+                eprintln!("--exe is only supported by the C backend");
+                std::process::exit(2);
+            }
+            compile_exe(&args.input, &output, opt_level, &args.lints)
         }
+        #[cfg(feature = "backend-js")]
+        Subcommands::Build {
+            output,
+            backend: Some(Backends::Js | Backends::Javascript),
+            exe: false,
+            js_output,
+            opt_level,
+            ..
+        } => compile_file(
+            &args.input,
+            &output,
+            &mut codegen::Js::new(js_output),
+            opt_level,
+            &args.lints,
+        ),
+        Subcommands::Build {
+            output,
+            backend: Some(backend),
+            exe: false,
+            opt_level,
+            ..
+        } => compile_file(&args.input, &output, backend.codegen().as_mut(), opt_level, &args.lints),
+        Subcommands::Build { backend: None, .. } => unreachable!("clap requires a backend"),
+        Subcommands::Run { backend } => run(&args.input, backend, &args.lints),
     }
     // if let Some((output, backend)) = args.output.zip(args.backend) {
     //     let mut codegen: Box<dyn Codegen<BufWriter<File>, [Span<Ast>]>> = match backend {
     //         Backends::C => Box::new(codegen::C),
-    //         Backends::Js | Backends::Javascript => Box::new(codegen::Js),
+    //         Backends::Js | Backends::Javascript => Box::new(codegen::Js::default()),
     //         Backends::Py | Backends::Python => Box::new(codegen::Py::new()),
     //         Backends::Scm => Box::new(codegen::Scm),
     //     };
@@ -0,0 +1,249 @@
+//! Mid-level IR: the linked program lowered to basic blocks.
+//!
+//! Every task is a list of blocks ending in an explicit [`Terminator`], every
+//! intermediate value lives in a typed temporary and crewmates are locals, so
+//! scoping and the placement of `eject` are already solved for whoever reads it.
+
+mod lower;
+mod validate;
+
+use std::fmt::{self, Display};
+
+pub use lower::lower;
+pub use validate::{validate, ValidationError};
+
+use crate::{ast::Operator, typecheck::Type};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LocalId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub usize);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub functions: Vec<Function>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub name: String,
+    /// The arguments are the first locals
+    pub params: usize,
+    pub ret: Type,
+    pub locals: Vec<Local>,
+    /// The first block is the entry point
+    pub blocks: Vec<BasicBlock>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Local {
+    pub typ: Type,
+    /// Name of the argument or crewmate, temporaries have none
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    Assign(LocalId, Rvalue),
+    /// Computes a value only for its side effects
+    Eval(Rvalue),
+    /// Ends the life of a local, which isn't read again before it is assigned
+    Kill(LocalId),
+    /// Ends the innermost [`Terminator::Meeting`], when its first block is left
+    EndMeeting,
+    /// Waits for a number of milliseconds
+    Afk(Operand),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rvalue {
+    Use(Operand),
+    Binary(Operator, Operand, Operand),
+    Call(String, Vec<Operand>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Local(LocalId),
+    Const(Constant),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Constant {
+    Number(i32),
+    Bool(bool),
+    String(String),
+}
+
+impl Constant {
+    pub fn typ(&self) -> Type {
+        match self {
+            Self::Number(_) => Type::Number,
+            Self::Bool(_) => Type::Bool,
+            Self::String(_) => Type::String,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Terminator {
+    Goto(BlockId),
+    Branch(Operand, BlockId, BlockId),
+    Return(Option<Operand>),
+    /// End of a task that returns a value without `eject`ing it
+    Unreachable,
+    /// Goes to the first block. Until an [`Instruction::EndMeeting`] or a
+    /// `Return`, a sabotage jumps to the second one with its reason in the local
+    Meeting(BlockId, BlockId, LocalId),
+    /// Leaves for the innermost meeting, or ends the program if there is none
+    Sabotage(Operand),
+    /// Ends the program with a status, 0 for `win` and 1 for `lose`
+    Exit(i32),
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Self::Goto(b) => vec![*b],
+            Self::Branch(_, a, b) | Self::Meeting(a, b, _) => vec![*a, *b],
+            Self::Return(_) | Self::Unreachable | Self::Sabotage(_) | Self::Exit(_) => Vec::new(),
+        }
+    }
+}
+
+impl Function {
+    pub fn local(&self, id: LocalId) -> &Local {
+        &self.locals[id.0]
+    }
+}
+
+impl Display for LocalId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "_{}", self.0)
+    }
+}
+
+impl Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{n}"),
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::String(s) => write!(f, "{s:?}"),
+        }
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Local(l) => write!(f, "{l}"),
+            Self::Const(c) => write!(f, "{c}"),
+        }
+    }
+}
+
+fn comma_separated<T: Display>(f: &mut fmt::Formatter<'_>, items: &[T]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i != 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{item}")?;
+    }
+    Ok(())
+}
+
+impl Display for Rvalue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Use(o) => write!(f, "{o}"),
+            Self::Binary(op, a, b) => write!(f, "{a} {op} {b}"),
+            Self::Call(name, args) => {
+                write!(f, "{name}(")?;
+                comma_separated(f, args)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Assign(l, r) => write!(f, "{l} = {r}"),
+            Self::Eval(r) => write!(f, "{r}"),
+            Self::Kill(l) => write!(f, "kill {l}"),
+            Self::EndMeeting => write!(f, "end meeting"),
+            Self::Afk(ms) => write!(f, "afk {ms}"),
+        }
+    }
+}
+
+impl Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Goto(b) => write!(f, "goto {b}"),
+            Self::Branch(c, a, b) => write!(f, "branch {c}, {a}, {b}"),
+            Self::Return(Some(o)) => write!(f, "return {o}"),
+            Self::Return(None) => write!(f, "return"),
+            Self::Unreachable => write!(f, "unreachable"),
+            Self::Meeting(body, impostor, reason) => {
+                write!(f, "meeting {body}, impostor {reason} in {impostor}")
+            }
+            Self::Sabotage(o) => write!(f, "sabotage {o}"),
+            Self::Exit(status) => write!(f, "exit {status}"),
+        }
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "task {}(", self.name)?;
+        for (i, local) in self.locals[..self.params].iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {:?}", LocalId(i), local.typ)?;
+        }
+        writeln!(f, ") -> {:?} {{", self.ret)?;
+        for (i, local) in self.locals.iter().enumerate() {
+            write!(f, "    let {}: {:?}", LocalId(i), local.typ)?;
+            match &local.name {
+                Some(name) => writeln!(f, " // {name}")?,
+                None => writeln!(f)?,
+            }
+        }
+        for (i, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "  {}:", BlockId(i))?;
+            for instruction in &block.instructions {
+                writeln!(f, "    {instruction}")?;
+            }
+            writeln!(f, "    {}", block.terminator)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, function) in self.functions.iter().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{function}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{parse::spans::Span, Ast, Expression, Operator, Statement, Typ},
+    resolve::{Resolution, SymbolId},
+    typecheck::{ExprTypes, Type},
+};
+
+use super::{
+    BasicBlock, BlockId, Constant, Function, Instruction, Local, LocalId, Operand, Program, Rvalue,
+    Terminator,
+};
+
+/// Where `continue` and `break` go in a loop
+struct Loop {
+    next: BlockId,
+    exit: BlockId,
+    /// Meetings open around the loop, the ones opened inside it end on a jump
+    meetings: usize,
+}
+
+struct Builder<'r> {
+    resolution: &'r Resolution,
+    types: &'r ExprTypes,
+    ret: Type,
+    locals: Vec<Local>,
+    symbols: HashMap<SymbolId, LocalId>,
+    blocks: Vec<(Vec<Instruction>, Option<Terminator>)>,
+    /// Block being filled, `None` right after an `eject`
+    current: Option<BlockId>,
+    /// Meetings open around the statement being lowered
+    meetings: usize,
+    /// Loops around the statement being lowered, innermost last
+    loops: Vec<Loop>,
+}
+
+impl<'r> Builder<'r> {
+    fn local(&mut self, typ: Type, name: Option<String>) -> LocalId {
+        self.locals.push(Local { typ, name });
+        LocalId(self.locals.len() - 1)
+    }
+
+    fn declare(&mut self, name: &Span<String>, typ: Typ) -> LocalId {
+        let local = self.local(typ.into(), Some(name.extra.data.clone()));
+        let symbol = self.resolution.get(name).expect("linked programs are resolved");
+        self.symbols.insert(symbol, local);
+        local
+    }
+
+    fn symbol<T>(&self, span: &Span<T>) -> LocalId {
+        let symbol = self.resolution.get(span).expect("linked programs are resolved");
+        self.symbols[&symbol]
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push((Vec::new(), None));
+        BlockId(self.blocks.len() - 1)
+    }
+
+    /// Block to add instructions to, a new one nothing jumps to if the last was terminated
+    fn current(&mut self) -> BlockId {
+        match self.current {
+            Some(block) => block,
+            None => {
+                let block = self.new_block();
+                self.current = Some(block);
+                block
+            }
+        }
+    }
+
+    fn push(&mut self, instruction: Instruction) {
+        let block = self.current();
+        self.blocks[block.0].0.push(instruction);
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        let block = self.current();
+        self.blocks[block.0].1 = Some(terminator);
+        self.current = None;
+    }
+
+    /// Jumps to `target` from the current block, unless it already ended with an `eject`
+    fn goto(&mut self, target: BlockId) {
+        if self.current.is_some() {
+            self.terminate(Terminator::Goto(target));
+        }
+    }
+
+    fn operand(&mut self, e: &Span<Expression>) -> Operand {
+        match &e.extra.data {
+            Expression::NumLit(n) => Operand::Const(Constant::Number(*n)),
+            Expression::BoolLit(b) => Operand::Const(Constant::Bool(*b)),
+            Expression::StringLit(s) => Operand::Const(Constant::String(s.clone())),
+            Expression::Variable(_) => Operand::Local(self.symbol(e)),
+            Expression::Call(_, _) | Expression::Operation(_, _, _) => {
+                let typ = self.types.get(e).cloned().expect("linked programs are typechecked");
+                let value = self.rvalue(e);
+                let temp = self.local(typ, None);
+                self.push(Instruction::Assign(temp, value));
+                Operand::Local(temp)
+            }
+        }
+    }
+
+    fn rvalue(&mut self, e: &Span<Expression>) -> Rvalue {
+        match &e.extra.data {
+            Expression::Call(name, args) => Rvalue::Call(
+                name.extra.data.clone(),
+                args.iter().map(|arg| self.operand(arg)).collect(),
+            ),
+            Expression::Operation(op, a, b) => {
+                Rvalue::Binary(op.extra.data, self.operand(a), self.operand(b))
+            }
+            Expression::NumLit(_)
+            | Expression::BoolLit(_)
+            | Expression::StringLit(_)
+            | Expression::Variable(_) => Rvalue::Use(self.operand(e)),
+        }
+    }
+
+    /// Lowers the statements of `body` into the current block and the ones after it
+    fn body(&mut self, body: &[Span<Statement>]) {
+        for line in body {
+            match &line.extra.data {
+                Statement::If(cond, then, otherwise) => {
+                    let cond = self.operand(cond);
+                    let then_block = self.new_block();
+                    let else_block = otherwise.as_ref().map(|_| self.new_block());
+                    let join = self.new_block();
+                    self.terminate(Terminator::Branch(cond, then_block, else_block.unwrap_or(join)));
+                    self.current = Some(then_block);
+                    self.body(&then.extra.data);
+                    self.goto(join);
+                    if let (Some(block), Some(otherwise)) = (else_block, otherwise) {
+                        self.current = Some(block);
+                        self.body(&otherwise.extra.data);
+                        self.goto(join);
+                    }
+                    self.current = Some(join);
+                }
+                Statement::While(cond, body) => {
+                    let header = self.new_block();
+                    self.goto(header);
+                    self.current = Some(header);
+                    let cond = self.operand(cond);
+                    let body_block = self.new_block();
+                    let exit = self.new_block();
+                    self.terminate(Terminator::Branch(cond, body_block, exit));
+                    self.current = Some(body_block);
+                    self.repeat(&body.extra.data, header, exit);
+                    self.current = Some(exit);
+                }
+                Statement::For(name, start, end, body) => {
+                    // the bounds are copied, so the body can't move them
+                    let counter = self.local(Type::Number, None);
+                    let start = self.rvalue(start);
+                    self.push(Instruction::Assign(counter, start));
+                    let last = self.local(Type::Number, None);
+                    let end = self.rvalue(end);
+                    self.push(Instruction::Assign(last, end));
+                    let crewmate = self.declare(name, Typ::Num);
+                    let header = self.new_block();
+                    self.goto(header);
+                    self.current = Some(header);
+                    let cond = self.local(Type::Bool, None);
+                    let below = Rvalue::Binary(
+                        Operator::Lt,
+                        Operand::Local(counter),
+                        Operand::Local(last),
+                    );
+                    self.push(Instruction::Assign(cond, below));
+                    let body_block = self.new_block();
+                    let next = self.new_block();
+                    let exit = self.new_block();
+                    self.terminate(Terminator::Branch(Operand::Local(cond), body_block, exit));
+                    self.current = Some(body_block);
+                    self.push(Instruction::Assign(crewmate, Rvalue::Use(Operand::Local(counter))));
+                    self.repeat(&body.extra.data, next, exit);
+                    self.current = Some(next);
+                    let one = Operand::Const(Constant::Number(1));
+                    let add = Rvalue::Binary(Operator::Add, Operand::Local(counter), one);
+                    self.push(Instruction::Assign(counter, add));
+                    self.goto(header);
+                    self.current = Some(exit);
+                }
+                Statement::Break | Statement::Continue => {
+                    let Some(turn) = self.loops.last() else {
+                        unreachable!("typechecked programs only jump inside loops")
+                    };
+                    let target = match line.extra.data {
+                        Statement::Break => turn.exit,
+                        _ => turn.next,
+                    };
+                    for _ in turn.meetings..self.meetings {
+                        self.push(Instruction::EndMeeting);
+                    }
+                    self.terminate(Terminator::Goto(target));
+                    return;
+                }
+                Statement::Return(value) => {
+                    let value = value.as_ref().map(|value| self.operand(value));
+                    self.terminate(Terminator::Return(value));
+                    // the rest of the block is never run
+                    return;
+                }
+                Statement::Expr(e) => {
+                    let value = self.rvalue(e);
+                    self.push(Instruction::Eval(value));
+                }
+                Statement::Declare(name, typ) => {
+                    self.declare(name, typ.extra.data);
+                }
+                Statement::Define(name, e) => {
+                    let local = self.symbol(name);
+                    let value = self.rvalue(e);
+                    self.push(Instruction::Assign(local, value));
+                }
+                Statement::Kill(name) => {
+                    let local = self.symbol(name);
+                    self.push(Instruction::Kill(local));
+                }
+                Statement::Try(body, name, typ, catch) => {
+                    let reason = self.declare(name, typ.extra.data);
+                    let body_block = self.new_block();
+                    let impostor = self.new_block();
+                    let join = self.new_block();
+                    self.terminate(Terminator::Meeting(body_block, impostor, reason));
+                    self.current = Some(body_block);
+                    self.meetings += 1;
+                    self.body(&body.extra.data);
+                    self.meetings -= 1;
+                    if self.current.is_some() {
+                        self.push(Instruction::EndMeeting);
+                    }
+                    self.goto(join);
+                    self.current = Some(impostor);
+                    self.body(&catch.extra.data);
+                    self.goto(join);
+                    self.current = Some(join);
+                }
+                Statement::Sabotage(reason) => {
+                    let reason = self.operand(reason);
+                    self.terminate(Terminator::Sabotage(reason));
+                    return;
+                }
+                Statement::Win | Statement::Lose => {
+                    let status = i32::from(line.extra.data == Statement::Lose);
+                    self.terminate(Terminator::Exit(status));
+                    return;
+                }
+                Statement::Afk(ms) => {
+                    let ms = self.operand(ms);
+                    self.push(Instruction::Afk(ms));
+                }
+            }
+        }
+    }
+
+    /// Lowers the body of a loop into the current block, going to `next` after it
+    fn repeat(&mut self, body: &[Span<Statement>], next: BlockId, exit: BlockId) {
+        self.loops.push(Loop {
+            next,
+            exit,
+            meetings: self.meetings,
+        });
+        self.body(body);
+        self.loops.pop();
+        self.goto(next);
+    }
+
+    fn finish(mut self, name: String, params: usize) -> Function {
+        if self.current.is_some() {
+            self.terminate(if self.ret == Type::Void {
+                Terminator::Return(None)
+            } else {
+                Terminator::Unreachable
+            });
+        }
+        Function {
+            name,
+            params,
+            ret: self.ret,
+            locals: self.locals,
+            blocks: self
+                .blocks
+                .into_iter()
+                .map(|(instructions, terminator)| BasicBlock {
+                    instructions,
+                    terminator: terminator.expect("every block is terminated"),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Lowers a linked program, using the resolution and types of the module graph it
+/// comes from
+pub fn lower(ast: &[Span<Ast>], resolution: &Resolution, types: &ExprTypes) -> Program {
+    let functions = ast
+        .iter()
+        .filter_map(|item| match &item.extra.data {
+            Ast::Func(name, ret, args, body) => {
+                let mut builder = Builder {
+                    resolution,
+                    types,
+                    ret: ret.extra.data.into(),
+                    locals: Vec::new(),
+                    symbols: HashMap::new(),
+                    blocks: Vec::new(),
+                    current: None,
+                    meetings: 0,
+                    loops: Vec::new(),
+                };
+                for arg in args {
+                    let (name, typ) = &arg.extra.data;
+                    builder.declare(name, typ.extra.data);
+                }
+                builder.current();
+                builder.body(&body.extra.data);
+                Some(builder.finish(name.extra.data.clone(), args.len()))
+            }
+            Ast::Mod(_) | Ast::Import(_) => None,
+        })
+        .collect();
+    Program { functions }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        mir::{validate, Instruction, LocalId, Program, Terminator},
+        typecheck::Type,
+        Session,
+    };
+
+    fn lower(program: &str) -> Program {
+        let mut session = Session::new();
+        session.add_source("main.sus", program);
+        session.ir().unwrap()
+    }
+
+    #[test]
+    fn loops_and_conditions() {
+        let program = lower(
+            "task ඬ with ➤ number\nචcrewmate i: numberඞ\nචmake i 0ඞ\nචwhile < i 10\nචචsus? == % i 2 0\nචචචcomplete report with \"%d\" and iඞ\nචචmake i + i 1ඞ\nචeject 0ඞ\n",
+        );
+        assert!(validate(&program).is_ok(), "{program}");
+        let main = &program.functions[0];
+        assert_eq!(main.local(LocalId(0)).name.as_deref(), Some("i"));
+        // entry, loop header, body, exit, then branch and the join after it
+        assert_eq!(main.blocks.len(), 6);
+        assert!(matches!(
+            main.blocks.last().unwrap().terminator,
+            Terminator::Goto(_)
+        ));
+        assert!(main
+            .blocks
+            .iter()
+            .any(|b| matches!(b.terminator, Terminator::Return(Some(_)))));
+        assert!(main.locals.iter().any(|l| l.typ == Type::Bool && l.name.is_none()));
+    }
+
+    #[test]
+    fn code_after_eject_is_dropped() {
+        let program = lower("task ඬ with ➤ void\nචejectඞ\nචcomplete report with \"dead\"ඞ\n");
+        let main = &program.functions[0];
+        assert_eq!(main.blocks.len(), 1);
+        assert!(main.blocks[0].instructions.is_empty());
+        assert_eq!(main.blocks[0].terminator, Terminator::Return(None));
+    }
+
+    #[test]
+    fn shadowed_crewmates_are_different_locals() {
+        let program = lower(
+            "task ඬ with ➤ number\nචcrewmate x: numberඞ\nචsus? == 1 1\nචචcrewmate x: stringඞ\nචචmake x \"a\"ඞ\nචmake x 1ඞ\nචeject xඞ\n",
+        );
+        assert!(validate(&program).is_ok(), "{program}");
+        let xs: Vec<_> = program.functions[0]
+            .locals
+            .iter()
+            .filter(|l| l.name.as_deref() == Some("x"))
+            .map(|l| &l.typ)
+            .collect();
+        assert_eq!(xs, [&Type::Number, &Type::String]);
+    }
+
+    #[test]
+    fn meetings_jump_to_their_impostor() {
+        let program = lower(
+            "task ඬ with ➤ void\nචemergency meeting\nචචsabotage \"a\"ඞ\nචimpostor red: string\nචචcomplete report with \"%s\" and redඞ\n",
+        );
+        assert!(validate(&program).is_ok(), "{program}");
+        let main = &program.functions[0];
+        let Terminator::Meeting(body, impostor, reason) = main.blocks[0].terminator else {
+            panic!("{program}")
+        };
+        assert!(matches!(main.blocks[body.0].terminator, Terminator::Sabotage(_)));
+        assert_eq!(main.local(reason).name.as_deref(), Some("red"));
+        assert_eq!(main.blocks[impostor.0].instructions.len(), 1);
+    }
+
+    #[test]
+    fn jumps_out_of_a_loop_end_its_meetings() {
+        let program = lower(
+            "task ඬ with ➤ void\nචfor red in 0 to 3\nචචemergency meeting\nචචචsus? == red 1\nචචචචcontinueඞ\nචචචbreakඞ\nචචimpostor pink: string\nචචචcomplete report with \"%s\" and pinkඞ\n",
+        );
+        assert!(validate(&program).is_ok(), "{program}");
+        let jumps: Vec<_> = program.functions[0]
+            .blocks
+            .iter()
+            .filter(|b| b.instructions.last() == Some(&Instruction::EndMeeting))
+            .map(|b| &b.terminator)
+            .collect();
+        assert_eq!(jumps.len(), 2, "{program}");
+        assert!(jumps.iter().all(|t| matches!(t, Terminator::Goto(_))));
+    }
+}
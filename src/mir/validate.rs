@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::{
+    ast::Operator,
+    typecheck::{builtins, Type},
+};
+
+use super::{BlockId, Function, Instruction, LocalId, Operand, Program, Rvalue, Terminator};
+
+/// The IR breaks one of its rules, which is a bug of the compiler
+#[derive(Error, Debug, Diagnostic, Clone, PartialEq, Eq)]
+#[error("Invalid IR in task `{function}`, {block}: {message}")]
+#[diagnostic(code(suslang::ir::invalid))]
+pub struct ValidationError {
+    pub function: String,
+    pub block: BlockId,
+    pub message: String,
+}
+
+struct Validator<'p> {
+    function: &'p Function,
+    block: BlockId,
+    signatures: &'p HashMap<&'p str, (Vec<Type>, Type)>,
+    errors: Vec<ValidationError>,
+}
+
+impl Validator<'_> {
+    fn error(&mut self, message: String) {
+        self.errors.push(ValidationError {
+            function: self.function.name.clone(),
+            block: self.block,
+            message,
+        });
+    }
+
+    fn local(&mut self, local: LocalId) -> Option<Type> {
+        let typ = self.function.locals.get(local.0).map(|l| l.typ.clone());
+        if typ.is_none() {
+            self.error(format!("{local} is not declared"));
+        }
+        typ
+    }
+
+    fn operand(&mut self, operand: &Operand) -> Option<Type> {
+        match operand {
+            Operand::Local(local) => self.local(*local),
+            Operand::Const(c) => Some(c.typ()),
+        }
+    }
+
+    fn expect(&mut self, what: &dyn std::fmt::Display, found: Option<Type>, expected: &Type) {
+        match found {
+            Some(found) if found != *expected => {
+                self.error(format!("{what} is {found:?} instead of {expected:?}"))
+            }
+            _ => (),
+        }
+    }
+
+    fn rvalue(&mut self, rvalue: &Rvalue) -> Option<Type> {
+        match rvalue {
+            Rvalue::Use(o) => self.operand(o),
+            Rvalue::Binary(op, a, b) => {
+                let a_type = self.operand(a);
+                let b_type = self.operand(b);
+                match op {
+                    Operator::Add | Operator::Sub | Operator::Mod => {
+                        self.expect(a, a_type, &Type::Number);
+                        self.expect(b, b_type, &Type::Number);
+                        Some(Type::Number)
+                    }
+                    Operator::GEt | Operator::Lt => {
+                        self.expect(a, a_type, &Type::Number);
+                        self.expect(b, b_type, &Type::Number);
+                        Some(Type::Bool)
+                    }
+                    Operator::Eq => {
+                        if let Some(a_type) = a_type {
+                            self.expect(b, b_type, &a_type);
+                        }
+                        Some(Type::Bool)
+                    }
+                }
+            }
+            Rvalue::Call(name, args) if name == "report" => {
+                if !matches!(args.first().map(|a| self.operand(a)), Some(Some(Type::String))) {
+                    self.error("report needs a string format".into());
+                }
+                for arg in args.iter().skip(1) {
+                    self.operand(arg);
+                }
+                Some(Type::Void)
+            }
+            Rvalue::Call(name, args) => {
+                let found: Vec<_> = args.iter().map(|a| self.operand(a)).collect();
+                let Some((params, ret)) = self.signatures.get(name.as_str()) else {
+                    self.error(format!("call to unknown task `{name}`"));
+                    return None;
+                };
+                if params.len() != args.len() {
+                    self.error(format!(
+                        "`{name}` takes {} arguments but got {}",
+                        params.len(),
+                        args.len()
+                    ));
+                }
+                for ((arg, found), expected) in args.iter().zip(found).zip(params) {
+                    self.expect(arg, found, expected);
+                }
+                Some(ret.clone())
+            }
+        }
+    }
+
+    fn target(&mut self, block: BlockId) {
+        if block.0 >= self.function.blocks.len() {
+            self.error(format!("jump to {block}, which does not exist"));
+        }
+    }
+
+    fn function(&mut self) {
+        let function = self.function;
+        if function.params > function.locals.len() {
+            self.error(format!("{} arguments but only {} locals", function.params, function.locals.len()));
+        }
+        if function.blocks.is_empty() {
+            self.error("no entry block".into());
+        }
+        for (i, block) in function.blocks.iter().enumerate() {
+            self.block = BlockId(i);
+            for instruction in &block.instructions {
+                match instruction {
+                    Instruction::Assign(local, rvalue) => {
+                        let found = self.rvalue(rvalue);
+                        if let Some(expected) = self.local(*local) {
+                            self.expect(&format!("value assigned to {local}"), found, &expected);
+                        }
+                    }
+                    Instruction::Eval(rvalue) => {
+                        self.rvalue(rvalue);
+                    }
+                    Instruction::Kill(local) => {
+                        self.local(*local);
+                    }
+                    Instruction::EndMeeting => (),
+                    Instruction::Afk(ms) => {
+                        let found = self.operand(ms);
+                        self.expect(ms, found, &Type::Number);
+                    }
+                }
+            }
+            match &block.terminator {
+                Terminator::Goto(target) => self.target(*target),
+                Terminator::Branch(cond, a, b) => {
+                    let found = self.operand(cond);
+                    self.expect(cond, found, &Type::Bool);
+                    self.target(*a);
+                    self.target(*b);
+                }
+                Terminator::Return(Some(value)) => {
+                    let found = self.operand(value);
+                    self.expect(value, found, &function.ret);
+                }
+                Terminator::Return(None) => {
+                    if function.ret != Type::Void {
+                        self.error(format!("return without a value from a task returning {:?}", function.ret));
+                    }
+                }
+                Terminator::Unreachable | Terminator::Exit(_) => (),
+                Terminator::Meeting(body, impostor, reason) => {
+                    self.target(*body);
+                    self.target(*impostor);
+                    let found = self.local(*reason);
+                    self.expect(&format!("reason {reason}"), found, &Type::String);
+                }
+                Terminator::Sabotage(reason) => {
+                    let found = self.operand(reason);
+                    self.expect(reason, found, &Type::String);
+                }
+            }
+        }
+    }
+}
+
+/// Checks that every local and block used exists and that every value has the
+/// type expected where it is used
+pub fn validate(program: &Program) -> Result<(), Vec<ValidationError>> {
+    let mut signatures: HashMap<&str, (Vec<Type>, Type)> = builtins()
+        .filter_map(|(name, t)| match t {
+            Type::Function(args, ret) => Some((name, (args, *ret))),
+            _ => None,
+        })
+        .collect();
+    for function in &program.functions {
+        let params = function.locals[..function.params.min(function.locals.len())]
+            .iter()
+            .map(|l| l.typ.clone())
+            .collect();
+        signatures.insert(&function.name, (params, function.ret.clone()));
+    }
+
+    let mut errors = Vec::new();
+    for function in &program.functions {
+        let mut validator = Validator {
+            function,
+            block: BlockId(0),
+            signatures: &signatures,
+            errors: Vec::new(),
+        };
+        validator.function();
+        errors.append(&mut validator.errors);
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        mir::{
+            BasicBlock, BlockId, Constant, Function, Instruction, Local, LocalId, Operand, Program,
+            Rvalue, Terminator,
+        },
+        typecheck::Type,
+    };
+
+    #[test]
+    fn finds_broken_programs() {
+        let program = Program {
+            functions: vec![Function {
+                name: "f".into(),
+                params: 0,
+                ret: Type::Number,
+                locals: vec![Local {
+                    typ: Type::Bool,
+                    name: None,
+                }],
+                blocks: vec![BasicBlock {
+                    instructions: vec![Instruction::Assign(
+                        LocalId(0),
+                        Rvalue::Call("len".into(), vec![Operand::Const(Constant::Number(1))]),
+                    )],
+                    terminator: Terminator::Branch(Operand::Local(LocalId(0)), BlockId(0), BlockId(3)),
+                }],
+            }],
+        };
+        let errors = super::validate(&program).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "1 is Number instead of String",
+                "value assigned to _0 is Number instead of Bool",
+                "jump to bb3, which does not exist",
+            ]
+        );
+    }
+}
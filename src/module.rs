@@ -43,7 +43,9 @@ impl<'a> Module<'a> {
         files: &'b mut Filesystem<'a>,
         path: ModuleUsePath,
     ) -> Result<Self, LoadError<'a, Span<'a>>> {
-        let (file, contents) = files.load(file)?;
+        let (file, contents) = files
+            .load(file.clone())
+            .map_err(|e| LoadError::IOError(file, e))?;
         let s = load_file_str(file, contents);
         let (_, items) = parse_items::<ParseError<_>>(s)?;
         let mut submodules = HashMap::new();
@@ -55,7 +57,7 @@ impl<'a> Module<'a> {
                 } else {
                     file.with_extension("").join(&mod_path)
                 };
-                if !mod_path.is_file() {
+                if !files.exists(&mod_path) {
                     return Err(LoadError::ModuleFileNotFound(
                         name.clone().map(|_| mod_path),
                     ));
@@ -131,7 +133,7 @@ impl<'a> Module<'a> {
     }
 
     pub fn iter<'b>(&'b self) -> impl Iterator<Item = &'b Module<'a>> {
-        std::iter::once_with(move || self).chain(self.submodules.values().flat_map(|m| m.iter()).collect::<Vec<_>>().into_iter())
+        std::iter::once_with(move || self).chain(self.submodules.values().flat_map(|m| m.iter()).collect::<Vec<_>>())
     }
 
     pub fn print_tree(&self) {
@@ -148,17 +150,11 @@ impl<'a> Module<'a> {
 
 #[derive(Debug)]
 pub enum LoadError<'a, T> {
-    IOError(std::io::Error),
+    IOError(PathBuf, std::io::Error),
     ParseError(nom::Err<ParseError<T>>),
     ModuleFileNotFound(Span<'a, PathBuf>),
 }
 
-impl<'a, T> From<std::io::Error> for LoadError<'a, T> {
-    fn from(value: std::io::Error) -> Self {
-        Self::IOError(value)
-    }
-}
-
 impl<'a, T> From<nom::Err<ParseError<T>>> for LoadError<'a, T> {
     fn from(value: nom::Err<ParseError<T>>) -> Self {
         Self::ParseError(value)
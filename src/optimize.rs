@@ -0,0 +1,345 @@
+//! Optimizations of the linked program.
+//!
+//! They work on the AST rather than on [`crate::mir`] so that every backend
+//! benefits from them. Numbers are folded with [`crate::semantics`], so the
+//! optimized program computes the same values, and a modulo by zero is left as
+//! written to eject at runtime.
+
+use std::collections::HashMap;
+
+use crate::{
+    ast::{
+        parse::spans::{MapExt, Span},
+        visit::{fold_block, fold_expression, walk_block, walk_expression, walk_statement, Fold, Visit},
+        Ast, Block, Expression, Operator, Statement,
+    },
+    linker::Linked,
+    resolve::{Location, Resolution, SymbolId, SymbolKind},
+    semantics,
+};
+
+/// How hard `build` tries to simplify the program, given with `-O`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum OptLevel {
+    /// The program as written
+    #[default]
+    O0,
+    /// Constants folded and propagated, dead code removed
+    O1,
+}
+
+impl std::str::FromStr for OptLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(Self::O0),
+            "1" => Ok(Self::O1),
+            _ => Err(format!("unknown optimization level `{s}`, expected 0 or 1")),
+        }
+    }
+}
+
+fn as_bool(e: &Span<Expression>) -> Option<bool> {
+    match e.extra.data {
+        Expression::BoolLit(b) => Some(b),
+        _ => None,
+    }
+}
+
+/// Whether a `for` from `start` to `end` is known to run no turn
+fn is_empty_range(start: &Span<Expression>, end: &Span<Expression>) -> bool {
+    match (&start.extra.data, &end.extra.data) {
+        (Expression::NumLit(start), Expression::NumLit(end)) => start >= end,
+        _ => false,
+    }
+}
+
+fn is_constant(e: &Expression) -> bool {
+    matches!(
+        e,
+        Expression::NumLit(_) | Expression::BoolLit(_) | Expression::StringLit(_)
+    )
+}
+
+/// Value of an operation over constants, `None` if it has to be computed at runtime
+fn fold_operation<'a>(op: Operator, a: &Expression<'a>, b: &Expression<'a>) -> Option<Expression<'a>> {
+    use Expression::{BoolLit, NumLit};
+    Some(match (op, a, b) {
+        (Operator::Add, NumLit(a), NumLit(b)) => NumLit(semantics::add(*a, *b)),
+        (Operator::Sub, NumLit(a), NumLit(b)) => NumLit(semantics::sub(*a, *b)),
+        (Operator::Mod, NumLit(a), NumLit(b)) => NumLit(semantics::rem(*a, *b).ok()?),
+        (Operator::Lt, NumLit(a), NumLit(b)) => BoolLit(a < b),
+        (Operator::GEt, NumLit(a), NumLit(b)) => BoolLit(a >= b),
+        (Operator::Eq, NumLit(a), NumLit(b)) => BoolLit(a == b),
+        (Operator::Eq, BoolLit(a), BoolLit(b)) => BoolLit(a == b),
+        // strings are compared as the backends do, which is not always by contents
+        _ => return None,
+    })
+}
+
+/// Value of an expression made only of constants, if it can be computed without
+/// running the program
+pub fn evaluate<'a>(e: &Expression<'a>) -> Option<Expression<'a>> {
+    match e {
+        Expression::Operation(op, a, b) => {
+            fold_operation(op.extra.data, &evaluate(&a.extra.data)?, &evaluate(&b.extra.data)?)
+        }
+        e if is_constant(e) => Some(e.clone()),
+        _ => None,
+    }
+}
+
+/// Where every crewmate is declared, assigned and first read
+struct Assignments<'r, 'a> {
+    resolution: &'r Resolution,
+    blocks: Vec<Location>,
+    declared: HashMap<SymbolId, Location>,
+    /// Block, offset and constant value of every `make`
+    defined: HashMap<SymbolId, Vec<(Location, usize, Option<Expression<'a>>)>>,
+    first_use: HashMap<SymbolId, usize>,
+}
+
+impl<'r, 'a> Assignments<'r, 'a> {
+    /// Crewmates that always hold the same constant when they are read: made once,
+    /// in the block that declares them, before any use
+    fn constants(items: &[Span<'a, Ast<'a>>], resolution: &'r Resolution) -> HashMap<SymbolId, Expression<'a>> {
+        let mut assignments = Self {
+            resolution,
+            blocks: Vec::new(),
+            declared: HashMap::new(),
+            defined: HashMap::new(),
+            first_use: HashMap::new(),
+        };
+        for item in items {
+            assignments.visit_item(item);
+        }
+        assignments
+            .defined
+            .into_iter()
+            .filter_map(|(symbol, definitions)| match definitions.as_slice() {
+                [(block, offset, Some(value))]
+                    if resolution.symbol(symbol).kind == SymbolKind::Crewmate
+                        && assignments.declared.get(&symbol) == Some(block)
+                        && assignments.first_use.get(&symbol).is_none_or(|used| used > offset) =>
+                {
+                    Some((symbol, value.clone()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl<'a> Visit<'a> for Assignments<'_, 'a> {
+    fn visit_block(&mut self, block: &Span<'a, Block<'a>>) {
+        self.blocks.push(Location::of(block));
+        walk_block(self, block);
+        self.blocks.pop();
+    }
+
+    fn visit_statement(&mut self, statement: &Span<'a, Statement<'a>>) {
+        let block = self.blocks.last().cloned().expect("statements are in blocks");
+        match &statement.extra.data {
+            Statement::Declare(name, _) => {
+                if let Some(symbol) = self.resolution.get(name) {
+                    self.declared.insert(symbol, block);
+                }
+            }
+            Statement::Define(name, value) => {
+                if let Some(symbol) = self.resolution.get(name) {
+                    let value = is_constant(&value.extra.data).then(|| value.extra.data.clone());
+                    self.defined
+                        .entry(symbol)
+                        .or_default()
+                        .push((block, name.location_offset(), value));
+                }
+            }
+            _ => (),
+        }
+        walk_statement(self, statement)
+    }
+
+    fn visit_expression(&mut self, expression: &Span<'a, Expression<'a>>) {
+        if let Expression::Variable(_) = expression.extra.data {
+            if let Some(symbol) = self.resolution.get(expression) {
+                let offset = expression.location_offset();
+                self.first_use
+                    .entry(symbol)
+                    .and_modify(|first| *first = offset.min(*first))
+                    .or_insert(offset);
+            }
+        }
+        walk_expression(self, expression)
+    }
+}
+
+struct Optimizer<'r, 'a> {
+    resolution: &'r Resolution,
+    /// Crewmates replaced by their value, whose declaration and `make` are removed
+    constants: HashMap<SymbolId, Expression<'a>>,
+}
+
+impl<'a> Optimizer<'_, 'a> {
+    fn is_constant<T>(&self, name: &Span<T>) -> bool {
+        self.resolution
+            .get(name)
+            .is_some_and(|symbol| self.constants.contains_key(&symbol))
+    }
+
+    /// What is left of a statement once conditions and constant crewmates are known
+    fn prune(&self, statement: Span<'a, Statement<'a>>) -> Vec<Span<'a, Statement<'a>>> {
+        let mut inlined = Vec::new();
+        // a block that declares crewmates keeps its own scope
+        let mut inline = |cond: Span<'a, Expression<'a>>, block: Span<'a, Block<'a>>| {
+            if block
+                .extra
+                .data
+                .iter()
+                .any(|s| matches!(s.extra.data, Statement::Declare(_, _)))
+            {
+                Some(Statement::If(cond.map(|_| Expression::BoolLit(true)), block, None))
+            } else {
+                inlined.extend(block.extra.data);
+                None
+            }
+        };
+        let statement = statement.map(|statement| match statement {
+            Statement::If(cond, then, otherwise) => match (as_bool(&cond), otherwise) {
+                (Some(true), _) => inline(cond, then),
+                (Some(false), Some(otherwise)) => inline(cond, otherwise),
+                (Some(false), None) => None,
+                (None, otherwise) => Some(Statement::If(cond, then, otherwise)),
+            },
+            Statement::While(cond, _) if as_bool(&cond) == Some(false) => None,
+            Statement::For(_, start, end, _) if is_empty_range(&start, &end) => None,
+            Statement::Declare(name, _) | Statement::Define(name, _) | Statement::Kill(name)
+                if self.is_constant(&name) =>
+            {
+                None
+            }
+            statement => Some(statement),
+        });
+        if statement.extra.data.is_some() {
+            inlined.push(statement.map(|s| s.expect("checked above")));
+        }
+        inlined
+    }
+}
+
+impl<'a> Fold<'a> for Optimizer<'_, 'a> {
+    fn fold_block(&mut self, block: Span<'a, Block<'a>>) -> Span<'a, Block<'a>> {
+        fold_block(self, block).map(|statements| {
+            let mut pruned = Vec::with_capacity(statements.len());
+            for statement in statements {
+                for statement in self.prune(statement) {
+                    let jumps = matches!(
+                        statement.extra.data,
+                        Statement::Return(_) | Statement::Break | Statement::Continue
+                    );
+                    pruned.push(statement);
+                    // the rest of the block is never run
+                    if jumps {
+                        return pruned;
+                    }
+                }
+            }
+            pruned
+        })
+    }
+
+    fn fold_expression(&mut self, expression: Span<'a, Expression<'a>>) -> Span<'a, Expression<'a>> {
+        let expression = match &expression.extra.data {
+            Expression::Variable(_) => match self.resolution.get(&expression) {
+                Some(symbol) if self.constants.contains_key(&symbol) => {
+                    let value = self.constants[&symbol].clone();
+                    return expression.map(|_| value);
+                }
+                _ => return expression,
+            },
+            _ => fold_expression(self, expression),
+        };
+        let folded = match &expression.extra.data {
+            Expression::Operation(op, a, b) => {
+                fold_operation(op.extra.data, &a.extra.data, &b.extra.data)
+            }
+            _ => None,
+        };
+        match folded {
+            Some(value) => expression.map(|_| value),
+            None => expression,
+        }
+    }
+}
+
+/// Optimizes a linked program
+pub fn optimize(program: Linked, level: OptLevel) -> Linked {
+    if level == OptLevel::O0 {
+        return program;
+    }
+    let Linked { items, resolution } = program;
+    let mut optimizer = Optimizer {
+        resolution: &resolution,
+        constants: HashMap::new(),
+    };
+    let mut items: Vec<_> = items.into_iter().map(|item| optimizer.fold_item(item)).collect();
+    // propagating a crewmate can make the value of another one constant
+    loop {
+        let constants = Assignments::constants(&items, &resolution);
+        if constants.is_empty() {
+            return Linked { items, resolution };
+        }
+        optimizer.constants.extend(constants);
+        items = items.into_iter().map(|item| optimizer.fold_item(item)).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        mir::{Constant, Instruction, Operand, Program, Rvalue, Terminator},
+        Session,
+    };
+
+    use super::OptLevel;
+
+    fn ir(program: &str, level: OptLevel) -> Program {
+        let mut session = Session::new();
+        session.add_source("main.sus", program).opt_level(level);
+        session.ir().unwrap()
+    }
+
+    #[test]
+    fn removes_constant_branches() {
+        let program = "task ඬ with ➤ number\nචsus? < 2 5\nචචcomplete report with \"%d\" and + 40 2ඞ\nචclean?\nචචcomplete report with \"no\"ඞ\nචwhile == 1 2\nචචcomplete report with \"loop\"ඞ\nචeject 0ඞ\n";
+        assert_eq!(ir(program, OptLevel::O0).functions[0].blocks.len(), 7);
+        let main = &ir(program, OptLevel::O1).functions[0];
+        assert_eq!(main.blocks.len(), 1, "{main}");
+        assert_eq!(
+            main.blocks[0].instructions,
+            [Instruction::Eval(Rvalue::Call(
+                "report".into(),
+                vec![
+                    Operand::Const(Constant::String("%d".into())),
+                    Operand::Const(Constant::Number(42))
+                ]
+            ))]
+        );
+    }
+
+    #[test]
+    fn propagates_crewmates_made_once() {
+        let program = "task ඬ with ➤ number\nචcrewmate x: numberඞ\nචmake x + 1 2ඞ\nචcrewmate y: numberඞ\nචmake y + x 1ඞ\nචcrewmate i: numberඞ\nචmake i 0ඞ\nචmake i + i yඞ\nචeject iඞ\nචcomplete report with \"dead\"ඞ\n";
+        let main = &ir(program, OptLevel::O1).functions[0];
+        let names: Vec<_> = main.locals.iter().filter_map(|l| l.name.as_deref()).collect();
+        assert_eq!(names, ["i"], "{main}");
+        assert!(main.to_string().contains("_0 + 4"), "{main}");
+        assert!(matches!(main.blocks[0].terminator, Terminator::Return(_)));
+    }
+
+    #[test]
+    fn keeps_runtime_ejections() {
+        let main = &ir("task ඬ with ➤ number\nචeject % 1 0ඞ\n", OptLevel::O1).functions[0];
+        assert!(main.to_string().contains("1 % 0"), "{main}");
+    }
+}
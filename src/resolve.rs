@@ -0,0 +1,463 @@
+//! Name resolution: every declaration and use of a name is given the [`SymbolId`]
+//! of what it refers to, so that the later passes never look names up themselves.
+
+use std::{collections::HashMap, fmt::Display, path::PathBuf};
+
+use crate::{
+    ast::{
+        parse::spans::{MapExt, Span},
+        Ast, Expression, Statement,
+    },
+    error::{FunctionNotFound, ItemNotFound, ModuleNotFoundError, TypeCheckError, VariableNotFound},
+    module::{Module, ModuleUsePath},
+    scope::{GlobalScope, Scope},
+    suggest,
+    typecheck::builtins,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SymbolId(usize);
+
+impl Display for SymbolId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolKind {
+    /// Provided by the prelude of every backend
+    Builtin,
+    Task,
+    Argument,
+    Crewmate,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Module where it is declared, empty for the main module and the builtins
+    pub module: ModuleUsePath,
+}
+
+/// File and byte range of a node, used to key the side tables of the compiler
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Location {
+    pub file: PathBuf,
+    pub offset: usize,
+    pub length: usize,
+}
+
+impl Location {
+    pub fn of<T>(span: &Span<T>) -> Self {
+        Self {
+            file: span.extra.filename.to_path_buf(),
+            offset: span.location_offset(),
+            length: span.len(),
+        }
+    }
+}
+
+/// Symbol of every name of a program.
+///
+/// Declarations are keyed by the span of their name, and so are the uses of a
+/// name in `make`, `complete` and `vent`. Crewmates used in expressions are keyed
+/// by the span of the expression.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Resolution {
+    symbols: Vec<Symbol>,
+    names: HashMap<Location, SymbolId>,
+}
+
+impl Resolution {
+    pub fn get<T>(&self, span: &Span<T>) -> Option<SymbolId> {
+        self.names.get(&Location::of(span)).copied()
+    }
+
+    pub fn symbol(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id.0]
+    }
+
+    pub fn symbol_at<T>(&self, span: &Span<T>) -> Option<&Symbol> {
+        self.get(span).map(|id| self.symbol(id))
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = (SymbolId, &Symbol)> {
+        self.symbols.iter().enumerate().map(|(i, s)| (SymbolId(i), s))
+    }
+
+    /// Adds `symbol`, declared by the name at `span`
+    pub(crate) fn declare<T>(&mut self, span: &Span<T>, symbol: Symbol) -> SymbolId {
+        let id = SymbolId(self.symbols.len());
+        self.symbols.push(symbol);
+        self.names.insert(Location::of(span), id);
+        id
+    }
+
+    /// Changes the name of a symbol, like the linker does to tasks
+    pub(crate) fn rename(&mut self, id: SymbolId, name: String) {
+        self.symbols[id.0].name = name;
+    }
+}
+
+/// `report` comes first among the builtins
+const REPORT: SymbolId = SymbolId(0);
+
+fn builtin_names() -> impl Iterator<Item = &'static str> {
+    std::iter::once("report").chain(builtins().map(|(name, _)| name))
+}
+
+/// Scope holding the builtins, which are always the first symbols
+fn globals<'a>() -> GlobalScope<'a, SymbolId> {
+    let mut scope = GlobalScope::new();
+    for (i, name) in builtin_names().enumerate() {
+        scope.add(name, SymbolId(i));
+    }
+    scope
+}
+
+struct Resolver {
+    resolution: Resolution,
+    errors: Vec<TypeCheckError>,
+    /// Every task of every room, to suggest the `vent` of a missing one
+    rooms: Vec<(ModuleUsePath, String)>,
+}
+
+impl Resolver {
+    fn new() -> Self {
+        let mut resolver = Self {
+            resolution: Resolution::default(),
+            errors: Vec::new(),
+            rooms: Vec::new(),
+        };
+        for name in builtin_names() {
+            resolver.resolution.symbols.push(Symbol {
+                name: name.to_string(),
+                kind: SymbolKind::Builtin,
+                module: Vec::new(),
+            });
+        }
+        resolver
+    }
+
+    fn declare(&mut self, name: &Span<String>, kind: SymbolKind, module: &ModuleUsePath) -> SymbolId {
+        let symbol = Symbol {
+            name: name.extra.data.clone(),
+            kind,
+            module: module.clone(),
+        };
+        self.resolution.declare(name, symbol)
+    }
+
+    fn record<T>(&mut self, span: &Span<T>, id: SymbolId) {
+        self.resolution.names.insert(Location::of(span), id);
+    }
+
+    /// Help for a name of `module` that refers to nothing: the `vent` that brings
+    /// a task of another room, or a close name in scope of the same kind
+    fn help<'a, S>(&self, scope: &S, module: &ModuleUsePath, name: &str, task: bool) -> Option<String>
+    where
+        S: Scope<SymbolId, &'a str> + ?Sized,
+    {
+        let room = self
+            .rooms
+            .iter()
+            .find(|(room, other)| task && other == name && room != module && !room.is_empty());
+        if let Some((room, _)) = room {
+            let room = room.join("<=");
+            return Some(format!("`{name}` is a task of room `{room}`, add `vent {room}<={name}ඞ`"));
+        }
+        let entries = scope.entries();
+        let candidates = entries
+            .iter()
+            .filter(|(_, &id)| {
+                let kind = self.resolution.symbol(id).kind;
+                matches!(kind, SymbolKind::Builtin | SymbolKind::Task) == task
+            })
+            .map(|&(&name, _)| name);
+        suggest::closest(name, candidates).map(|name| format!("did you mean `{name}`?"))
+    }
+
+    fn declare_tasks<'a>(
+        &mut self,
+        module: &ModuleUsePath,
+        items: &'a [Span<Ast>],
+    ) -> Vec<(&'a str, SymbolId)> {
+        items
+            .iter()
+            .filter_map(|item| match &item.extra.data {
+                Ast::Func(name, _, _, _) => Some((
+                    name.extra.data.as_str(),
+                    self.declare(name, SymbolKind::Task, module),
+                )),
+                Ast::Mod(_) | Ast::Import(_) => None,
+            })
+            .collect()
+    }
+
+    fn tasks<'a>(
+        &mut self,
+        mut scope: GlobalScope<'a, SymbolId>,
+        module: &ModuleUsePath,
+        items: &'a [Span<'a, Ast<'a>>],
+    ) {
+        for item in items {
+            if let Ast::Func(name, _, _, _) = &item.extra.data {
+                if let Some(id) = self.resolution.get(name) {
+                    scope.add(name.extra.data.as_str(), id);
+                }
+            }
+        }
+        for item in items {
+            if let Ast::Func(_, _, args, body) = &item.extra.data {
+                let mut scope = scope.push();
+                for arg in args {
+                    let name = &arg.extra.data.0;
+                    let id = self.declare(name, SymbolKind::Argument, module);
+                    scope.add(name.extra.data.as_str(), id);
+                }
+                self.body(scope, module, &body.extra.data);
+            }
+        }
+    }
+
+    fn body<'a, S>(&mut self, mut scope: S, module: &ModuleUsePath, body: &'a [Span<'a, Statement>])
+    where
+        S: Scope<SymbolId, &'a str>,
+    {
+        for line in body {
+            match &line.extra.data {
+                Statement::If(cond, body, else_body) => {
+                    self.expression(&mut scope, module, cond);
+                    self.body(scope.push(), module, &body.extra.data);
+                    if let Some(else_body) = else_body {
+                        self.body(scope.push(), module, &else_body.extra.data);
+                    }
+                }
+                Statement::While(cond, body) => {
+                    self.expression(&mut scope, module, cond);
+                    self.body(scope.push(), module, &body.extra.data);
+                }
+                Statement::For(name, start, end, body) => {
+                    self.expression(&mut scope, module, start);
+                    self.expression(&mut scope, module, end);
+                    let mut scope = scope.push();
+                    let id = self.declare(name, SymbolKind::Crewmate, module);
+                    scope.add(name.extra.data.as_str(), id);
+                    self.body(scope, module, &body.extra.data);
+                }
+                Statement::Return(x) => {
+                    if let Some(x) = x {
+                        self.expression(&mut scope, module, x);
+                    }
+                }
+                Statement::Expr(e) => self.expression(&mut scope, module, e),
+                Statement::Declare(name, _) => {
+                    let id = self.declare(name, SymbolKind::Crewmate, module);
+                    scope.add(name.extra.data.as_str(), id);
+                }
+                Statement::Define(name, e) => {
+                    self.variable(&scope, module, name);
+                    self.expression(&mut scope, module, e);
+                }
+                Statement::Kill(name) => self.variable(&scope, module, name),
+                Statement::Try(body, name, _, catch) => {
+                    self.body(scope.push(), module, &body.extra.data);
+                    let mut scope = scope.push();
+                    let id = self.declare(name, SymbolKind::Crewmate, module);
+                    scope.add(name.extra.data.as_str(), id);
+                    self.body(scope, module, &catch.extra.data);
+                }
+                Statement::Sabotage(reason) | Statement::Afk(reason) => {
+                    self.expression(&mut scope, module, reason)
+                }
+                Statement::Win | Statement::Lose | Statement::Break | Statement::Continue => (),
+            }
+        }
+    }
+
+    /// Name given to `make` or `kill`
+    fn variable<'a, S>(&mut self, scope: &S, module: &ModuleUsePath, name: &'a Span<String>)
+    where
+        S: Scope<SymbolId, &'a str>,
+    {
+        match scope.get(&name.extra.data.as_str()) {
+            Some(&id) => self.record(name, id),
+            None => {
+                let help = self.help(scope, module, &name.extra.data, false);
+                let error = VariableNotFound::from(name.clone()).with_help(help);
+                self.errors.push(error.into())
+            }
+        }
+    }
+
+    fn expression<'a, S>(&mut self, scope: &mut S, module: &ModuleUsePath, e: &'a Span<'a, Expression>)
+    where
+        S: Scope<SymbolId, &'a str> + ?Sized,
+    {
+        match &e.extra.data {
+            Expression::Call(name, args) => {
+                // `report` is checked apart by typecheck, so no crewmate can hide it
+                let id = if name.extra.data == "report" {
+                    Some(&REPORT)
+                } else {
+                    scope.get(&name.extra.data.as_str())
+                };
+                match id {
+                    Some(&id) => self.record(name, id),
+                    None => {
+                        let help = self.help(scope, module, &name.extra.data, true);
+                        let error = FunctionNotFound::from(name.clone()).with_help(help);
+                        self.errors.push(error.into())
+                    }
+                }
+                for arg in args {
+                    self.expression(scope, module, arg);
+                }
+            }
+            Expression::Operation(_, a, b) => {
+                self.expression(scope, module, a);
+                self.expression(scope, module, b);
+            }
+            Expression::Variable(name) => match scope.get(&name.as_str()) {
+                Some(&id) => self.record(e, id),
+                None => {
+                    let help = self.help(scope, module, name, false);
+                    let error = VariableNotFound::from(e.clone().map(|_| name.clone())).with_help(help);
+                    self.errors.push(error.into())
+                }
+            },
+            Expression::StringLit(_) | Expression::NumLit(_) | Expression::BoolLit(_) => (),
+        }
+    }
+}
+
+/// Resolves every name of every module, reporting the ones that refer to nothing
+pub fn resolve(module_graph: &Module) -> Result<Resolution, Vec<TypeCheckError>> {
+    let mut resolver = Resolver::new();
+    // sorted so that the ids don't depend on the order of the submodules in memory
+    let mut modules: Vec<_> = module_graph.iter().collect();
+    modules.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut tasks = HashMap::new();
+    for module in &modules {
+        for (name, id) in resolver.declare_tasks(&module.path, &module.items) {
+            tasks.insert((module.path.as_slice(), name), id);
+        }
+    }
+    resolver.rooms = tasks.keys().map(|&(room, name)| (room.to_vec(), name.to_string())).collect();
+    resolver.rooms.sort();
+
+    for module in &modules {
+        let mut scope = globals();
+        for item in &module.items {
+            let Ast::Import(path) = &item.extra.data else {
+                continue;
+            };
+            // FIXME Imports realtive to root
+            let (name, module_path) = path.extra.data.split_last().unwrap();
+            if module_graph.get_module(module_path).is_none() {
+                // a close room next to the missing one
+                let help = module_path.split_last().and_then(|(room, parent)| {
+                    let rooms = module_graph.get_module(parent)?.submodules.keys();
+                    suggest::closest(room, rooms.map(String::as_str))
+                        .map(|room| format!("did you mean room `{room}`?"))
+                });
+                let error = ModuleNotFoundError::from(path.clone().map(|_| ())).with_help(help);
+                resolver.errors.push(error.into());
+            } else if let Some(&id) = tasks.get(&(module_path, name.as_str())) {
+                resolver.record(path, id);
+                scope.add(name.as_str(), id);
+            } else {
+                let vented = tasks.keys().filter(|(room, _)| *room == module_path);
+                let help = suggest::closest(name, vented.map(|&(_, task)| task))
+                    .map(|task| format!("did you mean `{task}`?"));
+                let error = ItemNotFound::from(path.clone().map(|_| *path.fragment())).with_help(help);
+                resolver.errors.push(error.into());
+            }
+        }
+        resolver.tasks(scope, &module.path, &module.items);
+    }
+
+    if resolver.errors.is_empty() {
+        Ok(resolver.resolution)
+    } else {
+        Err(resolver.errors)
+    }
+}
+
+/// Resolves the names of a single list of items, like the one made by the linker.
+///
+/// Imports are ignored and names that refer to nothing are left out of the table.
+pub fn resolve_items(items: &[Span<Ast>]) -> Resolution {
+    let mut resolver = Resolver::new();
+    let module = Vec::new();
+    resolver.declare_tasks(&module, items);
+    resolver.tasks(globals(), &module, items);
+    resolver.resolution
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::{Ast, Expression, Statement},
+        parse_str,
+    };
+
+    use super::{resolve_items, SymbolKind};
+
+    #[test]
+    fn crewmate_shadows_task() {
+        let ast = parse_str(
+            "task n with ➤ number\nචeject 1ඞ\ntask ඬ with ➤ number\nචcrewmate n: numberඞ\nචmake n 2ඞ\nචeject nඞ\n",
+        )
+        .unwrap();
+        let resolution = resolve_items(&ast);
+        let Ast::Func(task, _, _, _) = &ast[0].extra.data else {
+            panic!()
+        };
+        let Ast::Func(_, _, _, body) = &ast[1].extra.data else {
+            panic!()
+        };
+        let [Statement::Declare(local, _), Statement::Define(define, _), Statement::Return(Some(used))] =
+            &body.extra.data.iter().map(|s| &s.extra.data).collect::<Vec<_>>()[..]
+        else {
+            panic!()
+        };
+        assert_eq!(resolution.symbol_at(task).unwrap().kind, SymbolKind::Task);
+        assert_eq!(resolution.symbol_at(local).unwrap().kind, SymbolKind::Crewmate);
+        assert_eq!(resolution.get(define), resolution.get(local));
+        assert_eq!(resolution.get(used), resolution.get(local));
+        assert_ne!(resolution.get(used), resolution.get(task));
+    }
+
+    #[test]
+    fn blocks_have_their_own_scope() {
+        let ast = parse_str(
+            "task ඬ with ➤ number\nචsus? == 1 1\nචචcrewmate x: numberඞ\nචචmake x 1ඞ\nචcrewmate x: boolඞ\nචcomplete report with \"%d\" and xඞ\nචeject 0ඞ\n",
+        )
+        .unwrap();
+        let resolution = resolve_items(&ast);
+        let Ast::Func(_, _, _, body) = &ast[0].extra.data else {
+            panic!()
+        };
+        let Statement::If(_, then, _) = &body.extra.data[0].extra.data else {
+            panic!()
+        };
+        let Statement::Declare(inner, _) = &then.extra.data[0].extra.data else {
+            panic!()
+        };
+        let Statement::Declare(outer, _) = &body.extra.data[1].extra.data else {
+            panic!()
+        };
+        let Statement::Expr(report) = &body.extra.data[2].extra.data else {
+            panic!()
+        };
+        let Expression::Call(report, args) = &report.extra.data else {
+            panic!()
+        };
+        assert_eq!(resolution.symbol_at(report).unwrap().kind, SymbolKind::Builtin);
+        assert_eq!(resolution.get(&args[1]), resolution.get(outer));
+        assert_ne!(resolution.get(inner), resolution.get(outer));
+    }
+}
@@ -5,7 +5,10 @@ pub trait Scope<T, K>: Debug {
 
     fn get(&self, n: &K) -> Option<&T>;
 
-    fn push(&mut self) -> ChildScope<T, K>;
+    /// Everything in scope, including what is hidden by inner scopes
+    fn entries(&self) -> Vec<(&K, &T)>;
+
+    fn push(&mut self) -> ChildScope<'_, T, K>;
 }
 
 #[derive(Debug)]
@@ -42,7 +45,11 @@ where
         self.data.get(n)
     }
 
-    fn push(&mut self) -> ChildScope<T, K> {
+    fn entries(&self) -> Vec<(&K, &T)> {
+        self.data.iter().collect()
+    }
+
+    fn push(&mut self) -> ChildScope<'_, T, K> {
         ChildScope::new(self)
     }
 }
@@ -74,7 +81,13 @@ where
         self.data.get(n).or_else(|| self.parent.get(n))
     }
 
-    fn push(&mut self) -> ChildScope<T, K> {
+    fn entries(&self) -> Vec<(&K, &T)> {
+        let mut entries = self.parent.entries();
+        entries.extend(self.data.iter());
+        entries
+    }
+
+    fn push(&mut self) -> ChildScope<'_, T, K> {
         ChildScope::new(self)
     }
 }
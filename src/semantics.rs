@@ -0,0 +1,61 @@
+//! Meaning of suslang numbers, which every backend has to follow.
+//!
+//! A `number` is a 32-bit two's complement integer. `+` and `-` wrap around on
+//! overflow and `%` truncates towards zero, so the result has the sign of the
+//! dividend, as in C. Taking the modulo by zero ejects the program.
+//!
+//! The functions here are the reference implementation: the backends emit
+//! helpers that compute exactly the same values.
+
+/// Reason for a program to be ejected while computing a number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ejection {
+    DivisionByZero,
+}
+
+impl Ejection {
+    /// Message printed by the generated code, after `program was ejected`
+    pub const fn reason(self) -> &'static str {
+        match self {
+            Self::DivisionByZero => "division by zero",
+        }
+    }
+}
+
+pub const fn add(a: i32, b: i32) -> i32 {
+    a.wrapping_add(b)
+}
+
+pub const fn sub(a: i32, b: i32) -> i32 {
+    a.wrapping_sub(b)
+}
+
+pub const fn rem(a: i32, b: i32) -> Result<i32, Ejection> {
+    if b == 0 {
+        Err(Ejection::DivisionByZero)
+    } else {
+        // `i32::MIN % -1` overflows in the division, but its remainder is 0
+        Ok(a.wrapping_rem(b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add, rem, sub, Ejection};
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(add(i32::MAX, 1), i32::MIN);
+        assert_eq!(sub(i32::MIN, 1), i32::MAX);
+        assert_eq!(add(-5, 3), -2);
+    }
+
+    #[test]
+    fn truncated_modulo() {
+        assert_eq!(rem(7, 3), Ok(1));
+        assert_eq!(rem(-7, 3), Ok(-1));
+        assert_eq!(rem(7, -3), Ok(1));
+        assert_eq!(rem(i32::MIN, -1), Ok(0));
+        assert_eq!(rem(1, 0), Err(Ejection::DivisionByZero));
+    }
+}
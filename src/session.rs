@@ -0,0 +1,434 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::{
+    ast::parse::spans::{MapExt, Span},
+    codegen::Codegen,
+    colors::Colors,
+    emit::{self, Stage},
+    entry,
+    error::{LintWarning, ModuleNotFoundError, ParseFailed, TypeCheckError, UnreachableCode},
+    flow,
+    fs::Filesystem,
+    linker::{self, Linked},
+    lint::{self, Level, Lint},
+    mir::{self, ValidationError},
+    module::{LoadError, Module},
+    optimize::{optimize, OptLevel},
+    resolve::{resolve, Resolution},
+    typecheck::{infer_types, ExprTypes},
+};
+
+/// Everything that can stop a [`Session`] from compiling
+#[derive(Error, Debug, miette::Diagnostic)]
+pub enum Diagnostic {
+    #[error("Nothing to compile, add a source first")]
+    #[diagnostic(code(suslang::session::empty))]
+    NoSource,
+    #[error("Could not read `{}`", path.display())]
+    #[diagnostic(code(suslang::io::read))]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Could not write the generated code")]
+    #[diagnostic(code(suslang::io::write))]
+    Write(#[source] std::io::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Parse(#[from] ParseFailed),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ModuleNotFound(#[from] ModuleNotFoundError),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    TypeCheck(#[from] TypeCheckError),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    InvalidIr(#[from] ValidationError),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Unreachable(#[from] UnreachableCode),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Lint(#[from] LintWarning),
+}
+
+impl Diagnostic {
+    /// Whether it stops the compilation, unlike a warning
+    pub fn is_error(&self) -> bool {
+        !matches!(
+            miette::Diagnostic::severity(self),
+            Some(miette::Severity::Warning | miette::Severity::Advice)
+        )
+    }
+}
+
+impl<'a> From<LoadError<'a, Span<'a>>> for Diagnostic {
+    fn from(value: LoadError<'a, Span<'a>>) -> Self {
+        match value {
+            LoadError::IOError(path, source) => Self::Read { path, source },
+            LoadError::ParseError(e) => ParseFailed::from(&e).into(),
+            LoadError::ModuleFileNotFound(span) => {
+                ModuleNotFoundError::from(span.map(|_| ())).into()
+            }
+        }
+    }
+}
+
+/// What the analysis of a program gives to the later stages
+struct Analysis {
+    resolution: Resolution,
+    types: ExprTypes,
+    warnings: Vec<Diagnostic>,
+}
+
+/// Compiler that can be embedded in other programs.
+///
+/// The main module is the file picked with [`Session::main_file`], or else the
+/// first source added. The `room`s it uses are looked up among the sources and
+/// then on disk. Nothing here panics or exits, every
+/// problem is reported as a [`Diagnostic`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Session {
+    main: Option<PathBuf>,
+    sources: HashMap<PathBuf, String>,
+    opt_level: OptLevel,
+    lint_levels: HashMap<Lint, Level>,
+    colors: Colors,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file that is kept in memory
+    pub fn add_source<P: Into<PathBuf>, S: Into<String>>(
+        &mut self,
+        path: P,
+        contents: S,
+    ) -> &mut Self {
+        let path = path.into();
+        self.main.get_or_insert_with(|| path.clone());
+        self.sources.insert(path, contents.into());
+        self
+    }
+
+    /// Picks the main module, which is read from disk when compiling unless a
+    /// source was added with its path
+    pub fn main_file<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.main = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets how much the linked program is optimized before generating code
+    pub fn opt_level(&mut self, level: OptLevel) -> &mut Self {
+        self.opt_level = level;
+        self
+    }
+
+    /// Sets what is done with what `lint` finds, instead of its default level
+    pub fn lint_level(&mut self, lint: Lint, level: Level) -> &mut Self {
+        self.lint_levels.insert(lint, level);
+        self
+    }
+
+    /// Adds names to the colors of the `non_color_name` lint
+    pub fn colors<'n>(&mut self, names: impl IntoIterator<Item = &'n str>) -> &mut Self {
+        self.colors.extend(names);
+        self
+    }
+
+    fn with_module<R>(
+        &self,
+        f: impl for<'a> FnOnce(&Module<'a>) -> Result<R, Vec<Diagnostic>>,
+    ) -> Result<R, Vec<Diagnostic>> {
+        let main = self.main.clone().ok_or_else(|| vec![Diagnostic::NoSource])?;
+        let mut fs = Filesystem::new();
+        for (path, contents) in &self.sources {
+            fs.add_source(path.clone(), contents.clone());
+        }
+        let module = Module::new(main, &mut fs).map_err(|e| vec![e.into()])?;
+        let result = f(&module);
+        drop(module);
+        drop(fs);
+        result
+    }
+
+    /// Resolves the names of the whole program, checks its entry point, then
+    /// typechecks, follows the control flow of and lints every module
+    fn analyse(&self, module_graph: &Module) -> Result<Analysis, Vec<Diagnostic>> {
+        let resolution = resolve(module_graph)
+            .map_err(|errors| errors.into_iter().map(Into::into).collect::<Vec<_>>())?;
+        let mut types = ExprTypes::default();
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+        let main = self.main.as_deref().expect("there is a module graph, so a main file");
+        errors.extend(entry::check(module_graph, main).into_iter().map(Into::into));
+        for module in module_graph.iter() {
+            match infer_types(&module.items, module_graph, &resolution) {
+                Ok(t) => types.merge(t),
+                Err(e) => errors.push(e.into()),
+            }
+            let flow = flow::check(&module.items, &resolution);
+            errors.extend(flow.missing_ejects.into_iter().map(|e| TypeCheckError::from(e).into()));
+            errors.extend(flow.uses_before_make.into_iter().map(|e| TypeCheckError::from(e).into()));
+            errors.extend(flow.kills.into_iter().map(Into::into));
+            warnings.extend(flow.unreachable.into_iter().map(Into::into));
+            for warning in lint::check(&module.items, &resolution, &self.colors) {
+                let lint = warning.lint();
+                match self.lint_levels.get(&lint).copied().unwrap_or(lint.default_level()) {
+                    Level::Allow => (),
+                    Level::Warn => warnings.push(warning.into()),
+                    Level::Deny => errors.push(warning.deny().into()),
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(Analysis {
+                resolution,
+                types,
+                warnings,
+            })
+        } else {
+            errors.append(&mut warnings);
+            Err(errors)
+        }
+    }
+
+    /// The program given to the backends, linked and optimized
+    fn link<'a>(&self, module_graph: &Module<'a>, resolution: &Resolution) -> Linked<'a> {
+        optimize(linker::link(module_graph, resolution), self.opt_level)
+    }
+
+    /// Checks the program without generating any code, returning its errors and
+    /// warnings
+    pub fn check(&self) -> Vec<Diagnostic> {
+        self.with_module(|module| self.analyse(module).map(|analysis| analysis.warnings))
+            .unwrap_or_else(|errors| errors)
+    }
+
+    /// Generates the code of the program with `codegen`, writing it to `out`
+    pub fn emit<C, W>(&self, codegen: &mut C, out: &mut W) -> Result<(), Vec<Diagnostic>>
+    where
+        C: for<'a> Codegen<W, Linked<'a>> + ?Sized,
+        W: Write,
+    {
+        self.with_module(|module| {
+            let Analysis { resolution, .. } = self.analyse(module)?;
+            let ast = self.link(module, &resolution);
+            codegen
+                .gen(&ast, out)
+                .and_then(|()| out.flush())
+                .map_err(|e| vec![Diagnostic::Write(e)])
+        })
+    }
+
+    /// JSON form of a stage of the compiler, see [`crate::emit`]
+    pub fn dump(&self, stage: Stage) -> Result<String, Vec<Diagnostic>> {
+        self.with_module(|module| {
+            let json = match stage {
+                Stage::Ast => serde_json::to_string_pretty(&emit::modules(module, None)),
+                Stage::Linked => {
+                    let Analysis { resolution, .. } = self.analyse(module)?;
+                    serde_json::to_string_pretty(&emit::items(&self.link(module, &resolution).items))
+                }
+                Stage::Types => {
+                    let Analysis { types, .. } = self.analyse(module)?;
+                    serde_json::to_string_pretty(&emit::modules(module, Some(&types)))
+                }
+                Stage::Ir => return self.lower(module).map(|program| program.to_string()),
+            };
+            Ok(json.expect("the forms of the AST always serialize"))
+        })
+    }
+
+    fn lower(&self, module_graph: &Module) -> Result<mir::Program, Vec<Diagnostic>> {
+        let Analysis { resolution, types, .. } = self.analyse(module_graph)?;
+        let linked = self.link(module_graph, &resolution);
+        let program = mir::lower(&linked.items, &linked.resolution, &types);
+        mir::validate(&program)
+            .map_err(|errors| errors.into_iter().map(Into::into).collect::<Vec<_>>())?;
+        Ok(program)
+    }
+
+    /// The program lowered to the mid-level IR
+    pub fn ir(&self) -> Result<mir::Program, Vec<Diagnostic>> {
+        self.with_module(|module| self.lower(module))
+    }
+
+    /// Generates the code of the program with `codegen`
+    pub fn build<C>(&self, codegen: &mut C) -> Result<String, Vec<Diagnostic>>
+    where
+        C: for<'a> Codegen<Vec<u8>, Linked<'a>> + ?Sized,
+    {
+        let mut out = Vec::new();
+        self.emit(codegen, &mut out)?;
+        Ok(String::from_utf8(out).expect("the backends only write UTF-8"))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{Diagnostic, Session};
+    use crate::{
+        codegen,
+        emit::Stage,
+        error::TypeCheckError,
+        lint::{Level, Lint},
+    };
+
+    /// Errors of the program made of `main.sus` and the `rooms`, given with the
+    /// name of their file
+    pub(crate) fn errors(main: &str, rooms: &[(&str, &str)]) -> Vec<Diagnostic> {
+        let mut session = Session::new();
+        session.add_source("main.sus", main);
+        for &(path, room) in rooms {
+            session.add_source(path, room);
+        }
+        session.check().into_iter().filter(Diagnostic::is_error).collect()
+    }
+
+    /// Errors of a program whose `ඬ` ejects nothing and runs `body`
+    pub(crate) fn errors_in_main(body: &str) -> Vec<Diagnostic> {
+        errors(&format!("task ඬ with ➤ void\n{body}"), &[])
+    }
+
+    #[test]
+    fn build_from_memory() {
+        let mut session = Session::new();
+        session
+            .add_source(
+                "main.sus",
+                "room libඞ\nvent lib<=twiceඞ\ntask ඬ with ➤ number\nචeject complete twice with 2ඞ\n",
+            )
+            .add_source(
+                "lib.sus",
+                "task twice with crewmate n: number ➤ number\nචeject + n nඞ\n",
+            );
+        assert!(session.check().is_empty());
+        let code = session.build(&mut codegen::Py::new()).unwrap();
+        assert!(code.contains("def lib_twice_"));
+    }
+
+    #[test]
+    fn main_file_picks_the_root() {
+        let mut session = Session::new();
+        session
+            .add_source("lib.sus", "task twice with crewmate n: number ➤ number\nචeject + n nඞ\n")
+            .add_source(
+                "main.sus",
+                "room libඞ\nvent lib<=twiceඞ\ntask ඬ with ➤ number\nචeject complete twice with 2ඞ\n",
+            );
+        assert!(!session.check().is_empty());
+        session.main_file("main.sus");
+        assert!(session.check().is_empty());
+    }
+
+    #[test]
+    fn dump_types() {
+        let mut session = Session::new();
+        session.add_source("main.sus", "task ඬ with ➤ number\nචeject + 1 2ඞ\n");
+        let json: serde_json::Value =
+            serde_json::from_str(&session.dump(Stage::Types).unwrap()).unwrap();
+        let value = &json[0]["items"][0]["node"]["Func"]["body"]["node"][0]["node"]["Return"]["value"];
+        assert_eq!(value["type"], "Number");
+        assert_eq!(value["file"], "main.sus");
+    }
+
+    #[test]
+    fn crewmate_shadows_task() {
+        let mut session = Session::new();
+        session.add_source(
+            "main.sus",
+            "task twice with ➤ number\nචeject 2ඞ\ntask ඬ with ➤ number\nචcrewmate twice: numberඞ\nචeject complete twiceඞ\n",
+        );
+        assert!(matches!(
+            session.check()[..],
+            [Diagnostic::TypeCheck(TypeCheckError::NotCallable(_))]
+        ));
+    }
+
+    #[test]
+    fn reports_instead_of_panicking() {
+        let mut session = Session::new();
+        assert!(matches!(session.check()[..], [Diagnostic::NoSource]));
+
+        session.add_source("main.sus", "task ඬ with ➤ number\nචeject + 1 \"a\"ඞ\n");
+        assert!(matches!(
+            session.check()[..],
+            [Diagnostic::TypeCheck(TypeCheckError::ExpressionTypeError(_))]
+        ));
+
+        let mut session = Session::new();
+        session.add_source("main.sus", "task ඬ ➤ number\n");
+        assert!(matches!(session.check()[..], [Diagnostic::Parse(_)]));
+
+        let mut session = Session::new();
+        session.add_source("main.sus", "room nowhereඞ\n");
+        assert!(matches!(session.check()[..], [Diagnostic::ModuleNotFound(_)]));
+    }
+
+    #[test]
+    fn suggests_close_names() {
+        let help = |main: &str| -> Vec<String> {
+            let lib = "task twice with crewmate n: number ➤ number\nචeject + n nඞ\n";
+            errors(&format!("room libඞ\n{main}"), &[("lib.sus", lib)])
+                .iter()
+                .map(|d| miette::Diagnostic::help(d).map_or(String::new(), |help| help.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            help("task ඬ with ➤ number\nචcrewmate total: numberඞ\nචmake totl 1ඞ\nචeject complete twice with totalඞ\n"),
+            [
+                "did you mean `total`?",
+                "`twice` is a task of room `lib`, add `vent lib<=twiceඞ`"
+            ]
+        );
+        assert_eq!(
+            help("vent lib<=twiseඞ\nvent lob<=twiceඞ\ntask ඬ with ➤ number\nචeject complete lenn with \"a\"ඞ\n"),
+            ["did you mean `twice`?", "did you mean room `lib`?", "did you mean `len`?"]
+        );
+    }
+
+    #[test]
+    fn lint_levels() {
+        let mut session = Session::new();
+        session.add_source(
+            "main.sus",
+            "task ඬ with ➤ number\nචcrewmate x: numberඞ\nචsus? == 1 1\nචචcrewmate x: numberඞ\nචeject 0ඞ\n",
+        );
+        let lints = |diagnostics: Vec<Diagnostic>| -> Vec<(Lint, bool)> {
+            diagnostics
+                .iter()
+                .map(|d| match d {
+                    Diagnostic::Lint(warning) => (warning.lint(), d.is_error()),
+                    d => panic!("{d}"),
+                })
+                .collect()
+        };
+        assert_eq!(
+            lints(session.check()),
+            [
+                (Lint::ConstantCondition, false),
+                (Lint::UnusedCrewmate, false),
+                (Lint::UnusedCrewmate, false)
+            ]
+        );
+        session
+            .lint_level(Lint::UnusedCrewmate, Level::Allow)
+            .lint_level(Lint::ShadowedName, Level::Warn)
+            .lint_level(Lint::ConstantCondition, Level::Deny);
+        assert_eq!(
+            lints(session.check()),
+            [(Lint::ConstantCondition, true), (Lint::ShadowedName, false)]
+        );
+        assert!(session.build(&mut codegen::Py::new()).is_err());
+    }
+}
@@ -0,0 +1,47 @@
+//! Close names for "did you mean" suggestions.
+
+/// Number of characters to insert, remove or replace to turn `a` into `b`
+pub fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(ca != cb);
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Closest of `candidates` to `name`, if it is close enough to be a typo of it
+pub fn closest<'c>(name: &str, candidates: impl IntoIterator<Item = &'c str>) -> Option<&'c str> {
+    let max = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|&candidate| candidate != name)
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= max)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{closest, distance};
+
+    #[test]
+    fn edit_distance() {
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("", "red"), 3);
+        assert_eq!(distance("ඬඞ", "ඬ"), 1);
+    }
+
+    #[test]
+    fn only_close_names_are_suggested() {
+        assert_eq!(closest("fibo", ["fib", "fibonacci", "foo"]), Some("fib"));
+        assert_eq!(closest("x", ["y", "xs"]), Some("xs"));
+        assert_eq!(closest("total", ["count", "n"]), None);
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+};
+
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use thiserror::Error;
+
+#[derive(Error, Debug, Diagnostic)]
+pub enum ToolchainError {
+    #[error("Could not run `{program}`")]
+    #[diagnostic(code(suslang::toolchain::spawn), help("is `{program}` installed and in PATH?"))]
+    Spawn {
+        program: String,
+        source: std::io::Error,
+    },
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Compiler(#[from] CompilerFailed),
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("`{program}` failed to compile the generated code ({status})")]
+#[diagnostic(code(suslang::toolchain::compiler), url(docsrs))]
+pub struct CompilerFailed {
+    pub program: String,
+    pub status: ExitStatus,
+    /// Messages that could be traced back to a suslang or generated source file
+    #[related]
+    pub messages: Vec<CompilerMessage>,
+    /// Raw output of the compiler, used when no message could be parsed
+    #[help]
+    pub stderr: Option<String>,
+}
+
+#[derive(Error, Debug, Diagnostic)]
+#[error("{message}")]
+#[diagnostic(code(suslang::toolchain::compiler::message))]
+pub struct CompilerMessage {
+    #[source_code]
+    src: NamedSource,
+    #[label("{severity}")]
+    bad_bit: Option<SourceSpan>,
+    severity: String,
+    message: String,
+}
+
+impl CompilerMessage {
+    /// Parses a `file:line:column: severity: message` line as printed by gcc and clang
+    fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.splitn(5, ':');
+        let file = PathBuf::from(parts.next()?);
+        let line_n = parts.next()?.trim().parse::<usize>().ok()?;
+        let column = parts.next()?.trim().parse::<usize>().ok()?;
+        let severity = parts.next()?.trim().to_string();
+        let message = parts.next()?.trim().to_string();
+        if !matches!(severity.as_str(), "error" | "fatal error" | "warning") {
+            return None;
+        }
+        let contents = std::fs::read_to_string(&file).ok();
+        let bad_bit = contents
+            .as_deref()
+            .map(|contents| (offset_of(contents, line_n, column), 0).into());
+        Some(Self {
+            src: NamedSource::new(file.display().to_string(), contents.unwrap_or_default()),
+            bad_bit,
+            severity,
+            message: format!("{}:{line_n}:{column}: {message}", file.display()),
+        })
+    }
+}
+
+/// Byte offset of a 1-based `line` and `column` inside `contents`. The column
+/// counts characters, so that it never lands inside a `ච`
+fn offset_of(contents: &str, line: usize, column: usize) -> usize {
+    let line_start = contents
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(str::len)
+        .sum::<usize>();
+    let line = contents[line_start..].split('\n').next().unwrap_or_default();
+    let column = line
+        .char_indices()
+        .nth(column.saturating_sub(1))
+        .map_or(line.len(), |(offset, _)| offset);
+    line_start + column
+}
+
+/// A C compiler, by default the one in `CC` (or `cc`) with the flags in `CFLAGS` (or `-O2`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CCompiler {
+    pub program: OsString,
+    pub flags: Vec<OsString>,
+}
+
+impl Default for CCompiler {
+    fn default() -> Self {
+        Self {
+            program: std::env::var_os("CC").unwrap_or_else(|| "cc".into()),
+            flags: std::env::var("CFLAGS")
+                .map(|flags| flags.split_whitespace().map(Into::into).collect())
+                .unwrap_or_else(|_| vec!["-O2".into()]),
+        }
+    }
+}
+
+impl CCompiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compiles the C file `source` into the executable `output`
+    pub fn compile<A: AsRef<Path>, B: AsRef<Path>>(
+        &self,
+        source: A,
+        output: B,
+    ) -> Result<(), ToolchainError> {
+        let program = self.program.to_string_lossy().into_owned();
+        let result = Command::new(&self.program)
+            .args(&self.flags)
+            .arg("-o")
+            .arg(output.as_ref())
+            .arg(source.as_ref())
+            .output()
+            .map_err(|source| ToolchainError::Spawn {
+                program: program.clone(),
+                source,
+            })?;
+        if result.status.success() {
+            return Ok(());
+        }
+        let stderr = String::from_utf8_lossy(&result.stderr).into_owned();
+        let messages = stderr
+            .lines()
+            .filter_map(CompilerMessage::parse)
+            .collect::<Vec<_>>();
+        Err(CompilerFailed {
+            program,
+            status: result.status,
+            stderr: (messages.is_empty() && !stderr.trim().is_empty()).then_some(stderr),
+            messages,
+        }
+        .into())
+    }
+}
+
+/// Interpreters used to run the code of the scripting backends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Interpreter {
+    Python,
+    Node,
+    Guile,
+}
+
+impl Interpreter {
+    /// Program invoked for this interpreter, overridable through `PYTHON`, `NODE` and `GUILE`
+    pub fn program(&self) -> OsString {
+        let (var, default) = match self {
+            Self::Python => ("PYTHON", "python3"),
+            Self::Node => ("NODE", "node"),
+            Self::Guile => ("GUILE", "guile"),
+        };
+        std::env::var_os(var).unwrap_or_else(|| default.into())
+    }
+
+    /// Command that runs the script at `file`, ready to have its arguments or stdio tweaked
+    pub fn command<P: AsRef<Path>>(&self, file: P) -> Command {
+        let mut command = Command::new(self.program());
+        if let Self::Guile = self {
+            command.arg("--no-auto-compile").arg("-s");
+        }
+        command.arg(file.as_ref());
+        command
+    }
+
+    /// Whether the interpreter can be found
+    pub fn is_available(&self) -> bool {
+        Command::new(self.program())
+            .arg("--version")
+            .output()
+            .is_ok_and(|output| output.status.success())
+    }
+
+    /// Runs the script at `file` with the current stdio and waits for it
+    pub fn run<P: AsRef<Path>>(&self, file: P) -> Result<ExitStatus, ToolchainError> {
+        self.command(file)
+            .status()
+            .map_err(|source| ToolchainError::Spawn {
+                program: self.program().to_string_lossy().into_owned(),
+                source,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{offset_of, CompilerMessage};
+
+    #[test]
+    fn offsets() {
+        const DATA: &str = "task ඬ\nචeject 0ඞ\n";
+        assert_eq!(offset_of(DATA, 1, 1), 0);
+        assert_eq!(offset_of(DATA, 2, 1), "task ඬ\n".len());
+        assert_eq!(offset_of(DATA, 2, 100), DATA.len() - 1);
+        assert_eq!(offset_of(DATA, 2, 2), "task ඬ\nච".len());
+    }
+
+    #[test]
+    fn parse_messages() {
+        let message =
+            CompilerMessage::parse("out.c:3:5: error: 'x' undeclared (first use in this function)")
+                .unwrap();
+        assert_eq!(message.severity, "error");
+        assert!(message.message.ends_with("'x' undeclared (first use in this function)"));
+        assert!(CompilerMessage::parse("out.c: In function 'main':").is_none());
+    }
+}
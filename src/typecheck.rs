@@ -1,6 +1,6 @@
 // Replace with Typ if functions is added as a type
 
-use std::{borrow::Cow, fmt::Debug};
+use std::{collections::HashMap, fmt::Debug};
 
 use nom_locate::LocatedSpan;
 
@@ -10,13 +10,14 @@ use crate::{
         Ast, Expression, Operator, Statement, Typ,
     },
     error::{
-        ExpressionTypeError, FunctionNotFound, ItemNotFound, ModuleNotFoundError, TypeCheckError, FunctionArgumentNumber, FunctionArgumentTypeError,
+        ExpressionTypeError, FunctionNotFound, TypeCheckError, FunctionArgumentNumber, FunctionArgumentTypeError,
+        LoopControlOutsideLoop, NotCallable, ReportFormatError, VariableNotFound,
     },
     module::Module,
-    scope::{GlobalScope, Scope},
+    resolve::{Location, Resolution, SymbolId, SymbolKind},
 };
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
 pub enum Type {
     Function(Vec<Self>, Box<Self>),
     Void,
@@ -36,47 +37,110 @@ impl From<Typ> for Type {
     }
 }
 
+/// Tasks provided by the prelude of every backend, `report` is checked apart
+pub fn builtins() -> impl Iterator<Item = (&'static str, Type)> {
+    use Type::*;
+    [
+        ("len", vec![String], Number),
+        ("getelement", vec![String, Number], String),
+        ("setelement", vec![String, Number, String], String),
+        ("replace", vec![String, String, String], String),
+        // technically lists of strings, but we don't have that yet
+        ("split", vec![String, String], String),
+        ("openfile", vec![String], String),
+        ("tostring", vec![Number], String),
+        ("tonumber", vec![String], Number),
+        ("concat", vec![String, String], String),
+    ]
+    .into_iter()
+    .map(|(name, args, ret)| (name, Function(args, Box::new(ret))))
+}
+
+/// Type of every expression that was checked, keyed by its file and byte range
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExprTypes(HashMap<Location, Type>);
+
+impl ExprTypes {
+    pub fn get<T>(&self, span: &Span<T>) -> Option<&Type> {
+        self.0.get(&Location::of(span))
+    }
+
+    pub fn merge(&mut self, other: Self) {
+        self.0.extend(other.0);
+    }
+
+    fn insert<T>(&mut self, span: &Span<T>, t: Type) {
+        self.0.insert(Location::of(span), t);
+    }
+}
+
+/// Types of the symbols declared so far
+struct Symbols<'r> {
+    resolution: &'r Resolution,
+    types: HashMap<SymbolId, Type>,
+}
+
+impl Symbols<'_> {
+    fn declare<T>(&mut self, name: &Span<T>, t: Type) {
+        if let Some(id) = self.resolution.get(name) {
+            self.types.insert(id, t);
+        }
+    }
+
+    fn get<T>(&self, name: &Span<T>) -> Option<&Type> {
+        self.resolution
+            .get(name)
+            .and_then(|id| self.types.get(&id))
+    }
+
+    fn is_report<T>(&self, name: &Span<T>) -> bool {
+        self.resolution
+            .symbol_at(name)
+            .is_some_and(|s| s.kind == SymbolKind::Builtin && s.name == "report")
+    }
+}
+
 pub fn typecheck(
     a: &[Span<Ast>],
     module_graph: &Module,
+    resolution: &Resolution,
 ) -> Result<(), crate::error::TypeCheckError> {
-    let mut scopes: GlobalScope<
-        nom_locate::LocatedSpan<&str, crate::ast::parse::spans::ExtraData<Type>>,
-        Cow<str>,
-    > = GlobalScope::new();
-    for a in a {
-        match &a.extra.data {
-            Ast::Mod(_) => (),
-            // FIXME Imports realtive to root
-            Ast::Import(path) => module_graph
-                .get_module(&path.extra.data[..(path.extra.data.len() - 1)])
-                .map_or_else(
-                    || -> Result<(), TypeCheckError> {
-                        Err(ModuleNotFoundError::from(path.clone().map(|_| ())).into())
-                    },
-                    |module| {
-                        if let Some((name, value)) = module
-                            .get_exports()
-                            .find(|(name, _)| name == path.extra.data.last().unwrap())
-                        {
-                            scopes.add(name.into(), path.clone().map(|_| value.clone()));
-                            Ok(())
-                        } else {
-                            Err(ItemNotFound::from(path.clone().map(|_| *path.fragment())).into())
-                        }
-                    },
-                )?,
-            Ast::Func(name, ret, args, _) => scopes.add(
-                name.extra.data.clone().into(),
-                a.clone().map(|_| {
+    infer_types(a, module_graph, resolution).map(|_| ())
+}
+
+/// Typechecks `a` like [`typecheck`], returning the type of every expression
+pub fn infer_types(
+    a: &[Span<Ast>],
+    module_graph: &Module,
+    resolution: &Resolution,
+) -> Result<ExprTypes, crate::error::TypeCheckError> {
+    let mut types = ExprTypes::default();
+    let mut symbols = Symbols {
+        resolution,
+        types: HashMap::new(),
+    };
+    let builtins: HashMap<_, _> = builtins().collect();
+    for (id, symbol) in resolution.symbols() {
+        if let Some(t) = builtins.get(symbol.name.as_str()) {
+            if symbol.kind == SymbolKind::Builtin {
+                symbols.types.insert(id, t.clone());
+            }
+        }
+    }
+    // every task that can be imported
+    for module in module_graph.iter() {
+        for item in &module.items {
+            if let Ast::Func(name, ret, args, _) = &item.extra.data {
+                symbols.declare(
+                    name,
                     Type::Function(
                         args.iter()
                             .map(|s| Type::from(s.extra.data.1.extra.data))
                             .collect(),
                         Box::new(Type::from(ret.extra.data)),
-                    )
-                }),
-            ),
+                    ),
+                );
+            }
         }
     }
 
@@ -84,65 +148,77 @@ pub fn typecheck(
         match &a.extra.data {
             Ast::Mod(_) => (),
             Ast::Import(_) => (),
-            Ast::Func(name, ret, args, body) => {
+            Ast::Func(_, ret, args, body) => {
                 let ret = ret.map(Into::into);
-                let f_name = name;
-                let mut scope = scopes.push();
                 for arg in args {
-                    scope.add(
-                        arg.extra.data.0.extra.data.as_str().into(),
-                        arg.extra.data.1.map(Into::into),
-                    );
+                    symbols.declare(&arg.extra.data.0, arg.extra.data.1.extra.data.into());
                 }
 
-                typecheck_body(scope, &f_name.extra.data, &ret, &body.extra.data)?;
+                typecheck_body(&mut symbols, &mut types, &ret, &body.extra.data, false)?;
             }
         }
     }
-    Ok(())
+    Ok(types)
 }
 
-fn typecheck_body<'a, S>(
-    mut scope: S,
-    f_name: &str,
+fn typecheck_body<'a>(
+    symbols: &mut Symbols,
+    types: &mut ExprTypes,
     ret: &Span<'a, Type>,
     body: &'a [Span<'a, Statement>],
-) -> Result<(), crate::error::TypeCheckError>
-where
-    S: Scope<Span<'a, Type>, Cow<'a, str>>,
-{
+    in_loop: bool,
+) -> Result<(), crate::error::TypeCheckError> {
     for line in body {
         match &line.extra.data {
             Statement::If(cond, body, else_body) => {
                 // typecheck condition
-                let e_type = typecheck_expr(&mut scope, f_name, cond)?;
+                let e_type = typecheck_expr(symbols, types, cond)?;
                 if e_type != Type::Bool {
                     return Err(TypeCheckError::ExpressionTypeError(
                         ExpressionTypeError::from(cond.clone().map(|_| (e_type, Type::Bool))),
                     ));
                     // panic!("Error on if condition in function {f_name}: Expected Bool but found {e_type:?}")
                 }
-                typecheck_body(scope.push(), f_name, ret, &body.extra.data)?;
+                typecheck_body(symbols, types, ret, &body.extra.data, in_loop)?;
                 if let Some(else_body) = else_body.as_ref() {
-                    typecheck_body(scope.push(), f_name, ret, &else_body.extra.data)?;
+                    typecheck_body(symbols, types, ret, &else_body.extra.data, in_loop)?;
                 }
             }
             Statement::While(cond, body) => {
                 // typecheck condition
-                let e_type = typecheck_expr(&mut scope, f_name, cond)?;
+                let e_type = typecheck_expr(symbols, types, cond)?;
                 if e_type != Type::Bool {
                     return Err(TypeCheckError::ExpressionTypeError(
                         ExpressionTypeError::from(cond.clone().map(|_| (e_type, Type::Bool))),
                     ));
                     // panic!("Error on if condition in function {f_name}: Expected Bool but found {e_type:?}")
                 }
-                typecheck_body(scope.push(), f_name, ret, &body.extra.data)?;
+                typecheck_body(symbols, types, ret, &body.extra.data, true)?;
+            }
+            Statement::For(name, start, end, body) => {
+                for bound in [start, end] {
+                    let e_type = typecheck_expr(symbols, types, bound)?;
+                    if e_type != Type::Number {
+                        let error = bound.clone().map(|_| (e_type, Type::Number));
+                        Err(ExpressionTypeError::from(error))?;
+                    }
+                }
+                symbols.declare(name, Type::Number);
+                typecheck_body(symbols, types, ret, &body.extra.data, true)?;
+            }
+            Statement::Break | Statement::Continue if !in_loop => {
+                let keyword = match line.extra.data {
+                    Statement::Break => "break",
+                    _ => "continue",
+                };
+                Err(LoopControlOutsideLoop::from(line.clone().map(|_| keyword.to_string())))?;
             }
+            Statement::Break | Statement::Continue => (),
             Statement::Return(x) => {
                 // Check expression type is the same as ret
                 let e_type = x
                     .as_ref()
-                    .map(|x| typecheck_expr(&mut scope, f_name, x))
+                    .map(|x| typecheck_expr(symbols, types, x))
                     .unwrap_or(Ok(Type::Void))?;
                 if e_type != ret.extra.data {
                     Err(ExpressionTypeError::from(
@@ -153,27 +229,25 @@ where
                     ))?;
                     // panic!("Error on return type for function {f_name}: expected {ret:?} but found {e_type:?}")
                 }
-                break;
+                // what follows never runs, but is still checked, see crate::flow
             }
             Statement::Expr(e) => {
                 // Typecheck expression
-                typecheck_expr(&mut scope, f_name, e)?;
+                typecheck_expr(symbols, types, e)?;
             }
             Statement::Declare(name, t) => {
-                scope.add(name.extra.data.as_str().into(), t.map(Into::into));
+                symbols.declare(name, t.extra.data.into());
             }
             Statement::Define(name, e) => {
-                let t = scope.get(&name.extra.data.as_str().into()).cloned();
+                let t = symbols.get(name).cloned();
                 t.map_or_else(
-                    || {
-                        panic!("{name} is not defined");
-                    },
+                    || Err(VariableNotFound::from(name.clone()).into()),
                     |t| -> Result<(), TypeCheckError> {
-                        let e_type = typecheck_expr(&mut scope, f_name, e)?;
-                        if t.extra.data != e_type {
+                        let e_type = typecheck_expr(symbols, types, e)?;
+                        if t != e_type {
                             return Err(TypeCheckError::ExpressionTypeError(
                                 ExpressionTypeError::from(
-                                    e.clone().map(|_| (e_type, t.extra.data)),
+                                    e.clone().map(|_| (e_type, t)),
                                 ),
                             ));
                             // panic!("Error on variable assigment in function {f_name}: Expected {t:?}, but found {e_type:?}");
@@ -182,65 +256,105 @@ where
                     },
                 )?
             }
+            Statement::Kill(name) => {
+                if symbols.get(name).is_none() {
+                    Err(VariableNotFound::from(name.clone()))?;
+                }
+            }
+            Statement::Try(body, name, typ, catch) => {
+                typecheck_body(symbols, types, ret, &body.extra.data, in_loop)?;
+                // the reason of every sabotage is a string
+                let t = Type::from(typ.extra.data);
+                if t != Type::String {
+                    Err(ExpressionTypeError::from(typ.map(|_| (t, Type::String))))?;
+                }
+                symbols.declare(name, Type::String);
+                typecheck_body(symbols, types, ret, &catch.extra.data, in_loop)?;
+            }
+            Statement::Sabotage(reason) => {
+                let e_type = typecheck_expr(symbols, types, reason)?;
+                if e_type != Type::String {
+                    Err(ExpressionTypeError::from(reason.clone().map(|_| (e_type, Type::String))))?;
+                }
+            }
+            Statement::Win | Statement::Lose => (),
+            Statement::Afk(ms) => {
+                let e_type = typecheck_expr(symbols, types, ms)?;
+                if e_type != Type::Number {
+                    Err(ExpressionTypeError::from(ms.clone().map(|_| (e_type, Type::Number))))?;
+                }
+            }
         }
     }
     Ok(())
 }
 
-fn typecheck_expr<'a, S>(
-    scope: &mut S,
-    f_name: &str,
+fn typecheck_expr<'a>(
+    symbols: &mut Symbols,
+    types: &mut ExprTypes,
+    e: &'a Span<'a, Expression>,
+) -> Result<Type, crate::error::TypeCheckError> {
+    let t = typecheck_expr_kind(symbols, types, e)?;
+    types.insert(e, t.clone());
+    Ok(t)
+}
+
+fn typecheck_expr_kind<'a>(
+    symbols: &mut Symbols,
+    types: &mut ExprTypes,
     e: &'a Span<'a, Expression>,
-) -> Result<Type, crate::error::TypeCheckError>
-where
-    S: Scope<Span<'a, Type>, Cow<'a, str>> + ?Sized,
-{
+) -> Result<Type, crate::error::TypeCheckError> {
     Ok(match &e.extra.data {
-        Expression::Call(name, args) if name.extra.data == "report" => {
+        Expression::Call(name, args) if symbols.is_report(name) => {
             // COMPILER MAGIIIC
-            if args.is_empty() {
-                panic!("On function {f_name}: Nothing to report");
-            }
+            let invalid = |span: &Span<'a, Expression>, reason: String| -> TypeCheckError {
+                ReportFormatError::from(span.clone().map(|_| reason)).into()
+            };
+            let Some(format) = args.first() else {
+                return Err(invalid(e, "nothing to report".into()));
+            };
+            let Expression::StringLit(s) = &format.extra.data else {
+                return Err(invalid(format, "the format has to be a string literal".into()));
+            };
             let mut expected_args = Vec::new();
-            for c in match args.first().map(|x| &x.extra.data) {
-                Some(Expression::StringLit(s)) => s,
-                Some(e) => {
-                    panic!("On function {f_name}: Expected string literal on report, found {e:?}")
-                }
-                None => unreachable!(),
-            }
-            .chars()
-            .collect::<Vec<_>>()
-            .windows(2)
-            {
-                if c[0] == '%' {
-                    match c[1] {
-                        'd' => expected_args.push(vec![Type::Number, Type::Bool]),
-                        's' => expected_args.push(vec![Type::String]),
-                        '%' => (), // Ignore
-                        x => panic!("On function {f_name}: Unexpected char {x:?} after %"),
+            let mut chars = s.chars();
+            while let Some(c) = chars.next() {
+                if c == '%' {
+                    match chars.next() {
+                        Some('d') => expected_args.push(vec![Type::Number, Type::Bool]),
+                        Some('s') => expected_args.push(vec![Type::String]),
+                        Some('%') => (), // Ignore
+                        Some(x) => return Err(invalid(format, format!("unexpected {x:?} after %"))),
+                        None => return Err(invalid(format, "the format ends with %".into())),
                     }
                 }
             }
 
-            let mut will_crash = false;
             if expected_args.len() != args.len() - 1 {
-                panic!("On function {f_name}: Unexpected number of format arguments on report, expected {}, but found {}", expected_args.len(), args.len()-1);
-            }
-            for (found, expected) in args
-                .iter()
-                .skip(1)
-                .map(|x| typecheck_expr(scope, f_name, x))
-                .zip(expected_args)
-            {
-                let found = found?;
+                return Err(invalid(
+                    e,
+                    format!(
+                        "the format takes {} arguments, but found {}",
+                        expected_args.len(),
+                        args.len() - 1
+                    ),
+                ));
+            }
+            let mut errors = Vec::new();
+            for (arg, expected) in args.iter().skip(1).zip(expected_args) {
+                let found = typecheck_expr(symbols, types, arg)?;
                 if !expected.contains(&found) {
-                    will_crash = true;
-                    eprintln!("On function {f_name}: In arguments for call to report expected {expected:?}, but found {found:?}")
+                    errors.push(ExpressionTypeError::from(
+                        arg.clone().map(|_| (found, expected[0].clone())),
+                    ));
                 }
             }
-            if will_crash {
-                panic!("On function {f_name}: Error on report arguments")
+            if !errors.is_empty() {
+                return Err(FunctionArgumentTypeError {
+                    related: errors,
+                    function_name: name.extra.data.clone(),
+                }
+                .into());
             }
 
             Type::Void
@@ -253,7 +367,7 @@ where
                     args.len()
                 );
             }
-            let t = typecheck_expr(scope, f_name, args.first().unwrap())?;
+            let t = typecheck_expr(symbols, types, args.first().unwrap())?;
             if t != Type::String {
                 panic!("On function {f_name}: Expected String, found {t:?}");
             }
@@ -267,12 +381,12 @@ where
                     args.len()
                 );
             }
-            let t = typecheck_expr(scope, f_name, args.first().unwrap())?;
+            let t = typecheck_expr(symbols, types, args.first().unwrap())?;
             if t != Type::String {
                 // for now only strings
                 panic!("On function {f_name}: Expected String, found {t:?}");
             }
-            let t = typecheck_expr(scope, f_name, args.last().unwrap())?;
+            let t = typecheck_expr(symbols, types, args.last().unwrap())?;
             if t != Type::Number {
                 panic!("On function {f_name}: Expected Number, found {t:?}");
             }
@@ -286,16 +400,16 @@ where
                     args.len()
                 );
             }
-            let t = typecheck_expr(scope, f_name, args.first().unwrap())?;
+            let t = typecheck_expr(symbols, types, args.first().unwrap())?;
             if t != Type::String {
                 // for now only strings
                 panic!("On function {f_name}: Expected String, found {t:?}");
             }
-            let t = typecheck_expr(scope, f_name, args.get(1).unwrap())?;
+            let t = typecheck_expr(symbols, types, args.get(1).unwrap())?;
             if t != Type::Number {
                 panic!("On function {f_name}: Expected Number, found {t:?}");
             }
-            let t = typecheck_expr(scope, f_name, args.last().unwrap())?;
+            let t = typecheck_expr(symbols, types, args.last().unwrap())?;
             if t != Type::String {
                 panic!("On function {f_name}: Expected String, found {t:?}");
             }
@@ -309,15 +423,15 @@ where
                     args.len()
                 );
             }
-            let t = typecheck_expr(scope, f_name, args.first().unwrap())?;
+            let t = typecheck_expr(symbols, types, args.first().unwrap())?;
             if t != Type::String {
                 panic!("On function {f_name}: Expected String, found {t:?}");
             }
-            let t = typecheck_expr(scope, f_name, args.get(1).unwrap())?;
+            let t = typecheck_expr(symbols, types, args.get(1).unwrap())?;
             if t != Type::String {
                 panic!("On function {f_name}: Expected String, found {t:?}");
             }
-            let t = typecheck_expr(scope, f_name, args.last().unwrap())?;
+            let t = typecheck_expr(symbols, types, args.last().unwrap())?;
             if t != Type::String {
                 panic!("On function {f_name}: Expected String, found {t:?}");
             }
@@ -331,11 +445,11 @@ where
                     args.len()
                 );
             }
-            let t = typecheck_expr(scope, f_name, args.first().unwrap())?;
+            let t = typecheck_expr(symbols, types, args.first().unwrap())?;
             if t != Type::String {
                 panic!("On function {f_name}: Expected String, found {t:?}");
             }
-            let t = typecheck_expr(scope, f_name, args.last().unwrap())?;
+            let t = typecheck_expr(symbols, types, args.last().unwrap())?;
             if t != Type::String {
                 panic!("On function {f_name}: Expected String, found {t:?}");
             }
@@ -349,7 +463,7 @@ where
                     args.len()
                 );
             }
-            let t = typecheck_expr(scope, f_name, args.first().unwrap())?;
+            let t = typecheck_expr(symbols, types, args.first().unwrap())?;
             if t != Type::String {
                 panic!("On function {f_name}: Expected String, found {t:?}");
             }
@@ -357,11 +471,7 @@ where
         }*/
         //end of string library
         Expression::Call(name, args) => {
-            match scope
-                .get(&(name.extra.data.as_str()).into())
-                .cloned()
-                .map(|x| x.extra.data)
-            {
+            match symbols.get(name).cloned() {
                 Some(Type::Function(args_t, ret)) => {
                     if args.len() != args_t.len() {
                         return Err(FunctionArgumentNumber::from(e.clone().map(|_| (name.extra.data.clone(), args_t.len(), args.len()))).into());
@@ -371,7 +481,7 @@ where
                     let mut errors = Vec::new();
                     for (expected, (ex, found)) in args_t
                         .into_iter()
-                        .zip(args.iter().map(|x| (x, typecheck_expr(scope, f_name, x))))
+                        .zip(args.iter().map(|x| (x, typecheck_expr(symbols, types, x))))
                     {
                         let found = found?;
                         if expected != found {
@@ -391,7 +501,7 @@ where
                     // }
                     *ret
                 }
-                Some(_) => panic!("{name} is not callable"),
+                Some(_) => return Err(NotCallable::from(name.clone()).into()),
                 //None => Type::Void,
                 None => {
                     return Err(TypeCheckError::FunctionNotFound(FunctionNotFound::from(
@@ -409,37 +519,50 @@ where
             b,
         ) => match op {
             Operator::Add | Operator::Sub | Operator::Mod => {
-                let a_type = typecheck_expr(scope, f_name, a.as_ref())?;
-                let b_type = typecheck_expr(scope, f_name, b.as_ref())?;
+                let a_type = typecheck_expr(symbols, types, a.as_ref())?;
+                let b_type = typecheck_expr(symbols, types, b.as_ref())?;
                 if a_type != Type::Number {
-                    panic!("On function {f_name}: LHS of {op} is not a number, it is a {a_type:?}");
+                    return Err(ExpressionTypeError::from(
+                        a.as_ref().clone().map(|_| (a_type, Type::Number)),
+                    )
+                    .into());
                 }
 
                 if b_type != Type::Number {
-                    panic!("On function {f_name}: RHS of {op} is not a number, it is a {b_type:?}");
+                    return Err(ExpressionTypeError::from(
+                        b.as_ref().clone().map(|_| (b_type, Type::Number)),
+                    )
+                    .into());
                 }
                 Type::Number
             }
             Operator::GEt | Operator::Lt => {
-                let a_type = typecheck_expr(scope, f_name, a.as_ref())?;
-                let b_type = typecheck_expr(scope, f_name, b.as_ref())?;
+                let a_type = typecheck_expr(symbols, types, a.as_ref())?;
+                let b_type = typecheck_expr(symbols, types, b.as_ref())?;
 
                 if a_type != Type::Number {
-                    panic!("On function {f_name}: LHS of {op} is not a number, it is a {a_type:?}");
+                    return Err(ExpressionTypeError::from(
+                        a.as_ref().clone().map(|_| (a_type, Type::Number)),
+                    )
+                    .into());
                 }
 
                 if b_type != Type::Number {
-                    panic!("On function {f_name}: RHS of {op} is not a number, it is a {b_type:?}");
+                    return Err(ExpressionTypeError::from(
+                        b.as_ref().clone().map(|_| (b_type, Type::Number)),
+                    )
+                    .into());
                 }
                 Type::Bool
             }
             Operator::Eq => {
-                let a_type = typecheck_expr(scope, f_name, a.as_ref())?;
-                let b_type = typecheck_expr(scope, f_name, b.as_ref())?;
+                let a_type = typecheck_expr(symbols, types, a.as_ref())?;
+                let b_type = typecheck_expr(symbols, types, b.as_ref())?;
 
                 if b_type != a_type {
-                    panic!(
-                        "On function {f_name}: RHS of == is not a {a_type:?}, it is a {b_type:?}"
+                    return Err(
+                        ExpressionTypeError::from(b.as_ref().clone().map(|_| (b_type, a_type)))
+                            .into(),
                     );
                 }
                 Type::Bool
@@ -447,14 +570,73 @@ where
         },
         Expression::StringLit(_) => Type::String,
         Expression::NumLit(_) => Type::Number,
-        Expression::Variable(name) => {
-            scope
-                .get(&name.into())
-                .cloned()
-                .unwrap_or_else(|| panic!("On function {f_name}: Variable {name} not declared"))
-                .extra
-                .data
-        }
+        Expression::Variable(name) => match symbols.get(e) {
+            Some(t) => t.clone(),
+            None => return Err(VariableNotFound::from(e.clone().map(|_| name.clone())).into()),
+        },
         Expression::BoolLit(_) => Type::Bool,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{error::TypeCheckError, session::tests::errors_in_main, Diagnostic};
+
+    #[test]
+    fn impostors_are_given_the_reason() {
+        assert!(errors_in_main("චemergency meeting\nචචsabotage \"a\"ඞ\nචimpostor red: string\nචචcomplete report with \"%s\" and redඞ\n").is_empty());
+        for main in [
+            "චsabotage 1ඞ\n",
+            "චemergency meeting\nචචsabotage \"a\"ඞ\nචimpostor red: number\nචචcomplete report with \"%d\" and redඞ\n",
+        ] {
+            assert!(matches!(
+                errors_in_main(main)[..],
+                [Diagnostic::TypeCheck(TypeCheckError::ExpressionTypeError(_))]
+            ));
+        }
+    }
+
+    #[test]
+    fn loops_count_numbers_and_hold_their_jumps() {
+        assert!(errors_in_main("චfor red in 0 to 3\nචචemergency meeting\nචචචbreakඞ\nචචimpostor pink: string\nචචචcontinueඞ\n").is_empty());
+        for main in ["චbreakඞ\n", "චsus? == 1 1\nචචcontinueඞ\n"] {
+            assert!(matches!(
+                errors_in_main(main)[..],
+                [Diagnostic::TypeCheck(TypeCheckError::LoopControlOutsideLoop(_))]
+            ));
+        }
+        for main in [
+            "චfor red in 0 to \"a\"\nචචbreakඞ\n",
+            "චfor red in 0 to 3\nචචmake red \"a\"ඞ\n",
+        ] {
+            assert!(matches!(
+                errors_in_main(main)[..],
+                [Diagnostic::TypeCheck(TypeCheckError::ExpressionTypeError(_))]
+            ));
+        }
+    }
+
+    #[test]
+    fn afk_waits_milliseconds() {
+        assert!(matches!(
+            errors_in_main("චafk 10ඞ\nචafk \"a\"ඞ\n")[..],
+            [Diagnostic::TypeCheck(TypeCheckError::ExpressionTypeError(_))]
+        ));
+    }
+
+    #[test]
+    fn numbers_and_strings_convert() {
+        assert!(errors_in_main("චcrewmate red: stringඞ\nචmake red complete tostring with 1ඞ\nචmake red complete concat with red and redඞ\nචcomplete report with \"%d\" and complete tonumber with redඞ\n").is_empty());
+        for main in [
+            "චcomplete tostring with \"1\"ඞ\n",
+            "චcomplete tonumber with 1ඞ\n",
+            "චcomplete concat with \"a\" and 1ඞ\n",
+        ] {
+            let errors = errors_in_main(main);
+            assert!(
+                matches!(errors[..], [Diagnostic::TypeCheck(TypeCheckError::FunctionArgumentTypeError(_))]),
+                "{main}: {errors:?}"
+            );
+        }
+    }
+}
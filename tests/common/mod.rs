@@ -0,0 +1,27 @@
+#![allow(dead_code)]
+
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use suslang::{codegen::Codegen, linker::Linked, Session};
+
+/// Unique path in the temporary directory for a file generated by a test
+pub fn out_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("suslang-test-{}-{name}", std::process::id()))
+}
+
+/// Compiles `examples/{example}` and writes it to `output` with `codegen`
+pub fn build<C>(example: &str, output: &Path, codegen: &mut C)
+where
+    C: for<'a> Codegen<BufWriter<File>, Linked<'a>> + ?Sized,
+{
+    let mut out = BufWriter::new(File::create(output).unwrap());
+    let mut session = Session::new();
+    session.main_file(Path::new("examples").join(example));
+    if let Err(errors) = session.emit(codegen, &mut out) {
+        panic!("{example} doesn't compile: {errors:?}");
+    }
+}
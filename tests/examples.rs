@@ -0,0 +1,194 @@
+//! Runs every example on every enabled backend and compares the results with
+//! `tests/examples/{name}.stdout` and `tests/examples/{name}.status`, and with each other.
+//! The programs run inside `tests/examples`, so that's where their input files live.
+
+mod common;
+
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use common::{build, out_file};
+use suslang::{
+    codegen,
+    toolchain::{CCompiler, Interpreter, ToolchainError},
+    Session,
+};
+
+/// Examples that aren't run, along with the reason
+const SKIPPED: &[(&str, &str)] = &[
+    ("bools.sus", "conditions made of a single crewmate don't parse"),
+    ("lib.sus", "library without ඬ"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    #[cfg(feature = "backend-c")]
+    C,
+    #[cfg(feature = "backend-js")]
+    Js,
+    #[cfg(feature = "backend-python")]
+    Py,
+    #[cfg(feature = "backend-scm")]
+    Scm,
+}
+
+const BACKENDS: &[Backend] = &[
+    #[cfg(feature = "backend-c")]
+    Backend::C,
+    #[cfg(feature = "backend-js")]
+    Backend::Js,
+    #[cfg(feature = "backend-python")]
+    Backend::Py,
+    #[cfg(feature = "backend-scm")]
+    Backend::Scm,
+];
+
+impl Backend {
+    fn interpreter(self) -> Option<Interpreter> {
+        match self {
+            #[cfg(feature = "backend-c")]
+            Self::C => None,
+            #[cfg(feature = "backend-js")]
+            Self::Js => Some(Interpreter::Node),
+            #[cfg(feature = "backend-python")]
+            Self::Py => Some(Interpreter::Python),
+            #[cfg(feature = "backend-scm")]
+            Self::Scm => Some(Interpreter::Guile),
+        }
+    }
+
+    /// Generates the code for `example` into `file`
+    fn build(self, example: &str, file: &Path) {
+        match self {
+            #[cfg(feature = "backend-c")]
+            Self::C => build(example, file, &mut codegen::C::new()),
+            #[cfg(feature = "backend-js")]
+            Self::Js => build(example, file, &mut codegen::Js::default()),
+            #[cfg(feature = "backend-python")]
+            Self::Py => build(example, file, &mut codegen::Py::new()),
+            #[cfg(feature = "backend-scm")]
+            Self::Scm => build(example, file, &mut codegen::Scm),
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            #[cfg(feature = "backend-c")]
+            Self::C => "c",
+            #[cfg(feature = "backend-js")]
+            Self::Js => "js",
+            #[cfg(feature = "backend-python")]
+            Self::Py => "py",
+            #[cfg(feature = "backend-scm")]
+            Self::Scm => "scm",
+        }
+    }
+
+    /// Builds and runs `example`, `None` if the tools of the backend aren't installed
+    fn run(self, example: &str) -> Option<Output> {
+        let file = out_file(&format!("{example}.{}", self.extension()));
+        self.build(example, &file);
+        let mut command = match self.interpreter() {
+            Some(interpreter) if !interpreter.is_available() => return None,
+            Some(interpreter) => interpreter.command(&file),
+            None => {
+                let exe = file.with_extension("out");
+                match CCompiler::new().compile(&file, &exe) {
+                    Ok(()) => (),
+                    Err(ToolchainError::Spawn { .. }) => return None,
+                    Err(e) => panic!("{example} failed to compile with {self:?}: {e:?}"),
+                }
+                Command::new(exe)
+            }
+        };
+        let output = command.current_dir(expected_dir()).output().unwrap();
+        std::fs::remove_file(&file).ok();
+        std::fs::remove_file(file.with_extension("out")).ok();
+        Some(output)
+    }
+}
+
+fn expected_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/examples")
+}
+
+fn examples() -> Vec<String> {
+    let mut examples = std::fs::read_dir("examples")
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with(".sus"))
+        .filter(|name| SKIPPED.iter().all(|(skipped, _)| skipped != name))
+        .collect::<Vec<_>>();
+    examples.sort();
+    examples
+}
+
+#[test]
+fn examples_have_expectations() {
+    for example in examples() {
+        let name = example.trim_end_matches(".sus");
+        for extension in ["stdout", "status"] {
+            assert!(
+                expected_dir().join(format!("{name}.{extension}")).exists(),
+                "missing tests/examples/{name}.{extension}, run the example with the C backend to create it"
+            );
+        }
+    }
+}
+
+#[test]
+fn examples_on_every_backend() {
+    let mut failures = Vec::new();
+    for example in examples() {
+        let name = example.trim_end_matches(".sus");
+        let Ok(stdout) = std::fs::read_to_string(expected_dir().join(format!("{name}.stdout")))
+        else {
+            continue;
+        };
+        let status = std::fs::read_to_string(expected_dir().join(format!("{name}.status")))
+            .map(|status| status.trim().parse::<i32>().unwrap())
+            .unwrap_or(0);
+
+        let mut results: Vec<(Backend, Output)> = Vec::new();
+        for &backend in BACKENDS {
+            let Some(output) = backend.run(&example) else {
+                eprintln!("skipping {example} on {backend:?}, its tools aren't available");
+                continue;
+            };
+            let found = String::from_utf8_lossy(&output.stdout);
+            if found != stdout {
+                failures.push(format!(
+                    "{example} on {backend:?}: expected stdout {stdout:?}, found {found:?}\nstderr: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                ));
+            }
+            if output.status.code() != Some(status) {
+                failures.push(format!(
+                    "{example} on {backend:?}: expected exit code {status}, found {:?}",
+                    output.status.code()
+                ));
+            }
+            if let Some((other, _)) = results
+                .iter()
+                .find(|(_, other)| (&other.stdout, other.status) != (&output.stdout, output.status))
+            {
+                failures.push(format!("{example}: {other:?} and {backend:?} disagree"));
+            }
+            results.push((backend, output));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
+
+#[test]
+fn examples_lower_to_valid_ir() {
+    for example in examples() {
+        let mut session = Session::new();
+        session.main_file(Path::new("examples").join(&example));
+        if let Err(errors) = session.ir() {
+            panic!("{example} doesn't lower to valid IR: {errors:?}");
+        }
+    }
+}
@@ -6,20 +6,36 @@ use suslang::{
 #[test]
 fn parse_helloworld() {
     let s = include_str!("../examples/helloworld.sus");
-    let ast = parse_str(s);
-    assert_eq!(
-        ast,
-        vec![Ast::Func(
-            "ඬ".into(),
-            Typ::Num,
-            vec![],
-            vec![
-                Statement::Expr(Expression::Call(
-                    "report".into(),
-                    vec![Expression::StringLit("hello world".into())]
-                )),
-                Statement::Return(Some(Expression::NumLit(0)))
-            ]
-        )]
-    );
+    let ast = parse_str(s).unwrap();
+    let [item] = &ast[..] else {
+        panic!("expected a single task, found {ast:?}")
+    };
+    let Ast::Func(name, ret, args, body) = &item.extra.data else {
+        panic!("expected a task, found {item:?}")
+    };
+    assert_eq!(name.extra.data, "ඬ");
+    assert_eq!(ret.extra.data, Typ::Num);
+    assert!(args.is_empty());
+    let [report, eject] = &body.extra.data[..] else {
+        panic!("expected two statements, found {body:?}")
+    };
+    assert!(matches!(
+        &report.extra.data,
+        Statement::Expr(call) if matches!(
+            &call.extra.data,
+            Expression::Call(name, args)
+                if name.extra.data == "report"
+                    && args.len() == 1
+                    && args[0].extra.data == Expression::StringLit("hello world".into())
+        )
+    ));
+    assert!(matches!(
+        &eject.extra.data,
+        Statement::Return(Some(value)) if value.extra.data == Expression::NumLit(0)
+    ));
+}
+
+#[test]
+fn parse_error() {
+    assert!(parse_str("task ඬ ➤ number\n").is_err());
 }
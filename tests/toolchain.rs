@@ -0,0 +1,78 @@
+mod common;
+
+use std::{fs::File, io::BufWriter};
+
+use common::{build, out_file};
+use suslang::{
+    codegen::{self, Codegen},
+    linker::Linked,
+    toolchain::{CCompiler, Interpreter},
+};
+
+fn run_interpreter<C>(interpreter: Interpreter, extension: &str, codegen: &mut C)
+where
+    C: for<'a> Codegen<BufWriter<File>, Linked<'a>>,
+{
+    if !interpreter.is_available() {
+        eprintln!("{interpreter:?} not available, skipping");
+        return;
+    }
+    let file = out_file(&format!("sum.{extension}"));
+    build("sum.sus", &file, codegen);
+    let output = interpreter.command(&file).output().unwrap();
+    std::fs::remove_file(&file).ok();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Result: 11"));
+}
+
+#[test]
+fn c_executable() {
+    let source = out_file("sum.c");
+    let exe = out_file("sum");
+    build("sum.sus", &source, &mut codegen::C::new());
+    match CCompiler::new().compile(&source, &exe) {
+        Ok(()) => {
+            let output = std::process::Command::new(&exe).output().unwrap();
+            assert!(output.status.success());
+            assert_eq!(
+                String::from_utf8_lossy(&output.stdout),
+                "hello world\nResult: 11\n"
+            );
+        }
+        Err(suslang::toolchain::ToolchainError::Spawn { program, .. }) => {
+            eprintln!("{program} not available, skipping")
+        }
+        Err(e) => panic!("{e:?}"),
+    }
+    std::fs::remove_file(&source).ok();
+    std::fs::remove_file(&exe).ok();
+}
+
+#[test]
+fn c_compiler_errors() {
+    let source = out_file("broken.c");
+    std::fs::write(&source, "int main() {\n\treturn x;\n}\n").unwrap();
+    match CCompiler::new().compile(&source, out_file("broken")) {
+        Err(suslang::toolchain::ToolchainError::Compiler(e)) => assert!(!e.messages.is_empty()),
+        Err(suslang::toolchain::ToolchainError::Spawn { program, .. }) => {
+            eprintln!("{program} not available, skipping")
+        }
+        Ok(()) => panic!("Broken code compiled"),
+    }
+    std::fs::remove_file(&source).ok();
+}
+
+#[test]
+fn python() {
+    run_interpreter(Interpreter::Python, "py", &mut codegen::Py::new());
+}
+
+#[test]
+fn node() {
+    run_interpreter(Interpreter::Node, "js", &mut codegen::Js::default());
+}
+
+#[test]
+fn guile() {
+    run_interpreter(Interpreter::Guile, "scm", &mut codegen::Scm);
+}